@@ -0,0 +1,252 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::drive_access::Permissions;
+
+/// Prefix of token secrets, making them easy to recognize e.g. by secret scanners.
+const TOKEN_PREFIX: &str = "mydrive_";
+/// Last use times are saved at most this often, so busy scripts do not rewrite the file
+/// on every request.
+const LAST_USED_PRECISION: u64 = 60;
+
+/// Operations allowed with an API token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum TokenScope {
+    /// Listing and downloading files.
+    Read,
+    /// Uploading files and creating folders, without seeing the drive contents.
+    Upload,
+    /// Everything the token owner can do.
+    Full,
+}
+
+impl TokenScope {
+    /// Upper bound of the token owner's permissions when using the token.
+    pub(crate) fn permissions(&self) -> Permissions {
+        match self {
+            TokenScope::Read => Permissions::read_only(),
+            TokenScope::Upload => Permissions {
+                write: true,
+                ..Default::default()
+            },
+            TokenScope::Full => Permissions::all(),
+        }
+    }
+}
+
+/// Personal API token, only a hash of its secret is stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ApiToken {
+    pub(crate) id: String,
+    pub(crate) owner: String,
+    #[serde(default)]
+    pub(crate) owner_is_admin: bool,
+    pub(crate) name: String,
+    pub(crate) scope: TokenScope,
+    pub(crate) created_at: u64,
+    pub(crate) expires_at: Option<u64>,
+    pub(crate) last_used_at: Option<u64>,
+    secret_hash: String,
+}
+
+impl ApiToken {
+    pub(crate) fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// API tokens persisted in the JSON file configured with `API_TOKENS_FILE`.
+#[derive(Debug)]
+pub(crate) struct TokenStore {
+    file: PathBuf,
+    tokens: Mutex<Vec<ApiToken>>,
+}
+
+impl TokenStore {
+    pub(crate) fn load(file: &Path) -> anyhow::Result<Self> {
+        let tokens = if file.exists() {
+            let contents =
+                std::fs::read_to_string(file).context(format!("Failed to read {:?}", file))?;
+            serde_json::from_str(&contents).context(format!("Failed to parse {:?}", file))?
+        } else {
+            Vec::new()
+        };
+        Ok(Self {
+            file: file.to_path_buf(),
+            tokens: Mutex::new(tokens),
+        })
+    }
+
+    /// Creates a token and returns it with its secret, which cannot be retrieved later.
+    pub(crate) fn create(
+        &self,
+        owner: &str,
+        owner_is_admin: bool,
+        name: &str,
+        scope: TokenScope,
+        expires_in: Option<Duration>,
+    ) -> anyhow::Result<(ApiToken, String)> {
+        let id = random_string::<9>();
+        let secret = format!("{TOKEN_PREFIX}{id}_{}", random_string::<32>());
+        let now = now();
+        let token = ApiToken {
+            id,
+            owner: owner.to_owned(),
+            owner_is_admin,
+            name: name.to_owned(),
+            scope,
+            created_at: now,
+            expires_at: expires_in.map(|expires_in| now + expires_in.as_secs()),
+            last_used_at: None,
+            secret_hash: hash_secret(&secret),
+        };
+        let mut tokens = self.tokens.lock().unwrap();
+        tokens.push(token.clone());
+        self.save(&tokens)?;
+        Ok((token, secret))
+    }
+
+    /// Finds the token with the given secret, recording its use.
+    pub(crate) fn authenticate(&self, secret: &str) -> Option<ApiToken> {
+        let id = secret
+            .strip_prefix(TOKEN_PREFIX)?
+            .split_once('_')
+            .map(|(id, _)| id)?;
+        let now = now();
+        let mut tokens = self.tokens.lock().unwrap();
+        let token = tokens.iter_mut().find(|token| token.id == id)?;
+        // secrets are long random strings, so a plain hash is enough to protect them
+        if !hashes_match(&token.secret_hash, &hash_secret(secret)) || token.is_expired(now) {
+            return None;
+        }
+        let save = token
+            .last_used_at
+            .is_none_or(|last_used_at| now >= last_used_at + LAST_USED_PRECISION);
+        token.last_used_at = Some(now);
+        let token = token.clone();
+        if save {
+            if let Err(e) = self.save(&tokens) {
+                tracing::warn!("Failed to save token use: {:?}", e);
+            }
+        }
+        Some(token)
+    }
+
+    /// Tokens of `owner`, newest first.
+    pub(crate) fn list(&self, owner: &str) -> Vec<ApiToken> {
+        let mut tokens = self
+            .tokens
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|token| token.owner == owner)
+            .cloned()
+            .collect::<Vec<_>>();
+        tokens.sort_by_key(|token| std::cmp::Reverse(token.created_at));
+        tokens
+    }
+
+    /// Removes the token of `owner`, returning `false` when there is no such token.
+    pub(crate) fn revoke(&self, id: &str, owner: &str) -> anyhow::Result<bool> {
+        let mut tokens = self.tokens.lock().unwrap();
+        let Some(index) = tokens
+            .iter()
+            .position(|token| token.id == id && token.owner == owner)
+        else {
+            return Ok(false);
+        };
+        tokens.remove(index);
+        self.save(&tokens)?;
+        Ok(true)
+    }
+
+    fn save(&self, tokens: &[ApiToken]) -> anyhow::Result<()> {
+        let contents = serde_json::to_string_pretty(tokens)?;
+        // replace the file atomically, so a crash cannot leave it truncated
+        let temp_file = self.file.with_extension("tmp");
+        std::fs::write(&temp_file, contents).context(format!("Failed to write {:?}", temp_file))?;
+        std::fs::rename(&temp_file, &self.file).context(format!("Failed to write {:?}", self.file))
+    }
+}
+
+fn random_string<const N: usize>() -> String {
+    let mut bytes = [0u8; N];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    // "_" separates the parts of a secret
+    URL_SAFE_NO_PAD.encode(bytes).replace('_', "-")
+}
+
+fn hash_secret(secret: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(secret.as_bytes()))
+}
+
+/// Compares hashes in constant time.
+fn hashes_match(expected: &str, actual: &str) -> bool {
+    expected.len() == actual.len()
+        && expected
+            .bytes()
+            .zip(actual.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{TokenScope, TokenStore};
+
+    #[test]
+    fn test_authenticate() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("tokens.json");
+        let store = TokenStore::load(&file).unwrap();
+        let (token, secret) = store
+            .create("alice", false, "backup", TokenScope::Read, None)
+            .unwrap();
+        assert!(!std::fs::read_to_string(&file).unwrap().contains(&secret));
+
+        let authenticated = store.authenticate(&secret).unwrap();
+        assert_eq!(authenticated.id, token.id);
+        assert_eq!(authenticated.owner, "alice");
+        assert!(authenticated.last_used_at.is_some());
+
+        let wrong_secret = format!("{}x", &secret[..secret.len() - 1]);
+        assert!(store.authenticate(&wrong_secret).is_none());
+        assert!(store.authenticate("garbage").is_none());
+
+        // tokens survive restarts
+        let store = TokenStore::load(&file).unwrap();
+        assert!(store.authenticate(&secret).is_some());
+        assert!(!store.revoke(&token.id, "bob").unwrap());
+        assert!(store.revoke(&token.id, "alice").unwrap());
+        assert!(store.authenticate(&secret).is_none());
+    }
+
+    #[test]
+    fn test_expired_tokens() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = TokenStore::load(&dir.path().join("tokens.json")).unwrap();
+        let (_, secret) = store
+            .create("alice", false, "ci", TokenScope::Full, Some(Duration::ZERO))
+            .unwrap();
+        assert!(store.authenticate(&secret).is_none());
+    }
+}
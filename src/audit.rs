@@ -0,0 +1,375 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{config::Config, drive_access::volumes::Volumes};
+
+/// Modification of the drive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum AuditAction {
+    Upload,
+    CreateDir,
+    Delete,
+    Move,
+    Copy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Outcome {
+    Success,
+    Failure,
+}
+
+/// Line of the audit log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct AuditRecord {
+    /// Unix time in seconds.
+    pub(crate) time: u64,
+    /// Signed-in user, or the name given by an upload link visitor.
+    pub(crate) actor: Option<String>,
+    /// How the actor got access when not with a session, e.g. an API token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) via: Option<String>,
+    pub(crate) client_ip: Option<String>,
+    pub(crate) action: AuditAction,
+    /// Drive path, e.g. "/photos/cat.jpg".
+    pub(crate) path: String,
+    /// Destination of moved and copied entries.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) target: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) bytes: Option<u64>,
+    pub(crate) outcome: Outcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+}
+
+/// Criteria of the records shown on the audit page; empty ones match everything.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub(crate) struct AuditFilter {
+    #[serde(default)]
+    pub(crate) actor: String,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    pub(crate) action: Option<AuditAction>,
+    /// Records of this drive path and everything below it.
+    #[serde(default)]
+    pub(crate) path: String,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    pub(crate) outcome: Option<Outcome>,
+}
+
+/// Reads empty values of filter forms as "any".
+fn empty_as_none<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: serde::de::DeserializeOwned,
+{
+    match String::deserialize(deserializer)?.as_str() {
+        "" => Ok(None),
+        value => T::deserialize(serde::de::value::StrDeserializer::new(value)).map(Some),
+    }
+}
+
+impl AuditFilter {
+    fn matches(&self, record: &AuditRecord) -> bool {
+        let path = self.path.trim_end_matches('/');
+        (self.actor.is_empty() || record.actor.as_deref() == Some(self.actor.as_str()))
+            && self.action.is_none_or(|action| action == record.action)
+            && self.outcome.is_none_or(|outcome| outcome == record.outcome)
+            && (path.is_empty()
+                || record.path == path
+                || record
+                    .path
+                    .strip_prefix(path)
+                    .is_some_and(|rest| rest.starts_with('/')))
+    }
+}
+
+/// Append-only JSON Lines file of drive modifications, configured with `AUDIT_LOG`.
+///
+/// The file is rotated when it grows over `AUDIT_LOG_MAX_SIZE` megabytes, keeping
+/// `AUDIT_LOG_MAX_FILES` older files named e.g. `audit.jsonl.1`.
+#[derive(Debug)]
+pub(crate) struct AuditLog {
+    file: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    writer: Mutex<Option<File>>,
+}
+
+impl AuditLog {
+    pub(crate) fn from_config(config: &Config) -> Option<Self> {
+        let file = config.audit_log.as_ref()?;
+        Some(Self::new(
+            file.clone(),
+            config.audit_log_max_size,
+            config.audit_log_max_files,
+        ))
+    }
+
+    fn new(file: PathBuf, max_size: u64, max_files: usize) -> Self {
+        Self {
+            file,
+            max_size,
+            max_files,
+            writer: Mutex::new(None),
+        }
+    }
+
+    pub(crate) fn append(&self, record: &AuditRecord) -> anyhow::Result<()> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        let mut writer = self.writer.lock().unwrap();
+        let size = match writer.as_ref() {
+            Some(file) => file.metadata()?.len(),
+            None => std::fs::metadata(&self.file).map(|m| m.len()).unwrap_or(0),
+        };
+        if size > 0 && size + line.len() as u64 > self.max_size {
+            *writer = None;
+            self.rotate()?;
+        }
+        if writer.is_none() {
+            *writer = Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.file)
+                    .context(format!("Failed to open {:?}", self.file))?,
+            );
+        }
+        writer
+            .as_mut()
+            .unwrap()
+            .write_all(line.as_bytes())
+            .context(format!("Failed to write {:?}", self.file))
+    }
+
+    fn rotate(&self) -> anyhow::Result<()> {
+        if self.max_files == 0 {
+            return std::fs::remove_file(&self.file)
+                .context(format!("Failed to remove {:?}", self.file));
+        }
+        for index in (1..self.max_files).rev() {
+            let older = self.rotated_file(index);
+            if older.exists() {
+                std::fs::rename(&older, self.rotated_file(index + 1))
+                    .context(format!("Failed to rotate {:?}", older))?;
+            }
+        }
+        std::fs::rename(&self.file, self.rotated_file(1))
+            .context(format!("Failed to rotate {:?}", self.file))
+    }
+
+    fn rotated_file(&self, index: usize) -> PathBuf {
+        let mut name = self.file.clone().into_os_string();
+        name.push(format!(".{index}"));
+        PathBuf::from(name)
+    }
+
+    /// Records matching `filter`, newest first, skipping `offset` of them.
+    pub(crate) fn query(
+        &self,
+        filter: &AuditFilter,
+        offset: usize,
+        limit: usize,
+    ) -> anyhow::Result<Vec<AuditRecord>> {
+        // the current file is not rotated while reading
+        let _writer = self.writer.lock().unwrap();
+        let files = std::iter::once(self.file.clone())
+            .chain((1..=self.max_files).map(|index| self.rotated_file(index)));
+        let mut records = Vec::new();
+        for file in files {
+            if !file.exists() {
+                continue;
+            }
+            let mut file_records = read_records(&file)?;
+            file_records.retain(|record| filter.matches(record));
+            records.extend(file_records.into_iter().rev());
+            if records.len() >= offset + limit {
+                break;
+            }
+        }
+        Ok(records.into_iter().skip(offset).take(limit).collect())
+    }
+}
+
+fn read_records(file: &Path) -> anyhow::Result<Vec<AuditRecord>> {
+    let reader = BufReader::new(File::open(file).context(format!("Failed to open {:?}", file))?);
+    let mut records = Vec::new();
+    for line in reader.lines() {
+        let line = line.context(format!("Failed to read {:?}", file))?;
+        match serde_json::from_str(&line) {
+            Ok(record) => records.push(record),
+            // e.g. a line cut short by a crash
+            Err(e) => warn!("Skipping invalid audit record in {:?}: {:?}", file, e),
+        }
+    }
+    Ok(records)
+}
+
+/// Records operations of a single request, see [`crate::server`] for its extraction.
+///
+/// Successful operations are counted in the [`crate::metrics`] and OpenTelemetry metrics as well.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Auditor {
+    pub(crate) log: Option<Arc<AuditLog>>,
+    pub(crate) volumes: Arc<Volumes>,
+    pub(crate) actor: Option<String>,
+    pub(crate) via: Option<String>,
+    pub(crate) client_ip: Option<String>,
+}
+
+impl Auditor {
+    /// Uses `actor` instead of the signed-in user, e.g. the name given by an upload link visitor.
+    pub(crate) fn acting_as(mut self, actor: &str, via: String) -> Self {
+        self.actor = Some(actor.to_owned());
+        self.via = Some(via);
+        self
+    }
+
+    /// Appends a record of `action` on the entry at the absolute `path`, if audit is enabled.
+    pub(crate) fn record<T, E: std::fmt::Display>(
+        &self,
+        action: AuditAction,
+        path: &Path,
+        bytes: Option<u64>,
+        result: &Result<T, E>,
+    ) {
+        self.append(action, path, None, bytes, result);
+    }
+
+    /// Appends a record of moving or copying the entry at `from` to `to`.
+    pub(crate) fn record_transfer<T, E: std::fmt::Display>(
+        &self,
+        action: AuditAction,
+        from: &Path,
+        to: &Path,
+        bytes: Option<u64>,
+        result: &Result<T, E>,
+    ) {
+        self.append(action, from, Some(to), bytes, result);
+    }
+
+    fn append<T, E: std::fmt::Display>(
+        &self,
+        action: AuditAction,
+        path: &Path,
+        target: Option<&Path>,
+        bytes: Option<u64>,
+        result: &Result<T, E>,
+    ) {
+        if result.is_ok() {
+            crate::metrics::metrics().record(action, bytes);
+            if let (AuditAction::Upload, Some(bytes)) = (action, bytes) {
+                crate::telemetry::instruments().record_upload(bytes);
+            }
+        }
+        let Some(log) = &self.log else {
+            return;
+        };
+        let drive_path = |path: &Path| self.volumes.drive_path(path);
+        let record = AuditRecord {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            actor: self.actor.clone(),
+            via: self.via.clone(),
+            client_ip: self.client_ip.clone(),
+            action,
+            path: drive_path(path),
+            target: target.map(drive_path),
+            bytes,
+            outcome: if result.is_ok() {
+                Outcome::Success
+            } else {
+                Outcome::Failure
+            },
+            error: result.as_ref().err().map(|e| e.to_string()),
+        };
+        if let Err(e) = log.append(&record) {
+            warn!("Failed to write audit record {:?}: {:?}", record, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AuditAction, AuditFilter, AuditLog, AuditRecord, Outcome};
+
+    fn record(actor: &str, action: AuditAction, path: &str) -> AuditRecord {
+        AuditRecord {
+            time: 0,
+            actor: Some(actor.to_owned()),
+            via: None,
+            client_ip: Some("127.0.0.1".to_owned()),
+            action,
+            path: path.to_owned(),
+            target: None,
+            bytes: None,
+            outcome: Outcome::Success,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("audit.jsonl");
+        let log = AuditLog::new(file.clone(), 300, 2);
+        for index in 0..10 {
+            log.append(&record(
+                "alice",
+                AuditAction::Upload,
+                &format!("/{index}.txt"),
+            ))
+            .unwrap();
+        }
+        assert!(std::fs::metadata(&file).unwrap().len() <= 300);
+        assert!(dir.path().join("audit.jsonl.2").exists());
+        assert!(!dir.path().join("audit.jsonl.3").exists());
+
+        let records = log.query(&AuditFilter::default(), 0, 100).unwrap();
+        assert_eq!(records[0].path, "/9.txt");
+        assert!(records.windows(2).all(|pair| pair[0].path > pair[1].path));
+        let page = log.query(&AuditFilter::default(), 1, 2).unwrap();
+        assert_eq!(
+            page.iter().map(|r| r.path.as_str()).collect::<Vec<_>>(),
+            ["/8.txt", "/7.txt"]
+        );
+    }
+
+    #[test]
+    fn test_filter() {
+        let filter = AuditFilter {
+            actor: "alice".to_owned(),
+            action: Some(AuditAction::Delete),
+            path: "/photos/".to_owned(),
+            outcome: None,
+        };
+        assert!(filter.matches(&record("alice", AuditAction::Delete, "/photos")));
+        assert!(filter.matches(&record("alice", AuditAction::Delete, "/photos/a.jpg")));
+        assert!(!filter.matches(&record("alice", AuditAction::Delete, "/photos2")));
+        assert!(!filter.matches(&record("bob", AuditAction::Delete, "/photos/a.jpg")));
+        assert!(!filter.matches(&record("alice", AuditAction::Upload, "/photos/a.jpg")));
+        assert!(AuditFilter::default().matches(&record("bob", AuditAction::Upload, "/a")));
+    }
+
+    #[test]
+    fn test_filter_from_query() {
+        let filter: AuditFilter =
+            serde_urlencoded::from_str("actor=&action=create_dir&path=&outcome=").unwrap();
+        assert_eq!(filter.action, Some(AuditAction::CreateDir));
+        assert_eq!(filter.outcome, None);
+    }
+}
@@ -0,0 +1,274 @@
+use std::{fs::File, io::Write, path::Path};
+
+use anyhow::{Context, Result};
+use indicatif::ProgressBar;
+use reqwest::{
+    blocking::{multipart, RequestBuilder, Response},
+    Method, StatusCode, Url,
+};
+use serde::{Deserialize, Serialize};
+
+/// Entries requested at once when reading all pages of a listing.
+const PAGE_SIZE: usize = 1000;
+
+/// File or folder of the drive.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Entry {
+    pub(crate) path: String,
+    pub(crate) name: String,
+    pub(crate) kind: EntryKind,
+    pub(crate) size: Option<u64>,
+    pub(crate) modified_at: Option<u64>,
+    pub(crate) media_type: Option<String>,
+    pub(crate) permissions: Permissions,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum EntryKind {
+    File,
+    Folder,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Permissions {
+    pub(crate) read: bool,
+    pub(crate) write: bool,
+    pub(crate) delete: bool,
+    pub(crate) share: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct UploadResult {
+    pub(crate) saved: Vec<Entry>,
+    pub(crate) failed: Vec<UploadFailure>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct UploadFailure {
+    pub(crate) name: String,
+    pub(crate) detail: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Page {
+    folder: Option<Entry>,
+    entries: Vec<Entry>,
+    next_cursor: Option<String>,
+}
+
+/// Error description of the API, see RFC 7807.
+#[derive(Debug, Deserialize)]
+struct Problem {
+    title: String,
+    detail: Option<String>,
+}
+
+/// Client of the drive's JSON API.
+pub(crate) struct Client {
+    api: Url,
+    token: Option<String>,
+    http: reqwest::blocking::Client,
+}
+
+impl Client {
+    pub(crate) fn new(url: &str, token: Option<String>) -> Result<Self> {
+        let api = Url::parse(&format!("{}/api/v1", url.trim_end_matches('/')))
+            .context(format!("Invalid drive address {url:?}"))?;
+        let http = reqwest::blocking::Client::builder()
+            // transfers of large files take as long as they need
+            .timeout(None)
+            .build()?;
+        Ok(Self { api, token, http })
+    }
+
+    /// Entries of the folder at `path`, reading all pages.
+    pub(crate) fn list(&self, path: &str) -> Result<Vec<Entry>> {
+        self.read_pages(self.url("folders", path))
+    }
+
+    /// Readable entries whose names start with `query`.
+    pub(crate) fn search(&self, query: &str) -> Result<Vec<Entry>> {
+        let mut url = self.url("search", "");
+        url.query_pairs_mut().append_pair("q", query);
+        self.read_pages(url)
+    }
+
+    /// The file or folder at `path`, if it exists.
+    pub(crate) fn entry(&self, path: &str) -> Result<Option<Entry>> {
+        if let Some(response) =
+            optional(self.send(self.request(Method::GET, self.url("files", path))))?
+        {
+            return Ok(Some(response.json()?));
+        }
+        let mut url = self.url("folders", path);
+        url.query_pairs_mut().append_pair("limit", "1");
+        match optional(self.send(self.request(Method::GET, url)))? {
+            Some(response) => Ok(response.json::<Page>()?.folder),
+            None => Ok(None),
+        }
+    }
+
+    /// Writes the contents of the file at `path` to `out`, returning the number of bytes.
+    pub(crate) fn download(
+        &self,
+        path: &str,
+        out: &mut impl Write,
+        progress: &ProgressBar,
+    ) -> Result<u64> {
+        let response = self.send(self.request(Method::GET, self.url("downloads", path)))?;
+        if let Some(length) = response.content_length() {
+            progress.set_length(length);
+        }
+        std::io::copy(&mut progress.wrap_read(response), out).context("Download failed")
+    }
+
+    /// Uploads the local `file` to the folder at `path`.
+    pub(crate) fn upload(
+        &self,
+        path: &str,
+        file: &Path,
+        progress: &ProgressBar,
+    ) -> Result<UploadResult> {
+        let name = file
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .context(format!("Invalid file {:?}", file))?;
+        let contents = File::open(file).context(format!("Cannot read {:?}", file))?;
+        let length = contents.metadata()?.len();
+        progress.set_length(length);
+        let part = multipart::Part::reader_with_length(progress.wrap_read(contents), length)
+            .file_name(name);
+        let form = multipart::Form::new().part("file", part);
+        let request = self
+            .request(Method::POST, self.url("uploads", path))
+            .multipart(form);
+        Ok(self.send(request)?.json()?)
+    }
+
+    /// Creates the folder at `path`, its parent must exist.
+    pub(crate) fn create_folder(&self, path: &str) -> Result<Entry> {
+        let path = path.trim_end_matches('/');
+        let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+        let request = self
+            .request(Method::POST, self.url("folders", parent))
+            .json(&serde_json::json!({ "name": name }));
+        Ok(self.send(request)?.json()?)
+    }
+
+    pub(crate) fn delete(&self, entry: &Entry) -> Result<()> {
+        let resource = match entry.kind {
+            EntryKind::File => "files",
+            EntryKind::Folder => "folders",
+        };
+        self.send(self.request(Method::DELETE, self.url(resource, &entry.path)))?;
+        Ok(())
+    }
+
+    /// Deletes the folder at `path` unless it holds anything, even entries hidden from
+    /// listings. Returns whether it was deleted.
+    pub(crate) fn delete_empty_folder(&self, path: &str) -> Result<bool> {
+        let mut url = self.url("folders", path);
+        url.query_pairs_mut().append_pair("recursive", "false");
+        match self.send(self.request(Method::DELETE, url)) {
+            Ok(_) => Ok(true),
+            Err(e) => match e.downcast_ref::<RequestError>() {
+                Some(error) if error.status == StatusCode::CONFLICT => Ok(false),
+                _ => Err(e),
+            },
+        }
+    }
+
+    /// Moves or renames the entry at `from`, returning it at its new path.
+    pub(crate) fn move_entry(&self, from: &str, to: &str) -> Result<Entry> {
+        let request = self
+            .request(Method::POST, self.url("moves", ""))
+            .json(&serde_json::json!({ "from": from, "to": to }));
+        Ok(self.send(request)?.json()?)
+    }
+
+    fn read_pages(&self, url: Url) -> Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let mut page_url = url.clone();
+            page_url
+                .query_pairs_mut()
+                .append_pair("limit", &PAGE_SIZE.to_string());
+            if let Some(cursor) = &cursor {
+                page_url.query_pairs_mut().append_pair("cursor", cursor);
+            }
+            let page: Page = self.send(self.request(Method::GET, page_url))?.json()?;
+            entries.extend(page.entries);
+            match page.next_cursor {
+                Some(next_cursor) => cursor = Some(next_cursor),
+                None => return Ok(entries),
+            }
+        }
+    }
+
+    /// Address of the API `resource` for the drive `path`, e.g. `/api/v1/folders/photos`.
+    fn url(&self, resource: &str, path: &str) -> Url {
+        let mut url = self.api.clone();
+        url.path_segments_mut()
+            .unwrap()
+            .push(resource)
+            .extend(path.split('/').filter(|segment| !segment.is_empty()));
+        url
+    }
+
+    fn request(&self, method: Method, url: Url) -> RequestBuilder {
+        let request = self.http.request(method, url);
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    fn send(&self, request: RequestBuilder) -> Result<Response> {
+        let response = request.send().context("Cannot reach the drive")?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let message = match response.json::<Problem>() {
+            Ok(Problem {
+                detail: Some(detail),
+                ..
+            }) => detail,
+            Ok(problem) => problem.title,
+            Err(_) => status.to_string(),
+        };
+        Err(RequestError { status, message }.into())
+    }
+}
+
+/// Error response of the drive.
+#[derive(Debug, thiserror::Error)]
+#[error("{message}")]
+struct RequestError {
+    status: StatusCode,
+    message: String,
+}
+
+/// Turns "Not Found" responses into `None`.
+fn optional(response: Result<Response>) -> Result<Option<Response>> {
+    match response {
+        Ok(response) => Ok(Some(response)),
+        Err(e) => match e.downcast_ref::<RequestError>() {
+            Some(error) if error.status == StatusCode::NOT_FOUND => Ok(None),
+            _ => Err(e),
+        },
+    }
+}
+
+/// Advice for errors caused by the token or the permissions of its owner.
+pub(crate) fn hint(e: &anyhow::Error) -> Option<&'static str> {
+    match e.downcast_ref::<RequestError>()?.status {
+        StatusCode::UNAUTHORIZED => {
+            Some("Create an API token on the \"API tokens\" page and pass it with --token or MY_DRIVE_TOKEN")
+        }
+        StatusCode::FORBIDDEN => Some("The token's scope or your permissions do not allow this"),
+        _ => None,
+    }
+}
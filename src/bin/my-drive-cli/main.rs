@@ -0,0 +1,337 @@
+//! Command-line client of a running drive, talking to its JSON API.
+
+mod client;
+mod sync;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand};
+use client::{Client, Entry, EntryKind, UploadResult};
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::Serialize;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "my-drive-cli",
+    version,
+    about = "Command-line client of My Drive"
+)]
+struct Cli {
+    /// Address of the drive
+    #[arg(long, env = "MY_DRIVE_URL", default_value = "http://localhost:8080")]
+    url: String,
+    /// Personal API token, created on the "API tokens" page of the drive
+    #[arg(long, env = "MY_DRIVE_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+    /// Print results as JSON, e.g. for scripts
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// List a folder
+    Ls {
+        #[arg(default_value = "/")]
+        path: String,
+    },
+    /// Download a file
+    Get {
+        remote: String,
+        /// Local file or folder, the current folder by default
+        local: Option<PathBuf>,
+    },
+    /// Upload files and folders with all their contents
+    Put {
+        /// Local files or folders
+        #[arg(required = true)]
+        local: Vec<PathBuf>,
+        /// Drive folder to upload to
+        remote: String,
+    },
+    /// Delete a file or a folder with all its contents
+    Rm { path: String },
+    /// Create a folder
+    Mkdir { path: String },
+    /// Move or rename a file or folder
+    Mv { from: String, to: String },
+    /// Find files and folders by the beginning of their names
+    Search { query: String },
+    /// Synchronize a local directory with a drive folder in both directions
+    ///
+    /// Changes are detected with the sizes and modification times of files and the hashes of
+    /// local files, remembered in the `.my-drive-sync.json` file of the directory. When a file
+    /// changed on both sides, the local copy is kept next to the drive's one, e.g. as
+    /// "notes (conflict).txt". Entries starting with a dot and entries matching the rules of
+    /// the `.syncignore` file of the directory are not synchronized.
+    Sync {
+        local: PathBuf,
+        remote: String,
+        /// Gitignore-like pattern of entries not to synchronize, can be repeated
+        #[arg(long)]
+        ignore: Vec<String>,
+        /// Only print what would be done
+        #[arg(long)]
+        dry_run: bool,
+        /// Synchronize even if all entries disappeared on one side, deleting them on the other side
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+fn main() {
+    let cli = Cli::parse();
+    if let Err(e) = run(cli) {
+        eprintln!("Error: {e:#}");
+        if let Some(hint) = client::hint(&e) {
+            eprintln!("{hint}");
+        }
+        std::process::exit(1);
+    }
+}
+
+fn run(cli: Cli) -> Result<()> {
+    let client = Client::new(&cli.url, cli.token.clone())?;
+    let json = cli.json;
+    match cli.command {
+        Command::Ls { path } => print_entries(&client.list(&path)?, json),
+        Command::Search { query } => print_entries(&client.search(&query)?, json),
+        Command::Get { remote, local } => {
+            let entry = existing_entry(&client, &remote)?;
+            if entry.kind == EntryKind::Folder {
+                bail!("{} is a folder, only files can be downloaded", entry.path);
+            }
+            let local = match local {
+                Some(local) if local.is_dir() => local.join(&entry.name),
+                Some(local) => local,
+                None => PathBuf::from(&entry.name),
+            };
+            // an existing local file is only replaced once the download succeeded
+            let folder = match local.parent() {
+                Some(folder) if !folder.as_os_str().is_empty() => folder,
+                _ => Path::new("."),
+            };
+            let mut temp_file = tempfile::Builder::new()
+                .prefix(".my-drive-download")
+                .tempfile_in(folder)
+                .context(format!("Cannot create a file in {:?}", folder))?;
+            let progress = progress_bar(&entry.name, json);
+            let downloaded = client.download(&entry.path, temp_file.as_file_mut(), &progress);
+            progress.finish_and_clear();
+            let bytes = downloaded?;
+            temp_file
+                .persist(&local)
+                .context(format!("Cannot create {:?}", local))?;
+            print(
+                json,
+                &serde_json::json!({ "path": entry.path, "local": local, "bytes": bytes }),
+                || format!("{} -> {}", entry.path, local.display()),
+            )
+        }
+        Command::Put { local, remote } => {
+            let mut result = UploadResult::default();
+            for path in &local {
+                upload(&client, path, &remote, json, &mut result)?;
+            }
+            print(json, &result, || {
+                let failures = result
+                    .failed
+                    .iter()
+                    .map(|failure| format!("failed {}: {}", failure.name, failure.detail));
+                result
+                    .saved
+                    .iter()
+                    .map(|entry| entry.path.clone())
+                    .chain(failures)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })?;
+            if !result.failed.is_empty() {
+                bail!("{} file(s) could not be uploaded", result.failed.len());
+            }
+            Ok(())
+        }
+        Command::Rm { path } => {
+            let entry = existing_entry(&client, &path)?;
+            client.delete(&entry)?;
+            print(json, &serde_json::json!({ "deleted": entry.path }), || {
+                format!("deleted {}", entry.path)
+            })
+        }
+        Command::Mkdir { path } => {
+            let entry = client.create_folder(&path)?;
+            print(json, &entry, || entry.path.clone())
+        }
+        Command::Mv { from, to } => {
+            let entry = client.move_entry(&from, &to)?;
+            print(json, &entry, || format!("{from} -> {}", entry.path))
+        }
+        Command::Sync {
+            local,
+            remote,
+            ignore,
+            dry_run,
+            force,
+        } => {
+            let options = sync::Options {
+                url: &cli.url,
+                ignore: &ignore,
+                dry_run,
+                force,
+                json,
+            };
+            // one JSON document per line, so scripts can follow the progress
+            let failed = sync::sync(&client, &local, &remote, options, |report| {
+                if json {
+                    println!("{}", serde_json::to_string(report).unwrap_or_default());
+                } else {
+                    println!("{report}");
+                }
+            })?;
+            if failed > 0 {
+                bail!("{failed} action(s) failed, they are retried on the next run");
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Uploads the local file, or the folder with its contents, into the drive folder `remote`.
+fn upload(
+    client: &Client,
+    local: &Path,
+    remote: &str,
+    json: bool,
+    result: &mut UploadResult,
+) -> Result<()> {
+    let metadata = fs::metadata(local).context(format!("Cannot read {:?}", local))?;
+    if !metadata.is_dir() {
+        let name = local.file_name().unwrap_or_default().to_string_lossy();
+        let progress = progress_bar(&name, json);
+        let uploaded = client.upload(remote, local, &progress);
+        progress.finish_and_clear();
+        let uploaded = uploaded?;
+        result.saved.extend(uploaded.saved);
+        result.failed.extend(uploaded.failed);
+        return Ok(());
+    }
+    let name = local
+        .canonicalize()?
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .context("The root folder cannot be uploaded")?;
+    let folder = join(remote, &name);
+    match client.entry(&folder)? {
+        Some(entry) if entry.kind == EntryKind::Folder => (),
+        Some(_) => bail!("{folder} exists already and is not a folder"),
+        None => {
+            client.create_folder(&folder)?;
+        }
+    }
+    let mut children = fs::read_dir(local)?
+        .map(|child| child.map(|child| child.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    children.sort();
+    for child in children {
+        upload(client, &child, &folder, json, result)?;
+    }
+    Ok(())
+}
+
+fn existing_entry(client: &Client, path: &str) -> Result<Entry> {
+    client
+        .entry(path)?
+        .context(format!("{path} does not exist"))
+}
+
+/// Drive path of the entry `name` in the folder at `path`.
+fn join(path: &str, name: &str) -> String {
+    format!("/{}/{name}", path.trim_matches('/')).replace("//", "/")
+}
+
+/// Progress of a transfer on standard error, hidden for JSON output.
+fn progress_bar(name: &str, json: bool) -> ProgressBar {
+    if json {
+        return ProgressBar::hidden();
+    }
+    let progress = ProgressBar::new(0).with_message(name.to_owned());
+    progress.set_style(
+        ProgressStyle::with_template(
+            "{msg:20!} [{bar:30}] {bytes}/{total_bytes} {bytes_per_sec} {eta}",
+        )
+        .unwrap()
+        .progress_chars("=> "),
+    );
+    progress
+}
+
+fn print_entries(entries: &[Entry], json: bool) -> Result<()> {
+    print(json, &entries, || {
+        entries
+            .iter()
+            .map(|entry| match entry.kind {
+                EntryKind::Folder => format!("{:>10}  {}/", "-", entry.path),
+                EntryKind::File => format!(
+                    "{:>10}  {}",
+                    entry.size.map(format_size).unwrap_or_default(),
+                    entry.path
+                ),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    })
+}
+
+/// Prints the `value` as JSON or the text made by `text`.
+fn print(json: bool, value: &impl Serialize, text: impl FnOnce() -> String) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(value)?);
+    } else {
+        let text = text();
+        if !text.is_empty() {
+            println!("{text}");
+        }
+    }
+    Ok(())
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+#[cfg(test)]
+mod test {
+    use clap::CommandFactory;
+
+    use super::{format_size, join, Cli};
+
+    #[test]
+    fn test_cli() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_helpers() {
+        assert_eq!(join("/", "docs"), "/docs");
+        assert_eq!(join("photos/", "cat.jpg"), "/photos/cat.jpg");
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(5 * 1024 * 1024), "5.0 MB");
+    }
+}
@@ -0,0 +1,496 @@
+//! Two-way synchronization of a local directory with a drive folder.
+
+mod plan;
+mod state;
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io,
+    path::Path,
+    time::UNIX_EPOCH,
+};
+
+use anyhow::{bail, Context, Result};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    client::{Client, Entry, EntryKind},
+    join, progress_bar,
+};
+use plan::{Action, Node};
+use state::{Stat, SyncState, Synced};
+
+/// Name of the file in the local directory with gitignore-like rules for entries not to synchronize.
+pub(crate) const IGNORE_FILE_NAME: &str = ".syncignore";
+
+/// Detail of a deleted folder still holding ignored or hidden entries, which are never deleted.
+const KEPT_FOLDER: &str = "kept, it holds entries that are not synchronized";
+
+/// Outcome of an action, printed when it is done.
+#[derive(Debug, Serialize)]
+pub(crate) struct Report {
+    #[serde(flatten)]
+    action: Action,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = serde_json::to_value(&self.action)
+            .ok()
+            .and_then(|value| value["action"].as_str().map(|name| name.replace('_', " ")))
+            .unwrap_or_default();
+        write!(f, "{name:<20} {}", self.action.path())?;
+        match &self.detail {
+            Some(detail) => write!(f, " ({detail})"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Settings of a synchronization.
+pub(crate) struct Options<'a> {
+    pub(crate) url: &'a str,
+    pub(crate) ignore: &'a [String],
+    pub(crate) dry_run: bool,
+    pub(crate) force: bool,
+    pub(crate) json: bool,
+}
+
+/// Synchronizes the `local` directory with the drive folder at `remote` in both directions,
+/// calling `report` after each action. Returns the number of failed actions.
+pub(crate) fn sync(
+    client: &Client,
+    local: &Path,
+    remote: &str,
+    options: Options,
+    mut report: impl FnMut(&Report),
+) -> Result<usize> {
+    let remote = format!("/{}", remote.trim_matches('/'));
+    fs::create_dir_all(local).context(format!("Cannot create {:?}", local))?;
+    let mut state = SyncState::load(local, options.url, &remote)?;
+    let ignored = ignore_rules(local, options.ignore)?;
+
+    match client.entry(&remote)? {
+        Some(entry) if entry.kind == EntryKind::Folder => (),
+        Some(_) => bail!("{remote} is a file"),
+        None if options.dry_run => (),
+        None => {
+            client.create_folder(&remote)?;
+        }
+    }
+    let mut local_nodes = BTreeMap::new();
+    scan_local(local, "", &ignored, &mut local_nodes)?;
+    let mut remote_nodes = BTreeMap::new();
+    scan_remote(client, &remote, local, "", &ignored, &mut remote_nodes)?;
+    refresh_touched_files(local, &local_nodes, &mut state.entries);
+
+    // an unmounted disk or a wrong folder must not empty the other side
+    let vanished = |nodes: &BTreeMap<String, Node>| {
+        !state.entries.is_empty() && state.entries.keys().all(|path| !nodes.contains_key(path))
+    };
+    if !options.force && (vanished(&local_nodes) || vanished(&remote_nodes)) {
+        bail!("All synchronized entries are missing on one side, use --force to delete them on the other side");
+    }
+
+    let actions = plan::plan(&local_nodes, &remote_nodes, &state.entries);
+    let mut syncer = Syncer {
+        client,
+        local,
+        remote: &remote,
+        remote_nodes: &remote_nodes,
+        json: options.json,
+    };
+    // creations and transfers go from parents to contents, deletions the other way round
+    let (deletions, others): (Vec<_>, Vec<_>) = actions.into_iter().partition(Action::is_deletion);
+    let mut failed = 0;
+    for action in others.into_iter().chain(deletions.into_iter().rev()) {
+        let detail = if options.dry_run {
+            None
+        } else {
+            match syncer.apply(&action, &mut state.entries) {
+                Ok(detail) => detail,
+                Err(e) => {
+                    failed += 1;
+                    Some(format!("failed: {e:#}"))
+                }
+            }
+        };
+        if !matches!(action, Action::Record(_) | Action::Forget(_)) {
+            report(&Report { action, detail });
+        }
+    }
+    if !options.dry_run {
+        state.save(local)?;
+    }
+    Ok(failed)
+}
+
+struct Syncer<'a> {
+    client: &'a Client,
+    local: &'a Path,
+    remote: &'a str,
+    remote_nodes: &'a BTreeMap<String, Node>,
+    json: bool,
+}
+
+impl Syncer<'_> {
+    /// Applies the `action` and updates the synchronized `entries`, returning details for the user.
+    fn apply(
+        &mut self,
+        action: &Action,
+        entries: &mut BTreeMap<String, Synced>,
+    ) -> Result<Option<String>> {
+        let path = action.path();
+        let local_path = self.local.join(path);
+        let remote_path = join(self.remote, path);
+        match action {
+            Action::Record(_) => {
+                entries.insert(path.to_owned(), Synced::Folder);
+            }
+            Action::Forget(_) => {
+                entries.remove(path);
+            }
+            Action::CreateLocalFolder(_) => {
+                fs::create_dir_all(&local_path)?;
+                entries.insert(path.to_owned(), Synced::Folder);
+            }
+            Action::CreateRemoteFolder(_) => {
+                self.client.create_folder(&remote_path)?;
+                entries.insert(path.to_owned(), Synced::Folder);
+            }
+            Action::Upload(_) => {
+                let synced = self.upload(path)?;
+                entries.insert(path.to_owned(), synced);
+            }
+            Action::Download(_) => {
+                let (temp_file, sha256) = self.download(path)?;
+                temp_file.persist(&local_path)?;
+                entries.insert(path.to_owned(), self.synced(path, sha256)?);
+            }
+            Action::Conflict(_) => {
+                let (temp_file, remote_sha256) = self.download(path)?;
+                if hash_file(&local_path)? == remote_sha256 {
+                    entries.insert(path.to_owned(), self.synced(path, remote_sha256)?);
+                    return Ok(Some("same contents".to_owned()));
+                }
+                // the local copy is renamed and uploaded, the drive's one takes its place
+                let copy = self.conflict_copy_path(path);
+                fs::rename(&local_path, self.local.join(&copy))?;
+                temp_file.persist(&local_path)?;
+                entries.insert(path.to_owned(), self.synced(path, remote_sha256)?);
+                let synced = self.upload(&copy)?;
+                entries.insert(copy.clone(), synced);
+                return Ok(Some(format!("local copy kept as {copy}")));
+            }
+            Action::DeleteLocal(_) => {
+                let result = if local_path.is_dir() {
+                    fs::remove_dir(&local_path)
+                } else {
+                    fs::remove_file(&local_path)
+                };
+                match result {
+                    Err(e) if e.kind() == io::ErrorKind::DirectoryNotEmpty => {
+                        return Ok(Some(KEPT_FOLDER.to_owned()));
+                    }
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                        return Err(e).context(format!("Cannot delete {:?}", local_path))
+                    }
+                    _ => {
+                        entries.remove(path);
+                    }
+                }
+            }
+            Action::DeleteRemote(_) => {
+                let deleted = match self.client.entry(&remote_path)? {
+                    // its synchronized contents were deleted before
+                    Some(entry) if entry.kind == EntryKind::Folder => {
+                        self.client.delete_empty_folder(&remote_path)?
+                    }
+                    Some(entry) => {
+                        self.client.delete(&entry)?;
+                        true
+                    }
+                    None => true,
+                };
+                if !deleted {
+                    return Ok(Some(KEPT_FOLDER.to_owned()));
+                }
+                entries.remove(path);
+            }
+            Action::KindMismatch(_) => {
+                return Ok(Some(
+                    "a file on one side is a folder on the other, rename one of them".to_owned(),
+                ));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Uploads the local file at `path`, replacing the drive's one only once the upload succeeded.
+    fn upload(&mut self, path: &str) -> Result<Synced> {
+        let local_path = self.local.join(path);
+        let sha256 = hash_file(&local_path)?;
+        let remote_path = join(self.remote, path);
+        let folder = match path.rsplit_once('/') {
+            Some((folder, _)) => join(self.remote, folder),
+            None => self.remote.to_owned(),
+        };
+        let progress = progress_bar(path, self.json);
+        let uploaded = self.client.upload(&folder, &local_path, &progress);
+        progress.finish_and_clear();
+        let mut uploaded = uploaded?;
+        if let Some(failure) = uploaded.failed.pop() {
+            bail!("{}", failure.detail);
+        }
+        let mut entry = uploaded.saved.pop().context("Nothing was uploaded")?;
+        // uploads never overwrite files, they are saved under a new name instead
+        if entry.path != remote_path {
+            if let Some(existing) = self.client.entry(&remote_path)? {
+                self.client.delete(&existing)?;
+            }
+            entry = self.client.move_entry(&entry.path, &remote_path)?;
+        }
+        Ok(Synced::File {
+            local: local_stat(&fs::metadata(&local_path)?),
+            remote: remote_stat(&entry),
+            sha256,
+        })
+    }
+
+    /// Downloads the drive's file at `path` next to the local one, returning it with its hash.
+    fn download(&mut self, path: &str) -> Result<(tempfile::NamedTempFile, String)> {
+        let local_path = self.local.join(path);
+        let folder = local_path.parent().unwrap_or(self.local);
+        fs::create_dir_all(folder)?;
+        // starting with a dot, so it is never synchronized itself
+        let mut temp_file = tempfile::Builder::new()
+            .prefix(".my-drive-sync")
+            .tempfile_in(folder)?;
+        let progress = progress_bar(path, self.json);
+        let downloaded =
+            self.client
+                .download(&join(self.remote, path), temp_file.as_file_mut(), &progress);
+        progress.finish_and_clear();
+        downloaded?;
+        let sha256 = hash_file(temp_file.path())?;
+        Ok((temp_file, sha256))
+    }
+
+    /// State of the file at `path` after it was made equal on both sides.
+    fn synced(&self, path: &str, sha256: String) -> Result<Synced> {
+        let Some(Node::File(remote)) = self.remote_nodes.get(path) else {
+            bail!("{path} is not a file on the drive");
+        };
+        Ok(Synced::File {
+            local: local_stat(&fs::metadata(self.local.join(path))?),
+            remote: *remote,
+            sha256,
+        })
+    }
+
+    /// Free path for the local copy of a conflicting file, e.g. "notes (conflict 2).txt".
+    fn conflict_copy_path(&self, path: &str) -> String {
+        let (folder, name) = match path.rsplit_once('/') {
+            Some((folder, name)) => (format!("{folder}/"), name),
+            None => (String::new(), path),
+        };
+        let (stem, extension) = match name.rsplit_once('.') {
+            Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{extension}")),
+            _ => (name, String::new()),
+        };
+        (1..)
+            .map(|i| match i {
+                1 => format!("{folder}{stem} (conflict){extension}"),
+                i => format!("{folder}{stem} (conflict {i}){extension}"),
+            })
+            .find(|copy| !self.local.join(copy).exists() && !self.remote_nodes.contains_key(copy))
+            .unwrap()
+    }
+}
+
+/// Rules from the `.syncignore` file of the `local` directory and the given `patterns`.
+fn ignore_rules(local: &Path, patterns: &[String]) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(local);
+    let ignore_file = local.join(IGNORE_FILE_NAME);
+    if ignore_file.is_file() {
+        if let Some(e) = builder.add(&ignore_file) {
+            bail!("Invalid ignore file {:?}: {}", ignore_file, e);
+        }
+    }
+    for pattern in patterns {
+        builder
+            .add_line(None, pattern)
+            .context(format!("Invalid ignore pattern {pattern:?}"))?;
+    }
+    Ok(builder.build()?)
+}
+
+/// Entries starting with a dot are skipped like the drive hides them, e.g. the sync state.
+fn is_skipped(ignored: &Gitignore, name: &str, path: &Path, is_dir: bool) -> bool {
+    name.starts_with('.') || ignored.matched(path, is_dir).is_ignore()
+}
+
+fn scan_local(
+    local: &Path,
+    prefix: &str,
+    ignored: &Gitignore,
+    nodes: &mut BTreeMap<String, Node>,
+) -> Result<()> {
+    let dir = local.join(prefix);
+    for child in fs::read_dir(&dir).context(format!("Cannot read {:?}", dir))? {
+        let child = child?;
+        let name = child.file_name().to_string_lossy().into_owned();
+        let path = child_path(prefix, &name);
+        let Ok(metadata) = fs::metadata(child.path()) else {
+            // e.g. broken symbolic links
+            continue;
+        };
+        if is_skipped(ignored, &name, &local.join(&path), metadata.is_dir()) {
+            continue;
+        }
+        if metadata.is_dir() {
+            nodes.insert(path.clone(), Node::Folder);
+            scan_local(local, &path, ignored, nodes)?;
+        } else if metadata.is_file() {
+            nodes.insert(path, Node::File(local_stat(&metadata)));
+        }
+    }
+    Ok(())
+}
+
+fn scan_remote(
+    client: &Client,
+    remote: &str,
+    local: &Path,
+    prefix: &str,
+    ignored: &Gitignore,
+    nodes: &mut BTreeMap<String, Node>,
+) -> Result<()> {
+    // the folder is created later when synchronizing for real
+    if prefix.is_empty() && client.entry(remote)?.is_none() {
+        return Ok(());
+    }
+    for entry in client.list(&join(remote, prefix))? {
+        let path = child_path(prefix, &entry.name);
+        let is_dir = entry.kind == EntryKind::Folder;
+        if is_skipped(ignored, &entry.name, &local.join(&path), is_dir) {
+            continue;
+        }
+        if is_dir {
+            nodes.insert(path.clone(), Node::Folder);
+            scan_remote(client, remote, local, &path, ignored, nodes)?;
+        } else {
+            nodes.insert(path, Node::File(remote_stat(&entry)));
+        }
+    }
+    Ok(())
+}
+
+/// Updates the local stat of files whose modification time changed but not their contents,
+/// e.g. after they were copied back from a backup.
+fn refresh_touched_files(
+    local: &Path,
+    nodes: &BTreeMap<String, Node>,
+    entries: &mut BTreeMap<String, Synced>,
+) {
+    for (path, synced) in entries.iter_mut() {
+        if let (
+            Some(Node::File(stat)),
+            Synced::File {
+                local: synced_local,
+                sha256,
+                ..
+            },
+        ) = (nodes.get(path), synced)
+        {
+            if stat != synced_local
+                && stat.size == synced_local.size
+                && hash_file(&local.join(path)).is_ok_and(|hash| &hash == sha256)
+            {
+                *synced_local = *stat;
+            }
+        }
+    }
+}
+
+fn child_path(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{prefix}/{name}")
+    }
+}
+
+fn local_stat(metadata: &fs::Metadata) -> Stat {
+    Stat {
+        size: metadata.len(),
+        modified_at: metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |duration| duration.as_millis() as u64),
+    }
+}
+
+fn remote_stat(entry: &Entry) -> Stat {
+    Stat {
+        size: entry.size.unwrap_or(0),
+        modified_at: entry.modified_at.unwrap_or(0),
+    }
+}
+
+fn hash_file(path: &Path) -> Result<String> {
+    let mut file = File::open(path).context(format!("Cannot read {:?}", path))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::BTreeMap, fs};
+
+    use super::{plan::Action, state::Synced, Syncer, KEPT_FOLDER};
+    use crate::client::Client;
+
+    #[test]
+    fn test_deleted_folder_keeps_ignored_entries() {
+        let local = tempfile::tempdir().unwrap();
+        fs::create_dir(local.path().join("gone")).unwrap();
+        fs::write(local.path().join("gone/old.txt"), "synced").unwrap();
+        fs::write(local.path().join("gone/raw.tmp"), "ignored").unwrap();
+        // local deletions never reach the drive
+        let client = Client::new("http://127.0.0.1:1", None).unwrap();
+        let mut syncer = Syncer {
+            client: &client,
+            local: local.path(),
+            remote: "/",
+            remote_nodes: &BTreeMap::new(),
+            json: true,
+        };
+        let mut entries = BTreeMap::from([
+            ("gone".to_owned(), Synced::Folder),
+            ("gone/old.txt".to_owned(), Synced::Folder),
+        ]);
+
+        let detail = syncer
+            .apply(
+                &Action::DeleteLocal("gone/old.txt".to_owned()),
+                &mut entries,
+            )
+            .unwrap();
+        assert_eq!(detail, None);
+        let detail = syncer
+            .apply(&Action::DeleteLocal("gone".to_owned()), &mut entries)
+            .unwrap();
+        assert_eq!(detail.as_deref(), Some(KEPT_FOLDER));
+        assert!(local.path().join("gone/raw.tmp").exists());
+        assert!(!local.path().join("gone/old.txt").exists());
+        assert_eq!(entries.keys().collect::<Vec<_>>(), ["gone"]);
+    }
+}
@@ -0,0 +1,283 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Serialize;
+
+use super::state::{Stat, Synced};
+
+/// Entry found on one side, by its relative path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Node {
+    Folder,
+    File(Stat),
+}
+
+/// Step making both sides equal, applied to the relative path.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "action", content = "path", rename_all = "snake_case")]
+pub(super) enum Action {
+    /// Remembers a folder found on both sides.
+    Record(String),
+    /// Forgets an entry deleted on both sides.
+    Forget(String),
+    CreateLocalFolder(String),
+    CreateRemoteFolder(String),
+    Upload(String),
+    Download(String),
+    /// Both sides changed; copies with different contents are both kept.
+    Conflict(String),
+    DeleteLocal(String),
+    DeleteRemote(String),
+    /// A file on one side is a folder on the other, which is left for the user to resolve.
+    KindMismatch(String),
+}
+
+impl Action {
+    pub(super) fn path(&self) -> &str {
+        match self {
+            Action::Record(path)
+            | Action::Forget(path)
+            | Action::CreateLocalFolder(path)
+            | Action::CreateRemoteFolder(path)
+            | Action::Upload(path)
+            | Action::Download(path)
+            | Action::Conflict(path)
+            | Action::DeleteLocal(path)
+            | Action::DeleteRemote(path)
+            | Action::KindMismatch(path) => path,
+        }
+    }
+
+    pub(super) fn is_deletion(&self) -> bool {
+        matches!(self, Action::DeleteLocal(_) | Action::DeleteRemote(_))
+    }
+}
+
+/// Decides how to synchronize the `local` and `remote` entries, given the entries `synced` last time.
+///
+/// Entries changed or added on one side are copied to the other one, entries deleted on one side
+/// are deleted on the other one unless they changed there, changes winning over deletions.
+/// Actions are ordered by path, so folders come before their contents.
+pub(super) fn plan(
+    local: &BTreeMap<String, Node>,
+    remote: &BTreeMap<String, Node>,
+    synced: &BTreeMap<String, Synced>,
+) -> Vec<Action> {
+    let mut paths = local
+        .keys()
+        .chain(remote.keys())
+        .chain(synced.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    // by components, so the contents of folders follow them directly
+    paths.sort_by(|left, right| left.split('/').cmp(right.split('/')));
+    let mut actions = paths
+        .into_iter()
+        .filter_map(|path| {
+            let path = path.clone();
+            let synced = synced.get(&path);
+            match (local.get(&path), remote.get(&path)) {
+                (Some(Node::Folder), Some(Node::Folder)) => {
+                    (synced != Some(&Synced::Folder)).then_some(Action::Record(path))
+                }
+                (Some(Node::File(local)), Some(Node::File(remote))) => match synced {
+                    Some(Synced::File {
+                        local: synced_local,
+                        remote: synced_remote,
+                        ..
+                    }) => match (local != synced_local, remote != synced_remote) {
+                        (false, false) => None,
+                        (true, false) => Some(Action::Upload(path)),
+                        (false, true) => Some(Action::Download(path)),
+                        (true, true) => Some(Action::Conflict(path)),
+                    },
+                    _ => Some(Action::Conflict(path)),
+                },
+                (Some(_), Some(_)) => Some(Action::KindMismatch(path)),
+                (Some(node), None) => Some(if is_unchanged(node, synced, true) {
+                    Action::DeleteLocal(path)
+                } else if *node == Node::Folder {
+                    Action::CreateRemoteFolder(path)
+                } else {
+                    Action::Upload(path)
+                }),
+                (None, Some(node)) => Some(if is_unchanged(node, synced, false) {
+                    Action::DeleteRemote(path)
+                } else if *node == Node::Folder {
+                    Action::CreateLocalFolder(path)
+                } else {
+                    Action::Download(path)
+                }),
+                (None, None) => Some(Action::Forget(path)),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // folders deleted on one side are kept when anything inside them is kept,
+    // deepest folders first as they decide about their parents
+    for i in (0..actions.len()).rev() {
+        let path = actions[i].path().to_owned();
+        let prefix = format!("{path}/");
+        let keeps_contents = || {
+            actions[i + 1..]
+                .iter()
+                .take_while(|action| action.path().starts_with(&prefix))
+                .any(|action| !action.is_deletion() && !matches!(action, Action::Forget(_)))
+        };
+        match &actions[i] {
+            Action::DeleteLocal(_)
+                if local.get(&path) == Some(&Node::Folder) && keeps_contents() =>
+            {
+                actions[i] = Action::CreateRemoteFolder(path);
+            }
+            Action::DeleteRemote(_)
+                if remote.get(&path) == Some(&Node::Folder) && keeps_contents() =>
+            {
+                actions[i] = Action::CreateLocalFolder(path);
+            }
+            _ => (),
+        }
+    }
+    actions
+}
+
+/// Checks whether the entry found on one side only is as it was synchronized.
+fn is_unchanged(node: &Node, synced: Option<&Synced>, is_local: bool) -> bool {
+    match (node, synced) {
+        (Node::Folder, Some(Synced::Folder)) => true,
+        (Node::File(stat), Some(Synced::File { local, remote, .. })) => {
+            stat == if is_local { local } else { remote }
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::{plan, Action, Node};
+    use crate::sync::state::{Stat, Synced};
+
+    fn file(size: u64, modified_at: u64) -> Node {
+        Node::File(Stat { size, modified_at })
+    }
+
+    fn synced_file(local: Node, remote: Node) -> Synced {
+        let (Node::File(local), Node::File(remote)) = (local, remote) else {
+            unreachable!()
+        };
+        Synced::File {
+            local,
+            remote,
+            sha256: String::new(),
+        }
+    }
+
+    fn entries<T: Clone>(entries: &[(&str, T)]) -> BTreeMap<String, T> {
+        entries
+            .iter()
+            .map(|(path, entry)| (path.to_string(), entry.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_changes() {
+        let local = entries(&[
+            ("same.txt", file(1, 10)),
+            ("edited.txt", file(2, 20)),
+            ("new.txt", file(3, 30)),
+            ("both.txt", file(4, 40)),
+        ]);
+        let remote = entries(&[
+            ("same.txt", file(1, 1)),
+            ("edited.txt", file(2, 2)),
+            ("remote.txt", file(5, 5)),
+            ("both.txt", file(4, 5)),
+        ]);
+        let synced = entries(&[
+            ("same.txt", synced_file(file(1, 10), file(1, 1))),
+            ("edited.txt", synced_file(file(2, 10), file(2, 2))),
+            ("both.txt", synced_file(file(4, 10), file(4, 1))),
+        ]);
+        assert_eq!(
+            plan(&local, &remote, &synced),
+            [
+                Action::Conflict("both.txt".to_owned()),
+                Action::Upload("edited.txt".to_owned()),
+                Action::Upload("new.txt".to_owned()),
+                Action::Download("remote.txt".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deletions() {
+        let local = entries(&[
+            ("kept", Node::Folder),
+            ("kept/new.txt", file(1, 10)),
+            ("kept/old.txt", file(1, 10)),
+            ("edited.txt", file(2, 20)),
+        ]);
+        let remote = entries(&[("gone", Node::Folder), ("gone/old.txt", file(1, 1))]);
+        let synced = entries(&[
+            ("kept", Synced::Folder),
+            ("kept/old.txt", synced_file(file(1, 10), file(1, 1))),
+            ("gone", Synced::Folder),
+            ("gone/old.txt", synced_file(file(1, 10), file(1, 1))),
+            ("edited.txt", synced_file(file(2, 10), file(2, 1))),
+            ("both.txt", synced_file(file(2, 10), file(2, 1))),
+        ]);
+        assert_eq!(
+            plan(&local, &remote, &synced),
+            [
+                Action::Forget("both.txt".to_owned()),
+                Action::Upload("edited.txt".to_owned()),
+                Action::DeleteRemote("gone".to_owned()),
+                Action::DeleteRemote("gone/old.txt".to_owned()),
+                // the folder deleted on the drive has a new file
+                Action::CreateRemoteFolder("kept".to_owned()),
+                Action::Upload("kept/new.txt".to_owned()),
+                Action::DeleteLocal("kept/old.txt".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_new_folders() {
+        let local = entries(&[("a", Node::Folder), ("b", Node::Folder), ("c", file(1, 1))]);
+        let remote = entries(&[
+            ("a", Node::Folder),
+            ("c", Node::Folder),
+            ("d", Node::Folder),
+        ]);
+        assert_eq!(
+            plan(&local, &remote, &BTreeMap::new()),
+            [
+                Action::Record("a".to_owned()),
+                Action::CreateRemoteFolder("b".to_owned()),
+                Action::KindMismatch("c".to_owned()),
+                Action::CreateLocalFolder("d".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deleted_folder_with_ignored_entries() {
+        // "gone/raw.tmp" is ignored, so it is in neither the scanned entries nor the state
+        let local = BTreeMap::new();
+        let remote = entries(&[("gone", Node::Folder), ("gone/old.txt", file(1, 1))]);
+        let synced = entries(&[
+            ("gone", Synced::Folder),
+            ("gone/old.txt", synced_file(file(1, 10), file(1, 1))),
+        ]);
+        // applied in reverse, so the folder is deleted last, and only when it is empty
+        assert_eq!(
+            plan(&local, &remote, &synced),
+            [
+                Action::DeleteRemote("gone".to_owned()),
+                Action::DeleteRemote("gone/old.txt".to_owned()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,73 @@
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Name of the file in the local directory remembering the last synchronization.
+pub(super) const STATE_FILE_NAME: &str = ".my-drive-sync.json";
+
+/// Size and modification time of a file, milliseconds for local files and seconds for the drive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct Stat {
+    pub(super) size: u64,
+    pub(super) modified_at: u64,
+}
+
+/// Entry found on both sides after the last synchronization.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub(super) enum Synced {
+    Folder,
+    File {
+        local: Stat,
+        remote: Stat,
+        /// SHA-256 of the contents, to tell touched files from modified ones.
+        sha256: String,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct SyncState {
+    /// Drive address and folder the directory is synchronized with.
+    pub(super) url: String,
+    pub(super) remote: String,
+    /// Entries by their paths relative to both folders, e.g. "photos/cat.jpg".
+    pub(super) entries: BTreeMap<String, Synced>,
+}
+
+impl SyncState {
+    /// Reads the state of the `local` directory, which may only be synchronized with one drive folder.
+    pub(super) fn load(local: &Path, url: &str, remote: &str) -> Result<Self> {
+        let file = local.join(STATE_FILE_NAME);
+        if !file.exists() {
+            return Ok(Self {
+                url: url.to_owned(),
+                remote: remote.to_owned(),
+                entries: BTreeMap::new(),
+            });
+        }
+        let contents =
+            std::fs::read_to_string(&file).context(format!("Failed to read {:?}", file))?;
+        let state: Self =
+            serde_json::from_str(&contents).context(format!("Invalid sync state {:?}", file))?;
+        if state.url != url || state.remote != remote {
+            bail!(
+                "{:?} is synchronized with {} on {} already, remove {:?} to change it",
+                local,
+                state.remote,
+                state.url,
+                file
+            );
+        }
+        Ok(state)
+    }
+
+    pub(super) fn save(&self, local: &Path) -> Result<()> {
+        let file = local.join(STATE_FILE_NAME);
+        let contents = serde_json::to_string_pretty(self)?;
+        // replace the file atomically, so a crash cannot leave it truncated
+        let temp_file = file.with_extension("tmp");
+        std::fs::write(&temp_file, contents).context(format!("Failed to write {:?}", temp_file))?;
+        std::fs::rename(&temp_file, &file).context(format!("Failed to write {:?}", file))
+    }
+}
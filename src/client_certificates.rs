@@ -0,0 +1,180 @@
+use std::{any::Any, collections::BTreeMap, path::Path};
+
+use actix_tls::accept::rustls_0_22::TlsStream;
+use actix_web::{dev::Extensions, rt::net::TcpStream};
+use anyhow::Context;
+use serde::Deserialize;
+use tracing::warn;
+use x509_parser::{certificate::X509Certificate, extensions::GeneralName, prelude::FromDer};
+
+/// Client certificate verified against `TLS_CLIENT_CA_FILE` during the TLS handshake,
+/// kept as connection data for all requests of the connection.
+#[derive(Debug, Clone)]
+pub(crate) struct ClientCertificate {
+    /// Subject common names, then DNS and e-mail alternative names.
+    pub(crate) names: Vec<String>,
+}
+
+/// Name of the client certificate a request is authenticated with.
+#[derive(Debug, Clone)]
+pub(crate) struct CertificateLogin(pub(crate) String);
+
+/// Keeps the client certificate of a TLS connection, registered with `HttpServer::on_connect`.
+pub(crate) fn on_connect(connection: &dyn Any, extensions: &mut Extensions) {
+    let Some(stream) = connection.downcast_ref::<TlsStream<TcpStream>>() else {
+        return;
+    };
+    let (_, session) = stream.get_ref();
+    if let Some(certificate) = session.peer_certificates().and_then(|certs| certs.first()) {
+        match certificate_names(certificate) {
+            Ok(names) => {
+                extensions.insert(ClientCertificate { names });
+            }
+            Err(e) => warn!("Cannot read the client certificate: {:?}", e),
+        }
+    }
+}
+
+fn certificate_names(der: &[u8]) -> anyhow::Result<Vec<String>> {
+    let (_, certificate) = X509Certificate::from_der(der)?;
+    let mut names: Vec<String> = certificate
+        .subject()
+        .iter_common_name()
+        .filter_map(|name| name.as_str().ok())
+        .map(str::to_owned)
+        .collect();
+    if let Some(alternative_names) = certificate.subject_alternative_name()? {
+        for name in &alternative_names.value.general_names {
+            if let GeneralName::DNSName(name) | GeneralName::RFC822Name(name) = name {
+                names.push((*name).to_owned());
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// Users identified by client certificates, loaded from the file configured with
+/// `CLIENT_CERTS_FILE`.
+///
+/// Certificates are matched by a subject common name or a DNS or e-mail alternative name.
+/// Path prefixes can require a certificate; elsewhere it is accepted when presented:
+/// ```toml
+/// [certificates."backup.lan"]
+/// user = "backup"
+/// admin = false
+///
+/// [paths]
+/// "/dav" = "required"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ClientCertificates {
+    #[serde(default)]
+    certificates: BTreeMap<String, CertificateUser>,
+    #[serde(default)]
+    paths: BTreeMap<String, CertificatePolicy>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct CertificateUser {
+    pub(crate) user: String,
+    #[serde(default)]
+    pub(crate) admin: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum CertificatePolicy {
+    /// Requests with a known certificate are signed in as its user, others authenticate as usual.
+    #[default]
+    Accepted,
+    /// Requests without a known certificate are rejected.
+    Required,
+}
+
+impl ClientCertificates {
+    pub(crate) fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents =
+            std::fs::read_to_string(path).context(format!("Failed to read {:?}", path))?;
+        toml::from_str(&contents).context(format!("Failed to parse {:?}", path))
+    }
+
+    /// The user of the first name of `certificate` with a configured user, with that name.
+    pub(crate) fn identify<'a>(
+        &'a self,
+        certificate: &'a ClientCertificate,
+    ) -> Option<(&'a str, &'a CertificateUser)> {
+        certificate.names.iter().find_map(|name| {
+            self.certificates
+                .get(name)
+                .map(|user| (name.as_str(), user))
+        })
+    }
+
+    /// Policy of the longest configured prefix of the request `path`.
+    pub(crate) fn policy(&self, path: &str) -> CertificatePolicy {
+        self.paths
+            .iter()
+            .filter(|(prefix, _)| is_under(path, prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, policy)| *policy)
+            .unwrap_or_default()
+    }
+}
+
+fn is_under(path: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+    path == prefix
+        || path
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CertificatePolicy, ClientCertificate, ClientCertificates};
+
+    #[test]
+    fn test_identities_and_policies() {
+        let certificates: ClientCertificates = toml::from_str(
+            r#"
+            [certificates."backup.lan"]
+            user = "backup"
+
+            [certificates."admin@example.com"]
+            user = "alice"
+            admin = true
+
+            [paths]
+            "/dav" = "required"
+            "/dav/public" = "accepted"
+            "#,
+        )
+        .unwrap();
+
+        let certificate = ClientCertificate {
+            names: vec!["Alice".to_owned(), "admin@example.com".to_owned()],
+        };
+        let (name, user) = certificates.identify(&certificate).unwrap();
+        assert_eq!(name, "admin@example.com");
+        assert_eq!(user.user, "alice");
+        assert!(user.admin);
+        let unknown = ClientCertificate {
+            names: vec!["other.lan".to_owned()],
+        };
+        assert!(certificates.identify(&unknown).is_none());
+
+        assert_eq!(certificates.policy("/dav"), CertificatePolicy::Required);
+        assert_eq!(
+            certificates.policy("/dav/photos/a.jpg"),
+            CertificatePolicy::Required
+        );
+        assert_eq!(
+            certificates.policy("/dav/public/a.jpg"),
+            CertificatePolicy::Accepted
+        );
+        assert_eq!(certificates.policy("/davx"), CertificatePolicy::Accepted);
+        assert_eq!(certificates.policy("/list"), CertificatePolicy::Accepted);
+    }
+}
@@ -0,0 +1,451 @@
+use std::{
+    collections::BTreeMap,
+    net::IpAddr,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use clap::{Args, Parser, Subcommand};
+use serde::{Deserialize, Serialize};
+
+use crate::drive_access::{path_resolution::SymlinkPolicy, volumes::VolumeConfig};
+
+const DEFAULT_PORT: u16 = 8080;
+const DEFAULT_SORT_LOCALE: &str = "en";
+const DEFAULT_MAX_UPLOAD_SIZE_MB: u64 = 128;
+const DEFAULT_SHUTDOWN_GRACE_PERIOD_SECONDS: u64 = 30;
+const DEFAULT_SHARES_FILE: &str = "shares.json";
+const DEFAULT_API_TOKENS_FILE: &str = "api-tokens.json";
+const DEFAULT_AUDIT_LOG_MAX_SIZE_MB: u64 = 10;
+const DEFAULT_AUDIT_LOG_MAX_FILES: usize = 5;
+/// Shortest secret accepted for signing cookies and links.
+const MIN_SECRET_LENGTH: usize = 32;
+/// File in the working directory which configured the ngrok tunnel before the configuration file.
+const LEGACY_NGROK_CONFIG_FILE: &str = "ngrok-config.toml";
+
+/// Command-line interface of the server.
+#[derive(Debug, Parser)]
+#[command(version, about = "Serves a directory as a drive over HTTP")]
+pub(crate) struct Cli {
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>,
+    /// TOML file with settings, overridden by environment variables and flags
+    #[arg(long, env = "CONFIG_FILE")]
+    pub(crate) config: Option<PathBuf>,
+    #[command(flatten)]
+    pub(crate) settings: Settings,
+}
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum Command {
+    /// Reads a password from the standard input and prints its hash to be put in USERS_FILE
+    HashPassword,
+}
+
+/// Settings given by a single source, unset ones are taken from sources of lower precedence.
+///
+/// Keys of the configuration file are the names of the environment variables in lower case,
+/// e.g. `base_dir = "/srv/drive"`; volumes and ngrok OAuth can be set in the file only.
+#[derive(Debug, Default, Args, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Settings {
+    /// Directory being the root of the drive
+    #[arg(long, env = "BASE_DIR")]
+    base_dir: Option<PathBuf>,
+    /// Port of the HTTP server [default: 8080]
+    #[arg(long, env = "PORT")]
+    port: Option<u16>,
+    /// PEM file with the TLS certificate chain, serving HTTPS when set with TLS_KEY_FILE
+    #[arg(long, env = "TLS_CERT_FILE")]
+    tls_cert_file: Option<PathBuf>,
+    /// PEM file with the private key of the TLS certificate
+    #[arg(long, env = "TLS_KEY_FILE")]
+    tls_key_file: Option<PathBuf>,
+    /// PEM file with CA certificates verifying client certificates
+    #[arg(long, env = "TLS_CLIENT_CA_FILE")]
+    tls_client_ca_file: Option<PathBuf>,
+    /// TOML file mapping client certificates to users
+    #[arg(long, env = "CLIENT_CERTS_FILE")]
+    client_certs_file: Option<PathBuf>,
+    /// Port of a plain HTTP server redirecting to HTTPS
+    #[arg(long, env = "HTTP_REDIRECT_PORT")]
+    http_redirect_port: Option<u16>,
+    /// TOML file with directories shown as top-level folders
+    #[arg(long, env = "VOLUMES_FILE")]
+    volumes_file: Option<PathBuf>,
+    #[arg(skip)]
+    volumes: Option<BTreeMap<String, VolumeConfig>>,
+    /// Locale used to sort file names [default: en]
+    #[arg(long, env = "SORT_LOCALE")]
+    sort_locale: Option<String>,
+    /// Show files with names starting with a dot
+    #[arg(long, env = "SHOW_HIDDEN_FILES", num_args = 0..=1, default_missing_value = "true")]
+    show_hidden_files: Option<bool>,
+    /// File with gitignore-like rules hiding entries in the whole drive
+    #[arg(long, env = "GLOBAL_IGNORE_FILE")]
+    global_ignore_file: Option<PathBuf>,
+    /// Handling of symbolic links: deny, follow-within-root or follow-all [default: follow-within-root]
+    #[arg(long, env = "SYMLINK_POLICY")]
+    symlink_policy: Option<String>,
+    /// Forbid any modification of the drive
+    #[arg(long, env = "READ_ONLY", num_args = 0..=1, default_missing_value = "true")]
+    read_only: Option<bool>,
+    /// Comma-separated drive paths which cannot be modified
+    #[arg(long, env = "READ_ONLY_PATHS", value_delimiter = ',')]
+    read_only_paths: Option<Vec<String>>,
+    /// Largest upload request in megabytes [default: 128]
+    #[arg(long, env = "MAX_UPLOAD_SIZE")]
+    max_upload_size: Option<u64>,
+    /// Seconds uploads in progress may take to finish after SIGTERM [default: 30]
+    #[arg(long, env = "SHUTDOWN_GRACE_PERIOD")]
+    shutdown_grace_period: Option<u64>,
+    /// TOML file with user accounts
+    #[arg(long, env = "USERS_FILE")]
+    users_file: Option<PathBuf>,
+    /// TOML file with OpenID Connect provider settings
+    #[arg(long, env = "OIDC_CONFIG")]
+    oidc_config: Option<PathBuf>,
+    /// Secret (at least 32 bytes) signing session cookies
+    #[arg(long, env = "SESSION_SECRET", hide_env_values = true)]
+    session_secret: Option<String>,
+    /// Send session cookies only over HTTPS [default: true with TLS]
+    #[arg(long, env = "SESSION_COOKIE_SECURE", num_args = 0..=1, default_missing_value = "true")]
+    session_cookie_secure: Option<bool>,
+    /// TOML file with per-folder permissions
+    #[arg(long, env = "ACL_FILE")]
+    acl_file: Option<PathBuf>,
+    /// Secret (at least 32 bytes) signing share links
+    #[arg(long, env = "SHARE_SECRET", hide_env_values = true)]
+    share_secret: Option<String>,
+    /// JSON file keeping created shares [default: shares.json]
+    #[arg(long, env = "SHARES_FILE")]
+    shares_file: Option<PathBuf>,
+    /// JSON file keeping personal API tokens [default: api-tokens.json]
+    #[arg(long, env = "API_TOKENS_FILE")]
+    api_tokens_file: Option<PathBuf>,
+    /// JSON Lines file recording modifications of the drive
+    #[arg(long, env = "AUDIT_LOG")]
+    audit_log: Option<PathBuf>,
+    /// Size in megabytes after which the audit log is rotated [default: 10]
+    #[arg(long, env = "AUDIT_LOG_MAX_SIZE")]
+    audit_log_max_size: Option<u64>,
+    /// Number of rotated audit logs kept [default: 5]
+    #[arg(long, env = "AUDIT_LOG_MAX_FILES")]
+    audit_log_max_files: Option<usize>,
+    /// Secret (at least 32 bytes) Prometheus sends as a bearer token to scrape /metrics
+    #[arg(long, env = "METRICS_TOKEN", hide_env_values = true)]
+    metrics_token: Option<String>,
+    /// Comma-separated addresses of reverse proxies whose forwarded headers tell the client address
+    #[arg(long, env = "TRUSTED_PROXIES", value_delimiter = ',')]
+    trusted_proxies: Option<Vec<IpAddr>>,
+    /// Authtoken of the ngrok tunnel
+    #[arg(long, env = "NGROK_AUTH_TOKEN", hide_env_values = true)]
+    ngrok_auth_token: Option<String>,
+    /// Domain of the ngrok tunnel
+    #[arg(long, env = "NGROK_DOMAIN")]
+    ngrok_domain: Option<String>,
+    #[arg(skip)]
+    ngrok_oauth: Option<NgrokOauth>,
+}
+
+/// OAuth protection of the ngrok tunnel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct NgrokOauth {
+    pub(crate) provider: String,
+    pub(crate) allowed_emails: Option<Vec<String>>,
+    pub(crate) allowed_domains: Option<Vec<String>>,
+}
+
+/// Certificate and private key of the HTTPS server.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "ngrok", allow(dead_code))]
+pub(crate) struct TlsFiles {
+    pub(crate) cert_file: PathBuf,
+    pub(crate) key_file: PathBuf,
+    /// CA certificates verifying client certificates, which are requested when set.
+    pub(crate) client_ca_file: Option<PathBuf>,
+}
+
+/// Validated settings of the server.
+#[derive(Debug, Clone)]
+pub(crate) struct Config {
+    pub(crate) base_dir: PathBuf,
+    pub(crate) port: u16,
+    #[cfg(not(feature = "ngrok"))]
+    pub(crate) tls: Option<TlsFiles>,
+    #[cfg(not(feature = "ngrok"))]
+    pub(crate) http_redirect_port: Option<u16>,
+    pub(crate) volumes_file: Option<PathBuf>,
+    pub(crate) volumes: BTreeMap<String, VolumeConfig>,
+    pub(crate) sort_locale: String,
+    pub(crate) show_hidden_files: bool,
+    pub(crate) global_ignore_file: Option<PathBuf>,
+    pub(crate) symlink_policy: SymlinkPolicy,
+    pub(crate) read_only: bool,
+    pub(crate) read_only_paths: Vec<String>,
+    /// In bytes.
+    pub(crate) max_upload_size: u64,
+    /// In seconds.
+    pub(crate) shutdown_grace_period: u64,
+    pub(crate) users_file: Option<PathBuf>,
+    pub(crate) client_certs_file: Option<PathBuf>,
+    pub(crate) oidc_config: Option<PathBuf>,
+    pub(crate) session_secret: Option<String>,
+    pub(crate) session_cookie_secure: bool,
+    pub(crate) acl_file: Option<PathBuf>,
+    pub(crate) share_secret: Option<String>,
+    pub(crate) shares_file: PathBuf,
+    pub(crate) api_tokens_file: PathBuf,
+    pub(crate) audit_log: Option<PathBuf>,
+    /// In bytes.
+    pub(crate) audit_log_max_size: u64,
+    pub(crate) audit_log_max_files: usize,
+    pub(crate) metrics_token: Option<String>,
+    pub(crate) trusted_proxies: Vec<IpAddr>,
+    #[cfg(feature = "ngrok")]
+    pub(crate) ngrok_auth_token: Option<String>,
+    #[cfg(feature = "ngrok")]
+    pub(crate) ngrok_domain: Option<String>,
+    #[cfg(feature = "ngrok")]
+    pub(crate) ngrok_oauth: Option<NgrokOauth>,
+}
+
+impl Config {
+    /// Reads the settings from the configuration file, environment variables and flags,
+    /// in increasing precedence.
+    pub(crate) fn load(cli: Cli) -> anyhow::Result<Self> {
+        // its OAuth protection would be silently dropped otherwise
+        if cfg!(feature = "ngrok") && Path::new(LEGACY_NGROK_CONFIG_FILE).exists() {
+            anyhow::bail!(
+                "{LEGACY_NGROK_CONFIG_FILE} is no longer read, move its settings to ngrok_auth_token, \
+                ngrok_domain and [ngrok_oauth] in the config file and delete it"
+            );
+        }
+        let file = match &cli.config {
+            Some(file) => read_file(file)?,
+            None => Settings::default(),
+        };
+        Self::new(merge(file, cli.settings)?)
+    }
+
+    fn new(settings: Settings) -> anyhow::Result<Self> {
+        let mut errors = Vec::new();
+        let base_dir = match settings.base_dir {
+            Some(base_dir) if base_dir.is_dir() => base_dir,
+            Some(base_dir) => {
+                errors.push(format!(
+                    "BASE_DIR {:?} does not exist or is not a directory",
+                    base_dir
+                ));
+                base_dir
+            }
+            None => {
+                errors.push("BASE_DIR must be set".to_owned());
+                PathBuf::new()
+            }
+        };
+        for (name, file) in [
+            ("TLS_CERT_FILE", &settings.tls_cert_file),
+            ("TLS_KEY_FILE", &settings.tls_key_file),
+            ("TLS_CLIENT_CA_FILE", &settings.tls_client_ca_file),
+            ("CLIENT_CERTS_FILE", &settings.client_certs_file),
+            ("VOLUMES_FILE", &settings.volumes_file),
+            ("GLOBAL_IGNORE_FILE", &settings.global_ignore_file),
+            ("USERS_FILE", &settings.users_file),
+            ("OIDC_CONFIG", &settings.oidc_config),
+            ("ACL_FILE", &settings.acl_file),
+        ] {
+            if let Some(file) = file.as_ref().filter(|file| !file.is_file()) {
+                errors.push(format!("{name} {:?} does not exist or is not a file", file));
+            }
+        }
+        for (name, secret) in [
+            ("SESSION_SECRET", &settings.session_secret),
+            ("SHARE_SECRET", &settings.share_secret),
+            ("METRICS_TOKEN", &settings.metrics_token),
+        ] {
+            if secret
+                .as_ref()
+                .is_some_and(|secret| secret.len() < MIN_SECRET_LENGTH)
+            {
+                errors.push(format!(
+                    "{name} must be at least {MIN_SECRET_LENGTH} bytes long"
+                ));
+            }
+        }
+        let port = settings.port.unwrap_or(DEFAULT_PORT);
+        let tls = match (settings.tls_cert_file, settings.tls_key_file) {
+            (Some(cert_file), Some(key_file)) => Some(TlsFiles {
+                cert_file,
+                key_file,
+                client_ca_file: settings.tls_client_ca_file.clone(),
+            }),
+            (None, None) => None,
+            _ => {
+                errors.push("TLS_CERT_FILE and TLS_KEY_FILE must be set together".to_owned());
+                None
+            }
+        };
+        match settings.http_redirect_port {
+            Some(_) if tls.is_none() => {
+                errors.push("HTTP_REDIRECT_PORT requires TLS_CERT_FILE and TLS_KEY_FILE".to_owned())
+            }
+            Some(redirect_port) if redirect_port == port => {
+                errors.push("HTTP_REDIRECT_PORT must differ from PORT".to_owned())
+            }
+            _ => {}
+        }
+        if settings.tls_client_ca_file.is_some() && tls.is_none() {
+            errors.push("TLS_CLIENT_CA_FILE requires TLS_CERT_FILE and TLS_KEY_FILE".to_owned());
+        }
+        if settings.client_certs_file.is_some() && settings.tls_client_ca_file.is_none() {
+            errors.push("CLIENT_CERTS_FILE requires TLS_CLIENT_CA_FILE".to_owned());
+        }
+        if cfg!(feature = "ngrok") && tls.is_some() {
+            errors.push("TLS is terminated by ngrok, TLS_CERT_FILE must not be set".to_owned());
+        }
+        let symlink_policy = match settings.symlink_policy.as_deref().map(str::parse) {
+            Some(Ok(policy)) => policy,
+            Some(Err(e)) => {
+                errors.push(format!("SYMLINK_POLICY: {e}"));
+                SymlinkPolicy::default()
+            }
+            None => SymlinkPolicy::default(),
+        };
+        let max_upload_size = settings
+            .max_upload_size
+            .unwrap_or(DEFAULT_MAX_UPLOAD_SIZE_MB);
+        if max_upload_size == 0 {
+            errors.push("MAX_UPLOAD_SIZE must be greater than 0".to_owned());
+        }
+        if !errors.is_empty() {
+            anyhow::bail!("Invalid configuration:\n  {}", errors.join("\n  "));
+        }
+
+        Ok(Self {
+            base_dir,
+            port,
+            session_cookie_secure: settings.session_cookie_secure.unwrap_or(tls.is_some()),
+            #[cfg(not(feature = "ngrok"))]
+            tls,
+            #[cfg(not(feature = "ngrok"))]
+            http_redirect_port: settings.http_redirect_port,
+            volumes_file: settings.volumes_file,
+            volumes: settings.volumes.unwrap_or_default(),
+            sort_locale: settings
+                .sort_locale
+                .unwrap_or(DEFAULT_SORT_LOCALE.to_owned()),
+            show_hidden_files: settings.show_hidden_files.unwrap_or(false),
+            global_ignore_file: settings.global_ignore_file,
+            symlink_policy,
+            read_only: settings.read_only.unwrap_or(false),
+            read_only_paths: settings.read_only_paths.unwrap_or_default(),
+            max_upload_size: max_upload_size.saturating_mul(1024 * 1024),
+            shutdown_grace_period: settings
+                .shutdown_grace_period
+                .unwrap_or(DEFAULT_SHUTDOWN_GRACE_PERIOD_SECONDS),
+            users_file: settings.users_file,
+            client_certs_file: settings.client_certs_file,
+            oidc_config: settings.oidc_config,
+            session_secret: settings.session_secret,
+            acl_file: settings.acl_file,
+            share_secret: settings.share_secret,
+            shares_file: settings
+                .shares_file
+                .unwrap_or(PathBuf::from(DEFAULT_SHARES_FILE)),
+            api_tokens_file: settings
+                .api_tokens_file
+                .unwrap_or(PathBuf::from(DEFAULT_API_TOKENS_FILE)),
+            audit_log: settings.audit_log,
+            audit_log_max_size: settings
+                .audit_log_max_size
+                .unwrap_or(DEFAULT_AUDIT_LOG_MAX_SIZE_MB)
+                .saturating_mul(1024 * 1024),
+            audit_log_max_files: settings
+                .audit_log_max_files
+                .unwrap_or(DEFAULT_AUDIT_LOG_MAX_FILES),
+            metrics_token: settings.metrics_token,
+            trusted_proxies: settings.trusted_proxies.unwrap_or_default(),
+            #[cfg(feature = "ngrok")]
+            ngrok_auth_token: settings.ngrok_auth_token,
+            #[cfg(feature = "ngrok")]
+            ngrok_domain: settings.ngrok_domain,
+            #[cfg(feature = "ngrok")]
+            ngrok_oauth: settings.ngrok_oauth,
+        })
+    }
+}
+
+fn read_file(file: &Path) -> anyhow::Result<Settings> {
+    let contents = std::fs::read_to_string(file).context(format!("Failed to read {:?}", file))?;
+    toml::from_str(&contents).context(format!("Invalid configuration file {:?}", file))
+}
+
+/// Settings of `lower` overridden by the ones set in `higher`.
+fn merge(lower: Settings, higher: Settings) -> anyhow::Result<Settings> {
+    // unset settings are left out of the tables
+    let mut settings = toml::Table::try_from(lower)?;
+    settings.extend(toml::Table::try_from(higher)?);
+    Ok(settings.try_into()?)
+}
+
+#[cfg(test)]
+mod test {
+    use clap::Parser;
+
+    use super::{merge, read_file, Cli, Config, Settings};
+
+    #[test]
+    fn test_precedence() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("my-drive.toml");
+        std::fs::write(
+            &file,
+            format!(
+                "base_dir = {:?}\nport = 9000\nread_only_paths = [\"/archive\"]\n\n[volumes.media]\npath = {:?}\n",
+                dir.path(),
+                dir.path()
+            ),
+        )
+        .unwrap();
+        let cli = Cli::try_parse_from(["my-drive", "--port", "9001", "--read-only"]).unwrap();
+        let config = Config::new(merge(read_file(&file).unwrap(), cli.settings).unwrap()).unwrap();
+        assert_eq!(config.base_dir, dir.path());
+        assert_eq!(config.port, 9001);
+        assert!(config.read_only);
+        assert_eq!(config.read_only_paths, ["/archive"]);
+        assert!(config.volumes.contains_key("media"));
+        assert_eq!(config.max_upload_size, 128 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_validation_errors() {
+        let cli = Cli::try_parse_from([
+            "my-drive",
+            "--base-dir",
+            "/missing",
+            "--session-secret",
+            "short",
+            "--symlink-policy",
+            "follow",
+            "--tls-cert-file",
+            "/missing.pem",
+            "--http-redirect-port",
+            "80",
+            "--client-certs-file",
+            "/missing.toml",
+        ])
+        .unwrap();
+        let error = Config::new(cli.settings).unwrap_err().to_string();
+        assert!(error.contains("BASE_DIR \"/missing\" does not exist or is not a directory"));
+        assert!(error.contains("SESSION_SECRET must be at least 32 bytes long"));
+        assert!(error.contains("SYMLINK_POLICY: Unknown symlink policy"));
+        assert!(error.contains("TLS_CERT_FILE and TLS_KEY_FILE must be set together"));
+        assert!(error.contains("HTTP_REDIRECT_PORT requires TLS_CERT_FILE and TLS_KEY_FILE"));
+        assert!(error.contains("CLIENT_CERTS_FILE requires TLS_CLIENT_CA_FILE"));
+
+        let error = Config::new(Settings::default()).unwrap_err().to_string();
+        assert!(error.contains("BASE_DIR must be set"));
+    }
+}
@@ -1,14 +1,44 @@
+use std::sync::Arc;
+
 use tracing::info;
 
-pub(crate) async fn run_server() -> anyhow::Result<()> {
-    let local_address = (
-        "0.0.0.0",
-        dotenv::var("PORT")
-            .unwrap_or("8080".to_owned())
-            .parse::<u16>()
-            .unwrap(),
+use crate::{config::Config, server::Shutdown, tls};
+
+pub(crate) async fn run_server(config: Config) -> anyhow::Result<()> {
+    let shutdown = Shutdown::default();
+    shutdown.listen_for_signals();
+    let local_address = ("0.0.0.0", config.port);
+    let tls = match &config.tls {
+        Some(files) => {
+            let certificate = Arc::new(tls::ReloadingCertificate::new(
+                &files.cert_file,
+                &files.key_file,
+            )?);
+            certificate.watch();
+            Some(tls::server_config(
+                certificate,
+                files.client_ca_file.as_deref(),
+            )?)
+        }
+        None => None,
+    };
+    if let Some(redirect_port) = config.http_redirect_port {
+        let redirect_address = ("0.0.0.0", redirect_port);
+        info!("Redirecting HTTP at {:?} to HTTPS", redirect_address);
+        let redirect = tls::redirect_server(redirect_address, config.port)?;
+        let handle = redirect.handle();
+        actix_web::rt::spawn(redirect);
+        let shutdown = shutdown.clone();
+        actix_web::rt::spawn(async move {
+            shutdown.requested().await;
+            handle.stop(true).await;
+        });
+    }
+    info!(
+        "Starting {} server at {:?}",
+        if tls.is_some() { "HTTPS" } else { "HTTP" },
+        local_address
     );
-    info!("Starting server at {:?}", local_address);
-    let server = crate::webservices::start_http_server(&local_address);
+    let server = crate::webservices::start_http_server(&local_address, tls, config, shutdown);
     server.await
 }
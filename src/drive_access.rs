@@ -1,12 +1,23 @@
 use std::{
     os::unix::fs::MetadataExt,
     path::{Path, PathBuf},
+    time::Instant,
 };
 
 use actix_multipart::form::tempfile::TempFile;
 use anyhow::{Context, Ok, Result};
 use glob::MatchOptions;
 
+pub(crate) mod access_control;
+pub(crate) mod ordering;
+pub(crate) mod path_resolution;
+pub(crate) mod read_only;
+pub(crate) mod visibility;
+pub(crate) mod volumes;
+
+pub(crate) use access_control::{Permissions, UserAccess};
+pub(crate) use visibility::Visibility;
+
 #[derive(Debug, serde::Serialize)]
 pub(crate) struct FileType {
     pub mime: String,
@@ -65,6 +76,7 @@ pub(crate) struct FileInfo {
     pub is_dir: bool,
     pub file_type: Option<FileType>,
     pub metadata: Option<FileMetadata>,
+    pub permissions: Permissions,
 }
 
 #[derive(Debug, serde::Serialize)]
@@ -89,10 +101,16 @@ impl PartialOrd for FileInfo {
 }
 impl Ord for FileInfo {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        match self.is_dir.cmp(&other.is_dir) {
-            std::cmp::Ordering::Equal => self.name.cmp(&other.name),
-            ord => ord,
-        }
+        compare_entries((self.is_dir, &self.name), (other.is_dir, &other.name))
+    }
+}
+
+/// Orders `(is_dir, name)` pairs of entries in a directory: directories go first, then entries
+/// are ordered by name using natural, locale-aware collation.
+pub(crate) fn compare_entries(left: (bool, &str), right: (bool, &str)) -> std::cmp::Ordering {
+    match right.0.cmp(&left.0) {
+        std::cmp::Ordering::Equal => ordering::compare_names(left.1, right.1),
+        ord => ord,
     }
 }
 
@@ -101,6 +119,7 @@ pub(crate) struct FilesResult {
     pub files: Vec<FileInfo>,
     pub path: String,
     pub parent: Option<String>,
+    pub permissions: Permissions,
 }
 
 fn to_file_metadata(metadata: std::fs::Metadata) -> FileMetadata {
@@ -117,101 +136,237 @@ fn to_file_metadata(metadata: std::fs::Metadata) -> FileMetadata {
     }
 }
 
-#[tracing::instrument]
-pub(crate) async fn list_files(dir: &PathBuf, base_dir: &PathBuf) -> Result<FilesResult> {
-
+#[tracing::instrument(skip(access))]
+pub(crate) async fn list_files(dir: &PathBuf, access: &UserAccess) -> Result<FilesResult> {
+    let started = Instant::now();
+    let volumes = access.volumes();
+    let mut visibility = Visibility::new(volumes);
     let mut files = dir
         .read_dir()
         .context(format!("Reading {:?}", dir))?
         .filter_map(|f| {
-            f.ok().map(|f| {
-                let is_dir = f.file_type().map(|t| t.is_dir()).unwrap_or(false);
-                FileInfo {
-                    name: f.file_name().into_string().unwrap(),
-                    is_dir,
-                    file_type: if is_dir {
-                        None
-                    } else {
-                        Some((f.path().as_path()).try_into().unwrap_or_default())
-                    },
-                    metadata: f.metadata().ok().map(to_file_metadata),
-                }
+            let f = f.ok()?;
+            let is_dir = f.file_type().map(|t| t.is_dir()).unwrap_or(false);
+            if visibility.is_hidden(&f.path(), is_dir) || !access.is_listable(&f.path(), is_dir) {
+                return None;
+            }
+            Some(FileInfo {
+                name: f.file_name().into_string().unwrap(),
+                is_dir,
+                file_type: if is_dir {
+                    None
+                } else {
+                    Some((f.path().as_path()).try_into().unwrap_or_default())
+                },
+                metadata: f.metadata().ok().map(to_file_metadata),
+                permissions: access.permissions(&f.path()),
             })
         })
-        .filter(|f| !f.name.starts_with('.')) // ignore hidden files
         .collect::<Vec<_>>();
+    if dir == volumes.root() {
+        // volumes are shown as folders of the root
+        files.extend(
+            volumes
+                .volumes()
+                .iter()
+                .filter(|volume| {
+                    !visibility.is_hidden(&volume.path, true)
+                        && access.is_listable(&volume.path, true)
+                })
+                .map(|volume| file_info(&volume.path, access)),
+        );
+    }
 
     files.sort();
-    files.reverse();
 
+    let relative = volumes
+        .relative(dir)
+        .context(format!("{:?} is not a part of the drive", dir))?;
+    crate::telemetry::instruments().record_listing(started.elapsed());
     Ok(FilesResult {
         files,
-        path: relative_path(dir, base_dir)?,
-        parent: dir
-            .parent()
-            .into_iter()
-            .filter_map(|p| relative_path(p, base_dir).ok())
-            .next(),
+        path: relative_path(&relative),
+        parent: relative.parent().map(relative_path),
+        permissions: access.permissions(dir),
     })
 }
 
-fn relative_path(path: &Path, base_dir: &PathBuf) -> Result<String> {
-    let path = path.strip_prefix(base_dir)?.as_os_str().to_str().unwrap();
+fn relative_path(relative: &Path) -> String {
+    let path = relative.as_os_str().to_str().unwrap();
     if path.is_empty() {
-        return Ok("".to_owned());
+        return "".to_owned();
     }
-    Ok(format!("/{}", path))
+    format!("/{}", path)
 }
 
-#[tracing::instrument]
-pub(crate) fn query_files(query: &str, base_dir: &Path) -> Result<Vec<FileInfo>> {
-    use glob::glob_with;
-    let paths = glob_with(
-        &format!("{}/**/{}*", &base_dir.as_os_str().to_str().unwrap(), query),
-        MatchOptions {
-            case_sensitive: false,
-            ..Default::default()
-        },
-    )?;
-
-    let mut files = paths
-        .filter_map(|p| p.ok())
-        .map(|path| {
-            let is_dir = path.is_dir();
-            FileInfo {
-                name: path.file_name().unwrap().to_str().unwrap().to_owned(),
-                is_dir,
-                file_type: if is_dir {
-                    None
-                } else {
-                    Some((path.as_path()).try_into().unwrap_or_default())
-                },
-                metadata: path.metadata().ok().map(to_file_metadata),
-            }
-        })
-        .filter(|f| !f.name.starts_with('.')) // ignore hidden files
+#[tracing::instrument(skip(access))]
+pub(crate) fn query_files(query: &str, access: &UserAccess) -> Result<Vec<FileInfo>> {
+    let mut files = search_paths(query, access)?
+        .iter()
+        .map(|path| file_info(path, access))
         .collect::<Vec<_>>();
     files.sort();
-    files.reverse();
     Ok(files)
 }
 
+/// Paths of the visible and readable entries whose names start with `query` (ignoring case).
+#[tracing::instrument(skip(access))]
+pub(crate) fn search_paths(query: &str, access: &UserAccess) -> Result<Vec<PathBuf>> {
+    use glob::glob_with;
+    let started = Instant::now();
+    let volumes = access.volumes();
+    let options = MatchOptions {
+        case_sensitive: false,
+        ..Default::default()
+    };
+    let mut paths = Vec::new();
+    let bases = std::iter::once(volumes.root())
+        .chain(volumes.volumes().iter().map(|volume| volume.path.as_path()));
+    for base in bases {
+        let base = glob::Pattern::escape(base.as_os_str().to_str().unwrap());
+        paths
+            .extend(glob_with(&format!("{}/**/{}*", base, query), options)?.filter_map(|p| p.ok()));
+    }
+    // volumes are entries of the root, too
+    paths.extend(
+        volumes
+            .volumes()
+            .iter()
+            .filter(|volume| {
+                volume
+                    .name
+                    .to_lowercase()
+                    .starts_with(&query.to_lowercase())
+            })
+            .map(|volume| volume.path.clone()),
+    );
+
+    let mut visibility = Visibility::new(volumes);
+    let paths = paths
+        .into_iter()
+        .filter(|path| !visibility.is_hidden(path, path.is_dir()) && access.permissions(path).read)
+        .collect();
+    crate::telemetry::instruments().record_search(started.elapsed());
+    Ok(paths)
+}
+
+/// Describes the entry at `path`, which must exist.
+pub(crate) fn file_info(path: &Path, access: &UserAccess) -> FileInfo {
+    let is_dir = path.is_dir();
+    FileInfo {
+        name: match access.volumes().volume_of(path) {
+            Some(volume) if volume.path == path => volume.name.clone(),
+            _ => path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        },
+        is_dir,
+        file_type: if is_dir {
+            None
+        } else {
+            Some(path.try_into().unwrap_or_default())
+        },
+        metadata: path.metadata().ok().map(to_file_metadata),
+        permissions: access.permissions(path),
+    }
+}
+
+/// What happens when an uploaded file has the name of an existing entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OnConflict {
+    /// A number is added to the name of the new file, e.g. "report (1).pdf".
+    Rename,
+    /// The existing file is replaced.
+    Replace,
+    /// The file is not saved.
+    Fail,
+}
+
+/// Saves uploaded files in `dir`, optionally prefixing their names with `name_prefix`.
+///
+/// Files larger than `max_size` bytes are rejected. Existing files are handled according to `on_conflict`.
+/// Returns the names the files were saved with.
 #[tracing::instrument]
-pub(crate) fn save_files(
+pub(crate) fn save_files<'a>(
     files: Vec<TempFile>,
-    dir: &Path,
-) -> impl Iterator<Item = (String, Result<std::fs::File>)> + '_ {
+    dir: &'a Path,
+    name_prefix: Option<&'a str>,
+    max_size: Option<u64>,
+    on_conflict: OnConflict,
+) -> impl Iterator<Item = (String, Result<std::fs::File>)> + 'a {
     files
         .into_iter()
         .filter(|file| file.file_name.is_some())
-        .map(|file| {
-            let name = file.file_name.unwrap();
-            let path = dir.join(&name);
-            let persist_result = file.file.persist(path).context("Persisting file");
-            (name, persist_result)
+        .map(move |file| {
+            let name = match name_prefix {
+                Some(prefix) => format!("{} - {}", prefix, file.file_name.unwrap()),
+                None => file.file_name.unwrap(),
+            };
+            if !path_resolution::is_plain_file_name(&name) {
+                return (name, Err(anyhow::anyhow!("Invalid file name")));
+            }
+            if name == visibility::IGNORE_FILE_NAME {
+                return (name, Err(anyhow::anyhow!("Reserved file name")));
+            }
+            if max_size.is_some_and(|max_size| file.size as u64 > max_size) {
+                return (name, Err(anyhow::anyhow!("File is too large")));
+            }
+            let target = dir.join(&name);
+            let saved = match on_conflict {
+                OnConflict::Rename => persist_without_overwriting(file.file, dir, &name),
+                OnConflict::Replace => file
+                    .file
+                    .persist(&target)
+                    .map(|file| (name.clone(), file))
+                    .map_err(|e| anyhow::Error::new(e.error).context("Persisting file")),
+                OnConflict::Fail => match file.file.persist_noclobber(&target) {
+                    Result::Ok(file) => Ok((name.clone(), file)),
+                    Err(e) if e.error.kind() == std::io::ErrorKind::AlreadyExists => {
+                        Err(anyhow::anyhow!("A file with this name exists already"))
+                    }
+                    Err(e) => Err(anyhow::Error::new(e.error).context("Persisting file")),
+                },
+            };
+            match saved {
+                Result::Ok((saved_name, file)) => (saved_name, Ok(file)),
+                Err(e) => (name, Err(e)),
+            }
         })
 }
 
+/// Maximum number of alternative names tried for a file name already in use.
+const MAX_NAME_CONFLICTS: usize = 1000;
+
+fn persist_without_overwriting(
+    mut file: tempfile::NamedTempFile,
+    dir: &Path,
+    name: &str,
+) -> Result<(String, std::fs::File)> {
+    for attempt in 0..MAX_NAME_CONFLICTS {
+        let candidate = conflict_free_name(name, attempt);
+        match file.persist_noclobber(dir.join(&candidate)) {
+            Result::Ok(persisted) => return Ok((candidate, persisted)),
+            Err(e) if e.error.kind() == std::io::ErrorKind::AlreadyExists => file = e.file,
+            Err(e) => return Err(e.error).context("Persisting file"),
+        }
+    }
+    Err(anyhow::anyhow!("Too many files named {}", name))
+}
+
+/// Name for the `attempt`-th conflict of `name`, e.g. "report (2).pdf".
+fn conflict_free_name(name: &str, attempt: usize) -> String {
+    if attempt == 0 {
+        return name.to_owned();
+    }
+    match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => {
+            format!("{} ({}).{}", stem, attempt, extension)
+        }
+        _ => format!("{} ({})", name, attempt),
+    }
+}
+
 #[tracing::instrument]
 pub(crate) fn delete_file_or_directory(path: &PathBuf) -> Result<()> {
     if path.is_dir() {
@@ -221,7 +376,141 @@ pub(crate) fn delete_file_or_directory(path: &PathBuf) -> Result<()> {
     }
 }
 
+/// Deletes a directory only if it is empty, hidden entries included.
+#[tracing::instrument]
+pub(crate) fn delete_empty_directory(path: &Path) -> Result<()> {
+    std::fs::remove_dir(path).context(format!("Deleting directory {:?}", path))
+}
+
 #[tracing::instrument]
 pub(crate) fn create_dir(new_dir_path: &PathBuf) -> Result<()> {
     std::fs::create_dir(new_dir_path).context(format!("Creating directory {:?}", new_dir_path))
 }
+
+/// Moves a file or directory, replacing an existing file at `to`.
+#[tracing::instrument]
+pub(crate) fn move_entry(from: &Path, to: &Path) -> Result<()> {
+    std::fs::rename(from, to).context(format!("Moving {:?} to {:?}", from, to))
+}
+
+/// Copies a file, replacing an existing file at `to`.
+#[tracing::instrument]
+pub(crate) fn copy_file(from: &Path, to: &Path) -> Result<u64> {
+    std::fs::copy(from, to).context(format!("Copying {:?} to {:?}", from, to))
+}
+
+/// Moves a file or directory to another volume, which may be on another disk, by copying it
+/// and deleting the original. `to` must not exist; a partial copy is removed again.
+/// Returns the number of bytes copied.
+#[tracing::instrument]
+pub(crate) fn move_between_volumes(from: &Path, to: &Path) -> Result<u64> {
+    if to.symlink_metadata().is_ok() {
+        anyhow::bail!("{:?} exists already", to);
+    }
+    let result = copy_entry(from, to);
+    if result.is_err() && to.symlink_metadata().is_ok() {
+        let _ = delete_file_or_directory(&to.to_path_buf());
+    }
+    let bytes = result?;
+    delete_file_or_directory(&from.to_path_buf())?;
+    Ok(bytes)
+}
+
+/// Copies a file or a directory with everything in it, refusing symbolic links.
+fn copy_entry(from: &Path, to: &Path) -> Result<u64> {
+    let metadata = from
+        .symlink_metadata()
+        .context(format!("Reading {:?}", from))?;
+    if metadata.is_symlink() {
+        anyhow::bail!("Symbolic link {:?} cannot be copied", from);
+    }
+    if !metadata.is_dir() {
+        return copy_file(from, to);
+    }
+    std::fs::create_dir(to).context(format!("Creating directory {:?}", to))?;
+    let mut bytes = 0;
+    for entry in std::fs::read_dir(from).context(format!("Reading directory {:?}", from))? {
+        let name = entry?.file_name();
+        bytes += copy_entry(&from.join(&name), &to.join(&name))?;
+    }
+    Ok(bytes)
+}
+
+/// Size of the largest file at `path`, or inside of it if it is a directory.
+pub(crate) fn largest_file_size(path: &Path) -> u64 {
+    let Some(metadata) = path.symlink_metadata().ok() else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    std::fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| largest_file_size(&entry.path()))
+        .max()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use super::{conflict_free_name, move_between_volumes, persist_without_overwriting};
+
+    #[test]
+    fn test_conflict_free_names() {
+        assert_eq!(conflict_free_name("report.pdf", 0), "report.pdf");
+        assert_eq!(conflict_free_name("report.pdf", 2), "report (2).pdf");
+        assert_eq!(conflict_free_name("README", 1), "README (1)");
+        assert_eq!(conflict_free_name(".env", 1), ".env (1)");
+    }
+
+    #[test]
+    fn test_uploads_do_not_overwrite_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "original").unwrap();
+        let mut upload = tempfile::NamedTempFile::new_in(dir.path()).unwrap();
+        upload.write_all(b"uploaded").unwrap();
+
+        let (name, _) = persist_without_overwriting(upload, dir.path(), "a.txt").unwrap();
+        assert_eq!(name, "a (1).txt");
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("a.txt")).unwrap(),
+            "original"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("a (1).txt")).unwrap(),
+            "uploaded"
+        );
+    }
+
+    #[test]
+    fn test_move_between_volumes() {
+        let (root, disk) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let from = root.path().join("photos");
+        std::fs::create_dir_all(from.join("2020")).unwrap();
+        std::fs::write(from.join("a.jpg"), "image").unwrap();
+        std::fs::write(from.join("2020/b.jpg"), "other image").unwrap();
+
+        let to = disk.path().join("photos");
+        assert_eq!(move_between_volumes(&from, &to).unwrap(), 16);
+        assert!(!from.exists());
+        assert_eq!(std::fs::read_to_string(to.join("a.jpg")).unwrap(), "image");
+        assert_eq!(
+            std::fs::read_to_string(to.join("2020/b.jpg")).unwrap(),
+            "other image"
+        );
+
+        // nothing is deleted when the copy fails
+        std::fs::write(root.path().join("notes.txt"), "notes").unwrap();
+        assert!(move_between_volumes(&root.path().join("notes.txt"), &to).is_err());
+        assert!(root.path().join("notes.txt").exists());
+        std::os::unix::fs::symlink(root.path().join("notes.txt"), to.join("link")).unwrap();
+        let back = root.path().join("photos");
+        assert!(move_between_volumes(&to, &back).is_err());
+        assert!(to.join("a.jpg").exists());
+        assert!(!back.exists());
+    }
+}
@@ -0,0 +1,463 @@
+use std::{
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use super::{read_only::ReadOnlyPaths, volumes::Volumes};
+
+/// Principal matching every user (including anonymous ones when authentication is disabled).
+const ANY_PRINCIPAL: &str = "*";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Permission {
+    Read,
+    Write,
+    Delete,
+    Share,
+}
+
+/// Operations allowed on a drive entry.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub(crate) struct Permissions {
+    pub(crate) read: bool,
+    pub(crate) write: bool,
+    pub(crate) delete: bool,
+    pub(crate) share: bool,
+}
+
+impl Permissions {
+    pub(crate) fn all() -> Self {
+        Self {
+            read: true,
+            write: true,
+            delete: true,
+            share: true,
+        }
+    }
+
+    pub(crate) fn read_only() -> Self {
+        Self {
+            read: true,
+            ..Default::default()
+        }
+    }
+
+    /// Permissions granted by both `self` and `other`.
+    pub(crate) fn intersection(&self, other: &Permissions) -> Self {
+        Self {
+            read: self.read && other.read,
+            write: self.write && other.write,
+            delete: self.delete && other.delete,
+            share: self.share && other.share,
+        }
+    }
+
+    pub(crate) fn allows(&self, permission: Permission) -> bool {
+        match permission {
+            Permission::Read => self.read,
+            Permission::Write => self.write,
+            Permission::Delete => self.delete,
+            Permission::Share => self.share,
+        }
+    }
+}
+
+impl FromIterator<Permission> for Permissions {
+    fn from_iter<T: IntoIterator<Item = Permission>>(iter: T) -> Self {
+        iter.into_iter()
+            .fold(Permissions::default(), |mut permissions, permission| {
+                match permission {
+                    Permission::Read => permissions.read = true,
+                    Permission::Write => permissions.write = true,
+                    Permission::Delete => permissions.delete = true,
+                    Permission::Share => permissions.share = true,
+                }
+                permissions
+            })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AclEntry {
+    /// User name or `*` for all users.
+    principal: String,
+    /// Path prefix (relative to the base directory) the entry applies to.
+    path: PathBuf,
+    permissions: Vec<Permission>,
+}
+
+impl AclEntry {
+    fn applies_to(&self, principal: Option<&str>, path: &Path) -> bool {
+        (self.principal == ANY_PRINCIPAL || Some(self.principal.as_str()) == principal)
+            && path.starts_with(&self.path)
+    }
+
+    /// More specific entries (longer paths, then named principals) take precedence.
+    fn specificity(&self) -> (usize, bool) {
+        (
+            self.path.components().count(),
+            self.principal != ANY_PRINCIPAL,
+        )
+    }
+}
+
+/// Access control list read from the file configured with `ACL_FILE`.
+///
+/// The file is a TOML document with a list of entries:
+/// ```toml
+/// [[entries]]
+/// principal = "*"
+/// path = "/"
+/// permissions = ["read"]
+///
+/// [[entries]]
+/// principal = "alice"
+/// path = "/photos"
+/// permissions = ["read", "write", "delete", "share"]
+/// ```
+/// Permissions for a path come from the most specific matching entry; entries with an empty
+/// list of permissions make paths invisible. Paths not covered by any entry are not accessible.
+#[derive(Debug, Deserialize)]
+pub(crate) struct AccessControlList {
+    entries: Vec<AclEntry>,
+}
+
+impl AccessControlList {
+    pub(crate) fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents =
+            std::fs::read_to_string(path).context(format!("Failed to read {:?}", path))?;
+        let mut acl = toml::from_str::<AccessControlList>(&contents)
+            .context(format!("Failed to parse {:?}", path))?;
+        for entry in acl.entries.iter_mut() {
+            // entries use drive paths, e.g. "/photos", which are matched as relative ones
+            entry.path = entry
+                .path
+                .components()
+                .filter(|c| !matches!(c, Component::RootDir))
+                .collect();
+        }
+        Ok(acl)
+    }
+
+    fn permissions(&self, principal: Option<&str>, path: &Path) -> Permissions {
+        self.entries
+            .iter()
+            .filter(|entry| entry.applies_to(principal, path))
+            .max_by_key(|entry| entry.specificity())
+            .map(|entry| entry.permissions.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Checks whether a path below `path` cannot be deleted, so neither can `path` with
+    /// its contents.
+    fn has_undeletable_descendants(&self, principal: Option<&str>, path: &Path) -> bool {
+        self.entries.iter().any(|entry| {
+            entry.path.starts_with(path)
+                && entry.path != path
+                && (entry.principal == ANY_PRINCIPAL
+                    || Some(entry.principal.as_str()) == principal)
+                && !self.permissions(principal, &entry.path).delete
+        })
+    }
+
+    /// Checks whether there is any readable path below `path`, so it can be traversed
+    /// to get there.
+    fn has_readable_descendants(&self, principal: Option<&str>, path: &Path) -> bool {
+        self.entries.iter().any(|entry| {
+            entry.path.starts_with(path)
+                && entry.path != path
+                && (entry.principal == ANY_PRINCIPAL
+                    || Some(entry.principal.as_str()) == principal)
+                && self.permissions(principal, &entry.path).read
+        })
+    }
+}
+
+/// Outcome of checking whether the user may perform an operation on an entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AccessCheck {
+    Allowed,
+    /// The entry is visible to the user, who may not perform the operation.
+    Forbidden,
+    /// The entry can neither be read nor traversed, so its existence must not be revealed.
+    Hidden,
+}
+
+/// Access rights of the user making a request.
+#[derive(Debug, Clone)]
+pub(crate) struct UserAccess {
+    acl: Option<Arc<AccessControlList>>,
+    principal: Option<String>,
+    is_admin: bool,
+    volumes: Arc<Volumes>,
+    /// Upper bound of the permissions, e.g. for read-only views.
+    mask: Permissions,
+    read_only: Option<Arc<ReadOnlyPaths>>,
+}
+
+impl UserAccess {
+    pub(crate) fn new(
+        acl: Option<Arc<AccessControlList>>,
+        principal: Option<String>,
+        is_admin: bool,
+        volumes: Arc<Volumes>,
+    ) -> Self {
+        Self {
+            acl,
+            principal,
+            is_admin,
+            volumes,
+            mask: Permissions::all(),
+            read_only: None,
+        }
+    }
+
+    /// Prevents modifications of the read-only parts of the drive.
+    pub(crate) fn with_read_only(mut self, read_only: Arc<ReadOnlyPaths>) -> Self {
+        self.read_only = Some(read_only);
+        self
+    }
+
+    pub(crate) fn volumes(&self) -> &Volumes {
+        &self.volumes
+    }
+
+    /// Limits the permissions of every entry to `mask`.
+    pub(crate) fn restricted(mut self, mask: Permissions) -> Self {
+        self.mask = self.mask.intersection(&mask);
+        self
+    }
+
+    /// Permissions for the entry at `path` (an absolute path inside the drive's directories).
+    pub(crate) fn permissions(&self, path: &Path) -> Permissions {
+        let Some(relative) = self.volumes.relative(path) else {
+            return Permissions::default();
+        };
+        if self.volumes.is_shadowed(path) {
+            return Permissions::default();
+        }
+        let relative = relative.as_path();
+        let mut permissions = match &self.acl {
+            Some(_) if self.is_admin => Permissions::all(),
+            Some(acl) => {
+                let mut permissions = acl.permissions(self.principal.as_deref(), relative);
+                // deleting the entry would delete the protected ones too
+                if permissions.delete
+                    && acl.has_undeletable_descendants(self.principal.as_deref(), relative)
+                {
+                    permissions.delete = false;
+                }
+                permissions
+            }
+            // without access control list everything is allowed
+            None => Permissions::all(),
+        };
+        if let Some(read_only) = &self.read_only {
+            if read_only.is_read_only(relative) {
+                permissions.write = false;
+                permissions.delete = false;
+            } else if read_only.contains_read_only(relative) {
+                // deleting the entry would delete read-only ones too
+                permissions.delete = false;
+            }
+        }
+        if self.volumes.is_volume(path) {
+            permissions.delete = false;
+        }
+        permissions.intersection(&self.mask)
+    }
+
+    /// Checks whether the user may perform an operation requiring `required` on `path`.
+    pub(crate) fn check(&self, path: &Path, required: Permission) -> AccessCheck {
+        if self.permissions(path).allows(required) {
+            AccessCheck::Allowed
+        } else if self.is_listable(path, path.is_dir()) {
+            AccessCheck::Forbidden
+        } else {
+            // e.g. upload-only access must not reveal which entries exist
+            AccessCheck::Hidden
+        }
+    }
+
+    /// Checks whether the entry at `path` should be shown in listings, i.e. it is readable
+    /// or it is a directory leading to readable entries.
+    pub(crate) fn is_listable(&self, path: &Path, is_dir: bool) -> bool {
+        self.permissions(path).read || (is_dir && self.is_traversable(path))
+    }
+
+    fn is_traversable(&self, path: &Path) -> bool {
+        let (Some(acl), Some(relative)) = (&self.acl, self.volumes.relative(path)) else {
+            return false;
+        };
+        acl.has_readable_descendants(self.principal.as_deref(), &relative)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::Path, sync::Arc};
+
+    use super::{AccessCheck, AccessControlList, Permission, Permissions, UserAccess};
+    use crate::drive_access::volumes::Volumes;
+
+    fn acl() -> Arc<AccessControlList> {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(
+            file.path(),
+            r#"
+            [[entries]]
+            principal = "*"
+            path = "/"
+            permissions = ["read"]
+
+            [[entries]]
+            principal = "alice"
+            path = "/"
+            permissions = ["read", "write", "delete", "share"]
+
+            [[entries]]
+            principal = "*"
+            path = "/private"
+            permissions = []
+
+            [[entries]]
+            principal = "bob"
+            path = "/private/bob"
+            permissions = ["read", "write"]
+
+            [[entries]]
+            principal = "alice"
+            path = "/archive"
+            permissions = ["read"]
+            "#,
+        )
+        .unwrap();
+        Arc::new(AccessControlList::load(file.path()).unwrap())
+    }
+
+    fn access(principal: Option<&str>, is_admin: bool) -> UserAccess {
+        UserAccess::new(
+            Some(acl()),
+            principal.map(|p| p.to_owned()),
+            is_admin,
+            Arc::new(Volumes::single(Path::new("/drive"))),
+        )
+    }
+
+    fn read_only() -> Permissions {
+        Permissions {
+            read: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_most_specific_entry_wins() {
+        let alice = access(Some("alice"), false);
+        assert_eq!(
+            alice.permissions(Path::new("/drive/photos/a.jpg")),
+            Permissions::all()
+        );
+        assert_eq!(alice.permissions(Path::new("/drive/archive/2020")), read_only());
+        // "/archive" entry does not cover "/archives"
+        assert_eq!(
+            alice.permissions(Path::new("/drive/archives")),
+            Permissions::all()
+        );
+        // deeper entry for all users beats user's entry for a parent
+        assert_eq!(
+            alice.permissions(Path::new("/drive/private/a.txt")),
+            Permissions::default()
+        );
+    }
+
+    #[test]
+    fn test_protected_descendants() {
+        let alice = access(Some("alice"), false);
+        // "/archive" and "/private" cannot be deleted by alice
+        assert_eq!(
+            alice.permissions(Path::new("/drive")),
+            Permissions {
+                delete: false,
+                ..Permissions::all()
+            }
+        );
+        assert!(alice.permissions(Path::new("/drive/photos")).delete);
+        assert!(!alice.permissions(Path::new("/drive/archive")).delete);
+        let admin = access(Some("dave"), true);
+        assert!(admin.permissions(Path::new("/drive")).delete);
+    }
+
+    #[test]
+    fn test_invisible_paths() {
+        let carol = access(Some("carol"), false);
+        assert_eq!(carol.permissions(Path::new("/drive/photos")), read_only());
+        assert!(!carol.is_listable(Path::new("/drive/private"), true));
+        assert!(!carol.is_listable(Path::new("/drive/private/bob"), true));
+    }
+
+    #[test]
+    fn test_traversal_to_readable_paths() {
+        let bob = access(Some("bob"), false);
+        assert!(!bob.permissions(Path::new("/drive/private")).read);
+        assert!(bob.is_listable(Path::new("/drive/private"), true));
+        assert!(!bob.is_listable(Path::new("/drive/private/other.txt"), false));
+        assert_eq!(
+            bob.permissions(Path::new("/drive/private/bob/notes.txt")),
+            Permissions {
+                read: true,
+                write: true,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        let bob = access(Some("bob"), false);
+        let notes = Path::new("/drive/private/bob/notes.txt");
+        assert_eq!(bob.check(notes, Permission::Write), AccessCheck::Allowed);
+        assert_eq!(bob.check(notes, Permission::Delete), AccessCheck::Forbidden);
+        assert_eq!(
+            bob.check(Path::new("/drive/private/other.txt"), Permission::Read),
+            AccessCheck::Hidden
+        );
+    }
+
+    #[test]
+    fn test_admins_and_anonymous_users() {
+        let admin = access(Some("dave"), true);
+        assert_eq!(
+            admin.permissions(Path::new("/drive/private")),
+            Permissions::all()
+        );
+        let anonymous = access(None, false);
+        assert_eq!(
+            anonymous.permissions(Path::new("/drive/photos")),
+            read_only()
+        );
+
+        let without_acl = UserAccess::new(
+            None,
+            None,
+            false,
+            Arc::new(Volumes::single(Path::new("/drive"))),
+        );
+        assert_eq!(
+            without_acl.permissions(Path::new("/drive/private")),
+            Permissions::all()
+        );
+    }
+
+    #[test]
+    fn test_restricted_access() {
+        let admin = access(Some("dave"), true).restricted(read_only());
+        assert_eq!(admin.permissions(Path::new("/drive/photos")), read_only());
+        let carol = access(Some("carol"), false).restricted(Permissions::all());
+        assert!(!carol.is_listable(Path::new("/drive/private"), true));
+    }
+}
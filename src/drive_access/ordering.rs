@@ -0,0 +1,92 @@
+use std::{cmp::Ordering, sync::OnceLock};
+
+use icu_collator::{Collator, CollatorOptions, Numeric, Strength};
+use icu_locid::Locale;
+use tracing::warn;
+
+const DEFAULT_SORT_LOCALE: &str = "en";
+
+static COLLATOR: OnceLock<Collator> = OnceLock::new();
+
+/// Sets the locale names are sorted for (`SORT_LOCALE`), once at startup.
+pub(crate) fn configure(locale: &str) {
+    let collator = create_collator(locale).unwrap_or_else(|e| {
+        warn!(
+            "Invalid SORT_LOCALE {:?}, using {DEFAULT_SORT_LOCALE}: {:?}",
+            locale, e
+        );
+        default_collator()
+    });
+    if COLLATOR.set(collator).is_err() {
+        warn!("Sort locale is configured already");
+    }
+}
+
+/// Compares file names using natural numeric ordering (`IMG_2.jpg` before `IMG_10.jpg`)
+/// and case-insensitive collation for the configured locale.
+pub(crate) fn compare_names(left: &str, right: &str) -> Ordering {
+    let collator = COLLATOR.get_or_init(default_collator);
+    compare_with(collator, left, right)
+}
+
+fn default_collator() -> Collator {
+    create_collator(DEFAULT_SORT_LOCALE).expect("default collator to be available")
+}
+
+fn create_collator(locale: &str) -> anyhow::Result<Collator> {
+    let locale: Locale = locale.parse()?;
+    let mut options = CollatorOptions::new();
+    // secondary strength ignores case differences but keeps accents significant
+    options.strength = Some(Strength::Secondary);
+    options.numeric = Some(Numeric::On);
+    Ok(Collator::try_new(&(&locale).into(), options)?)
+}
+
+fn compare_with(collator: &Collator, left: &str, right: &str) -> Ordering {
+    // names equal for the collator (e.g. differing only by case) still need a stable order
+    collator.compare(left, right).then_with(|| left.cmp(right))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{compare_with, create_collator};
+
+    fn sorted(locale: &str, names: &[&str]) -> Vec<String> {
+        let collator = create_collator(locale).unwrap();
+        let mut names = names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        names.sort_by(|l, r| compare_with(&collator, l, r));
+        names
+    }
+
+    #[test]
+    fn test_numeric_ordering() {
+        assert_eq!(
+            sorted("en", &["IMG_10.jpg", "IMG_2.jpg", "IMG_1.jpg"]),
+            vec!["IMG_1.jpg", "IMG_2.jpg", "IMG_10.jpg"]
+        );
+    }
+
+    #[test]
+    fn test_case_insensitive_ordering() {
+        assert_eq!(
+            sorted("en", &["b.txt", "C.txt", "a.txt"]),
+            vec!["a.txt", "b.txt", "C.txt"]
+        );
+    }
+
+    #[test]
+    fn test_locale_aware_ordering() {
+        // in Polish "ł" is a separate letter sorted after "l"
+        assert_eq!(
+            sorted("pl", &["łódź", "lody", "mapa"]),
+            vec!["lody", "łódź", "mapa"]
+        );
+        // in Swedish "ä" is sorted after "z"
+        assert_eq!(sorted("sv", &["äpple", "zebra"]), vec!["zebra", "äpple"]);
+    }
+
+    #[test]
+    fn test_invalid_locale() {
+        assert!(create_collator("not a locale").is_err());
+    }
+}
@@ -0,0 +1,340 @@
+use std::{
+    io,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+
+/// Decides how symbolic links found inside the drive are treated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum SymlinkPolicy {
+    /// Any path going through a symbolic link is rejected.
+    Deny,
+    /// Symbolic links are followed as long as their targets stay inside the base directory.
+    #[default]
+    FollowWithinRoot,
+    /// Symbolic links are followed wherever they point to.
+    FollowAll,
+}
+
+impl FromStr for SymlinkPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "deny" => Ok(Self::Deny),
+            "follow-within-root" => Ok(Self::FollowWithinRoot),
+            "follow-all" => Ok(Self::FollowAll),
+            _ => Err(anyhow::anyhow!(
+                "Unknown symlink policy {s:?}, expected one of: deny, follow-within-root, follow-all"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum PathResolutionError {
+    #[error("Path {0:?} points outside of the drive")]
+    Escape(PathBuf),
+    #[error("Path {0:?} goes through a symbolic link")]
+    SymlinkDenied(PathBuf),
+    #[error("Cannot resolve path {0:?}: {1}")]
+    Io(PathBuf, #[source] io::Error),
+}
+
+/// Resolves `requested` path (relative to `base_dir`) to a path inside the drive.
+///
+/// The path is normalized first (`.` and `..` components are applied lexically and must not
+/// leave the base directory), then every existing component is checked for symbolic links
+/// which are handled according to the `policy`.
+///
+/// The returned path is the normalized one (not the symlink target), so it can be shown to
+/// users and related back to the base directory.
+pub(crate) fn resolve_path(
+    base_dir: &Path,
+    requested: &Path,
+    policy: SymlinkPolicy,
+) -> Result<PathBuf, PathResolutionError> {
+    let normalized = normalize(requested)?;
+    let root = base_dir
+        .canonicalize()
+        .map_err(|e| PathResolutionError::Io(base_dir.to_path_buf(), e))?;
+
+    let mut current = root.clone();
+    for component in normalized.components() {
+        current.push(component);
+        let metadata = match current.symlink_metadata() {
+            Ok(metadata) => metadata,
+            // the rest of the path does not exist yet, so it cannot contain symbolic links
+            Err(e) if e.kind() == io::ErrorKind::NotFound => break,
+            Err(e) => return Err(PathResolutionError::Io(requested.to_path_buf(), e)),
+        };
+        if !metadata.file_type().is_symlink() {
+            continue;
+        }
+        if policy == SymlinkPolicy::Deny {
+            return Err(PathResolutionError::SymlinkDenied(requested.to_path_buf()));
+        }
+        // parents were resolved already, so this resolves the whole chain of links
+        // starting at the current component (failing for dangling links and loops)
+        current = current
+            .canonicalize()
+            .map_err(|e| PathResolutionError::Io(requested.to_path_buf(), e))?;
+        if policy == SymlinkPolicy::FollowWithinRoot && !current.starts_with(&root) {
+            return Err(PathResolutionError::Escape(requested.to_path_buf()));
+        }
+    }
+
+    Ok(base_dir.join(normalized))
+}
+
+/// Applies `.` and `..` components lexically. Fails if the path is absolute or
+/// if it would leave its starting directory.
+pub(crate) fn normalize(path: &Path) -> Result<PathBuf, PathResolutionError> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return Err(PathResolutionError::Escape(path.to_path_buf()));
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => {
+                return Err(PathResolutionError::Escape(path.to_path_buf()))
+            }
+        }
+    }
+    Ok(normalized)
+}
+
+/// Checks whether `name` can be used as a name of a new entry in a directory,
+/// i.e. it is a single path component that is neither `.` nor `..`.
+pub(crate) fn is_plain_file_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    ) && !name.contains('/')
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        fs,
+        os::unix::fs::symlink,
+        path::{Path, PathBuf},
+    };
+
+    use super::{is_plain_file_name, resolve_path, PathResolutionError, SymlinkPolicy};
+
+    struct Drive {
+        // keeps the outside directory alive for the duration of a test
+        _tmp: tempfile::TempDir,
+        base: PathBuf,
+        outside: PathBuf,
+    }
+
+    fn prepare_drive() -> Drive {
+        let tmp = tempfile::tempdir().unwrap();
+        let base = tmp.path().join("drive");
+        let outside = tmp.path().join("outside");
+        fs::create_dir_all(base.join("docs/nested")).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(base.join("docs/a.txt"), "a").unwrap();
+        fs::write(outside.join("secret.txt"), "secret").unwrap();
+
+        symlink(base.join("docs"), base.join("docs_link")).unwrap();
+        symlink("..", base.join("docs/nested/up_link")).unwrap();
+        symlink(&outside, base.join("outside_link")).unwrap();
+        symlink("../outside/secret.txt", base.join("secret_link")).unwrap();
+        // a chain which starts inside and ends outside of the drive
+        symlink("outside_link", base.join("chain_link")).unwrap();
+        symlink(base.join("missing"), base.join("dangling_link")).unwrap();
+        symlink("loop_b", base.join("loop_a")).unwrap();
+        symlink("loop_a", base.join("loop_b")).unwrap();
+        Drive {
+            _tmp: tmp,
+            base,
+            outside,
+        }
+    }
+
+    fn resolve(
+        drive: &Drive,
+        path: &str,
+        policy: SymlinkPolicy,
+    ) -> Result<PathBuf, PathResolutionError> {
+        resolve_path(&drive.base, Path::new(path), policy)
+    }
+
+    const ALL_POLICIES: [SymlinkPolicy; 3] = [
+        SymlinkPolicy::Deny,
+        SymlinkPolicy::FollowWithinRoot,
+        SymlinkPolicy::FollowAll,
+    ];
+
+    #[test]
+    fn test_plain_paths() {
+        let drive = prepare_drive();
+        for policy in ALL_POLICIES {
+            assert_eq!(resolve(&drive, "", policy).unwrap(), drive.base);
+            assert_eq!(
+                resolve(&drive, "docs/a.txt", policy).unwrap(),
+                drive.base.join("docs/a.txt")
+            );
+            assert_eq!(
+                resolve(&drive, "docs/./nested/../a.txt", policy).unwrap(),
+                drive.base.join("docs/a.txt")
+            );
+            assert_eq!(resolve(&drive, "docs/..", policy).unwrap(), drive.base);
+            // not existing paths are allowed, e.g. as targets of new entries
+            assert_eq!(
+                resolve(&drive, "docs/new/file.txt", policy).unwrap(),
+                drive.base.join("docs/new/file.txt")
+            );
+            // dots which are part of names are not special
+            assert_eq!(
+                resolve(&drive, "...", policy).unwrap(),
+                drive.base.join("...")
+            );
+            assert_eq!(
+                resolve(&drive, "..a", policy).unwrap(),
+                drive.base.join("..a")
+            );
+        }
+    }
+
+    #[test]
+    fn test_traversal_vectors() {
+        let drive = prepare_drive();
+        let vectors = [
+            "..",
+            "../",
+            "../outside/secret.txt",
+            "docs/../../outside/secret.txt",
+            "docs/nested/../../../outside",
+            "./../drive/docs",
+            "docs/../..",
+            "/etc/passwd",
+            "//etc/passwd",
+            "/",
+        ];
+        for policy in ALL_POLICIES {
+            for vector in vectors {
+                assert!(
+                    matches!(
+                        resolve(&drive, vector, policy),
+                        Err(PathResolutionError::Escape(_))
+                    ),
+                    "{vector:?} should be rejected for {policy:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_deny_symlinks() {
+        let drive = prepare_drive();
+        for path in [
+            "docs_link",
+            "docs_link/a.txt",
+            "docs/nested/up_link/a.txt",
+            "outside_link/secret.txt",
+            "secret_link",
+            "dangling_link",
+        ] {
+            assert!(
+                matches!(
+                    resolve(&drive, path, SymlinkPolicy::Deny),
+                    Err(PathResolutionError::SymlinkDenied(_))
+                ),
+                "{path:?} should be denied"
+            );
+        }
+    }
+
+    #[test]
+    fn test_follow_symlinks_within_root() {
+        let drive = prepare_drive();
+        let policy = SymlinkPolicy::FollowWithinRoot;
+        assert_eq!(
+            resolve(&drive, "docs_link/a.txt", policy).unwrap(),
+            drive.base.join("docs_link/a.txt")
+        );
+        assert_eq!(
+            resolve(&drive, "docs/nested/up_link/nested/up_link/a.txt", policy).unwrap(),
+            drive.base.join("docs/nested/up_link/nested/up_link/a.txt")
+        );
+        for path in [
+            "outside_link",
+            "outside_link/secret.txt",
+            "secret_link",
+            "chain_link/secret.txt",
+        ] {
+            assert!(
+                matches!(
+                    resolve(&drive, path, policy),
+                    Err(PathResolutionError::Escape(_))
+                ),
+                "{path:?} should be rejected"
+            );
+        }
+        assert!(matches!(
+            resolve(&drive, "dangling_link", policy),
+            Err(PathResolutionError::Io(..))
+        ));
+        assert!(matches!(
+            resolve(&drive, "loop_a/file", policy),
+            Err(PathResolutionError::Io(..))
+        ));
+    }
+
+    #[test]
+    fn test_follow_all_symlinks() {
+        let drive = prepare_drive();
+        let policy = SymlinkPolicy::FollowAll;
+        let resolved = resolve(&drive, "outside_link/secret.txt", policy).unwrap();
+        assert_eq!(resolved, drive.base.join("outside_link/secret.txt"));
+        assert_eq!(
+            resolved.canonicalize().unwrap(),
+            drive.outside.canonicalize().unwrap().join("secret.txt")
+        );
+        assert!(resolve(&drive, "chain_link/secret.txt", policy).is_ok());
+        assert!(matches!(
+            resolve(&drive, "loop_a", policy),
+            Err(PathResolutionError::Io(..))
+        ));
+    }
+
+    #[test]
+    fn test_plain_file_names() {
+        assert!(is_plain_file_name("file.txt"));
+        assert!(is_plain_file_name("..."));
+        assert!(is_plain_file_name(".hidden"));
+        assert!(!is_plain_file_name(""));
+        assert!(!is_plain_file_name("."));
+        assert!(!is_plain_file_name(".."));
+        assert!(!is_plain_file_name("../file.txt"));
+        assert!(!is_plain_file_name("dir/file.txt"));
+        assert!(!is_plain_file_name("dir/"));
+        assert!(!is_plain_file_name("/etc/passwd"));
+    }
+
+    #[test]
+    fn test_policy_parsing() {
+        assert_eq!(
+            "deny".parse::<SymlinkPolicy>().unwrap(),
+            SymlinkPolicy::Deny
+        );
+        assert_eq!(
+            "follow-within-root".parse::<SymlinkPolicy>().unwrap(),
+            SymlinkPolicy::FollowWithinRoot
+        );
+        assert_eq!(
+            "follow-all".parse::<SymlinkPolicy>().unwrap(),
+            SymlinkPolicy::FollowAll
+        );
+        assert!("follow".parse::<SymlinkPolicy>().is_err());
+    }
+}
@@ -0,0 +1,96 @@
+use std::path::{Component, Path, PathBuf};
+
+use super::volumes::Volumes;
+use crate::config::Config;
+
+/// Parts of the drive which cannot be modified.
+///
+/// The whole drive is read-only with `READ_ONLY=true`; `READ_ONLY_PATHS` lists
+/// comma-separated drive paths (e.g. `/archive,/photos/2020`) which are read-only
+/// together with everything below them. Volumes configured as read-only are added to them.
+#[derive(Debug, Default)]
+pub(crate) struct ReadOnlyPaths {
+    all: bool,
+    paths: Vec<PathBuf>,
+}
+
+impl ReadOnlyPaths {
+    pub(crate) fn from_config(config: &Config, volumes: &Volumes) -> Self {
+        Self::new(
+            config.read_only,
+            config
+                .read_only_paths
+                .iter()
+                .map(|path| path.trim())
+                .filter(|path| !path.is_empty())
+                .chain(
+                    volumes
+                        .volumes()
+                        .iter()
+                        .filter(|volume| volume.read_only)
+                        .map(|volume| volume.name.as_str()),
+                ),
+        )
+    }
+
+    fn new<'a>(all: bool, paths: impl Iterator<Item = &'a str>) -> Self {
+        Self {
+            all,
+            paths: paths
+                .map(|path| {
+                    // drive paths, e.g. "/archive", are matched as relative ones
+                    Path::new(path)
+                        .components()
+                        .filter(|c| !matches!(c, Component::RootDir))
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.all || !self.paths.is_empty()
+    }
+
+    /// Checks whether the entry at `relative` path cannot be modified.
+    pub(crate) fn is_read_only(&self, relative: &Path) -> bool {
+        self.all || self.paths.iter().any(|path| relative.starts_with(path))
+    }
+
+    /// Checks whether any read-only entry is below `relative` path, so it cannot be deleted
+    /// as a whole.
+    pub(crate) fn contains_read_only(&self, relative: &Path) -> bool {
+        self.paths.iter().any(|path| path.starts_with(relative))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::ReadOnlyPaths;
+
+    #[test]
+    fn test_read_only_paths() {
+        let read_only = ReadOnlyPaths::new(false, ["/archive", "photos/2020"].into_iter());
+        assert!(read_only.is_enabled());
+        assert!(read_only.is_read_only(Path::new("archive")));
+        assert!(read_only.is_read_only(Path::new("archive/2019/a.jpg")));
+        assert!(read_only.is_read_only(Path::new("photos/2020/b.jpg")));
+        assert!(!read_only.is_read_only(Path::new("archives")));
+        assert!(!read_only.is_read_only(Path::new("photos")));
+
+        assert!(read_only.contains_read_only(Path::new("photos")));
+        assert!(read_only.contains_read_only(Path::new("")));
+        assert!(!read_only.contains_read_only(Path::new("documents")));
+    }
+
+    #[test]
+    fn test_whole_drive_read_only() {
+        let read_only = ReadOnlyPaths::new(true, std::iter::empty());
+        assert!(read_only.is_enabled());
+        assert!(read_only.is_read_only(Path::new("")));
+        assert!(read_only.is_read_only(Path::new("documents/a.txt")));
+        assert!(!ReadOnlyPaths::default().is_enabled());
+    }
+}
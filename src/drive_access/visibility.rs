@@ -0,0 +1,278 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    Match,
+};
+use tracing::warn;
+
+use super::volumes::Volumes;
+
+/// Name of the per-directory file with gitignore-like rules for hiding entries.
+pub(crate) const IGNORE_FILE_NAME: &str = ".mydriveignore";
+
+#[derive(Debug, Default)]
+struct Settings {
+    show_hidden: bool,
+    global_ignore_file: Option<PathBuf>,
+}
+
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+
+/// Sets `SHOW_HIDDEN_FILES` and `GLOBAL_IGNORE_FILE` for the whole drive, once at startup.
+pub(crate) fn configure(show_hidden: bool, global_ignore_file: Option<PathBuf>) {
+    let settings = Settings {
+        show_hidden,
+        global_ignore_file,
+    };
+    if SETTINGS.set(settings).is_err() {
+        warn!("Visibility of entries is configured already");
+    }
+}
+
+/// Decides which entries of the drive are hidden from listings and blocked from direct access.
+///
+/// Entries are hidden when:
+/// - they are `.mydriveignore` files,
+/// - their names start with a dot and `SHOW_HIDDEN_FILES` is not enabled,
+/// - they match rules from `.mydriveignore` files placed in their parent directories
+///   or from the global ignore file configured with `GLOBAL_IGNORE_FILE`,
+/// - any of their parent directories is hidden, including the volume they belong to.
+#[derive(Debug)]
+pub(crate) struct Visibility {
+    volumes: Volumes,
+    show_hidden: bool,
+    global: Gitignore,
+    local: HashMap<PathBuf, Gitignore>,
+}
+
+impl Visibility {
+    pub(crate) fn new(volumes: &Volumes) -> Self {
+        let settings = SETTINGS.get_or_init(Settings::default);
+        Self::with_settings(
+            volumes,
+            settings.show_hidden,
+            settings.global_ignore_file.as_deref(),
+        )
+    }
+
+    fn with_settings(
+        volumes: &Volumes,
+        show_hidden: bool,
+        global_ignore_file: Option<&Path>,
+    ) -> Self {
+        let global = match global_ignore_file {
+            Some(file) => {
+                // global rules are anchored at the root of the drive
+                let mut builder = GitignoreBuilder::new(volumes.root());
+                if let Some(e) = builder.add(file) {
+                    warn!("Failed to read global ignore file {:?}: {:?}", file, e);
+                }
+                builder.build().unwrap_or_else(|e| {
+                    warn!("Invalid global ignore file {:?}: {:?}", file, e);
+                    Gitignore::empty()
+                })
+            }
+            None => Gitignore::empty(),
+        };
+        Self {
+            volumes: volumes.clone(),
+            show_hidden,
+            global,
+            local: HashMap::new(),
+        }
+    }
+
+    /// Checks whether the entry at `path` or any of its parents (up to the root of the drive) is hidden.
+    pub(crate) fn is_hidden(&mut self, path: &Path, is_dir: bool) -> bool {
+        let (Some(base), Some(relative)) =
+            (self.volumes.base_of(path), self.volumes.relative(path))
+        else {
+            return true;
+        };
+        let base = base.to_path_buf();
+        let root = self.volumes.root().to_path_buf();
+        let mut components = relative.components().collect::<Vec<_>>();
+        let mut current = base.clone();
+        let mut drive_path = root.clone();
+        if base != root {
+            // the volume is shown as a directory of the root, so the root's rules apply to it
+            drive_path.push(components.remove(0));
+            if self.is_entry_hidden(&drive_path, &drive_path, &root, true) {
+                return true;
+            }
+        }
+        for (i, component) in components.iter().enumerate() {
+            current.push(component);
+            drive_path.push(component);
+            let is_last = i == components.len() - 1;
+            if self.is_entry_hidden(&current, &drive_path, &base, !is_last || is_dir) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Checks the entry at `path` inside the `base` directory, global rules being matched
+    /// against its `drive_path` below the root.
+    fn is_entry_hidden(
+        &mut self,
+        path: &Path,
+        drive_path: &Path,
+        base: &Path,
+        is_dir: bool,
+    ) -> bool {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy())
+            .unwrap_or_default();
+        if name == IGNORE_FILE_NAME {
+            return true;
+        }
+        if !self.show_hidden && name.starts_with('.') {
+            return true;
+        }
+        // rules from the closest directory take precedence
+        for dir in path.ancestors().skip(1) {
+            if !dir.starts_with(base) {
+                break;
+            }
+            match self.local_rules(dir).matched(path, is_dir) {
+                Match::None => continue,
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+            }
+        }
+        self.global.matched(drive_path, is_dir).is_ignore()
+    }
+
+    fn local_rules(&mut self, dir: &Path) -> &Gitignore {
+        self.local.entry(dir.to_path_buf()).or_insert_with(|| {
+            let ignore_file = dir.join(IGNORE_FILE_NAME);
+            if !ignore_file.is_file() {
+                return Gitignore::empty();
+            }
+            let (rules, error) = Gitignore::new(&ignore_file);
+            if let Some(e) = error {
+                warn!("Failed to parse {:?}: {:?}", ignore_file, e);
+            }
+            rules
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, path::Path};
+
+    use super::{Visibility, IGNORE_FILE_NAME};
+    use crate::drive_access::volumes::{Volume, Volumes};
+
+    fn prepare_drive() -> tempfile::TempDir {
+        let base = tempfile::tempdir().unwrap();
+        let root = base.path();
+        fs::create_dir_all(root.join("photos/raw")).unwrap();
+        fs::create_dir_all(root.join("private")).unwrap();
+        fs::write(root.join(".secret"), "").unwrap();
+        fs::write(root.join("notes.txt"), "").unwrap();
+        fs::write(root.join("notes.bak"), "").unwrap();
+        fs::write(root.join("photos/a.jpg"), "").unwrap();
+        fs::write(root.join("photos/keep.bak"), "").unwrap();
+        fs::write(root.join("photos/raw/a.cr2"), "").unwrap();
+        fs::write(root.join("private/doc.txt"), "").unwrap();
+        fs::write(root.join(IGNORE_FILE_NAME), "*.bak\n/private\n").unwrap();
+        fs::write(
+            root.join("photos").join(IGNORE_FILE_NAME),
+            "!keep.bak\nraw/\n",
+        )
+        .unwrap();
+        base
+    }
+
+    fn hidden(visibility: &mut Visibility, root: &Path, relative: &str) -> bool {
+        let path = root.join(relative);
+        let is_dir = path.is_dir();
+        visibility.is_hidden(&path, is_dir)
+    }
+
+    #[test]
+    fn test_dot_files_hidden_by_default() {
+        let base = prepare_drive();
+        let root = base.path();
+        let mut visibility = Visibility::with_settings(&Volumes::single(root), false, None);
+        assert!(hidden(&mut visibility, root, ".secret"));
+        assert!(hidden(&mut visibility, root, IGNORE_FILE_NAME));
+        assert!(!hidden(&mut visibility, root, "notes.txt"));
+        assert!(!hidden(&mut visibility, root, ""));
+
+        let mut visibility = Visibility::with_settings(&Volumes::single(root), true, None);
+        assert!(!hidden(&mut visibility, root, ".secret"));
+        assert!(hidden(&mut visibility, root, IGNORE_FILE_NAME));
+    }
+
+    #[test]
+    fn test_nested_ignore_files() {
+        let base = prepare_drive();
+        let root = base.path();
+        let mut visibility = Visibility::with_settings(&Volumes::single(root), false, None);
+        assert!(hidden(&mut visibility, root, "notes.bak"));
+        assert!(hidden(&mut visibility, root, "private"));
+        assert!(hidden(&mut visibility, root, "private/doc.txt"));
+        assert!(!hidden(&mut visibility, root, "photos/a.jpg"));
+        assert!(!hidden(&mut visibility, root, "photos/keep.bak"));
+        assert!(hidden(&mut visibility, root, "photos/raw"));
+        assert!(hidden(&mut visibility, root, "photos/raw/a.cr2"));
+    }
+
+    #[test]
+    fn test_global_ignore_file() {
+        let base = prepare_drive();
+        let root = base.path();
+        let global = tempfile::NamedTempFile::new().unwrap();
+        fs::write(global.path(), "*.jpg\n").unwrap();
+        let mut visibility =
+            Visibility::with_settings(&Volumes::single(root), false, Some(global.path()));
+        assert!(hidden(&mut visibility, root, "photos/a.jpg"));
+        assert!(!hidden(&mut visibility, root, "notes.txt"));
+    }
+
+    #[test]
+    fn test_paths_outside_base_dir_hidden() {
+        let base = prepare_drive();
+        let mut visibility = Visibility::with_settings(&Volumes::single(base.path()), false, None);
+        assert!(visibility.is_hidden(Path::new("/etc/passwd"), false));
+    }
+
+    #[test]
+    fn test_entries_of_volumes() {
+        let base = prepare_drive();
+        let root = base.path();
+        let disk = tempfile::tempdir().unwrap();
+        fs::create_dir_all(disk.path().join("raw")).unwrap();
+        fs::write(disk.path().join(IGNORE_FILE_NAME), "raw/\n").unwrap();
+        let volume = |name: &str| Volume {
+            name: name.to_owned(),
+            path: disk.path().to_path_buf(),
+            read_only: false,
+            max_upload_size: None,
+        };
+        let volumes = Volumes::new(root.to_path_buf(), vec![volume("media")]).unwrap();
+        let global = tempfile::NamedTempFile::new().unwrap();
+        fs::write(global.path(), "/media/*.jpg\n").unwrap();
+        let mut visibility = Visibility::with_settings(&volumes, false, Some(global.path()));
+        let disk = disk.path();
+        assert!(!hidden(&mut visibility, disk, ""));
+        assert!(!hidden(&mut visibility, disk, "a.txt"));
+        assert!(hidden(&mut visibility, disk, "raw"));
+        assert!(hidden(&mut visibility, disk, "a.jpg"));
+
+        // volumes are hidden by the rules of the root directory
+        let volumes = Volumes::new(root.to_path_buf(), vec![volume("media.bak")]).unwrap();
+        let mut visibility = Visibility::with_settings(&volumes, false, None);
+        assert!(hidden(&mut visibility, disk, "a.txt"));
+    }
+}
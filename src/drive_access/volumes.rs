@@ -0,0 +1,331 @@
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+
+use super::path_resolution::{
+    is_plain_file_name, normalize, resolve_path, PathResolutionError, SymlinkPolicy,
+};
+use crate::config::Config;
+
+/// Directory, e.g. on another disk, shown as a top-level folder of the drive.
+#[derive(Debug, Clone)]
+pub(crate) struct Volume {
+    pub(crate) name: String,
+    pub(crate) path: PathBuf,
+    pub(crate) read_only: bool,
+    /// Largest file which can be uploaded, in bytes.
+    pub(crate) max_upload_size: Option<u64>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct VolumesFile {
+    volumes: BTreeMap<String, VolumeConfig>,
+}
+
+/// Volume as configured in `VOLUMES_FILE` or the configuration file.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct VolumeConfig {
+    path: PathBuf,
+    #[serde(default)]
+    read_only: bool,
+    /// In megabytes.
+    max_upload_size: Option<u64>,
+}
+
+/// Directories making up the drive.
+///
+/// `BASE_DIR` is the root of the drive; volumes configured in the TOML file `VOLUMES_FILE`
+/// (or in the `volumes` table of the configuration file) appear as top-level folders next to
+/// the root's own entries, e.g.
+///
+/// ```toml
+/// [volumes.media]
+/// path = "/mnt/disk1/media"
+/// read_only = false
+/// max_upload_size = 4096 # megabytes
+/// ```
+///
+/// Entries of the drive are addressed by absolute paths inside these directories. Every
+/// volume is a root of its own when resolving paths, so symbolic links cannot lead out of it.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Volumes {
+    root: PathBuf,
+    volumes: Vec<Volume>,
+}
+
+impl Volumes {
+    pub(crate) fn from_config(config: &Config) -> anyhow::Result<Self> {
+        let mut volumes = config.volumes.clone();
+        if let Some(file) = &config.volumes_file {
+            for (name, volume) in load(file)? {
+                if volumes.insert(name.clone(), volume).is_some() {
+                    bail!("Volume {:?} is configured twice", name);
+                }
+            }
+        }
+        let volumes = volumes
+            .into_iter()
+            .map(|(name, volume)| Volume {
+                name,
+                path: volume.path,
+                read_only: volume.read_only,
+                max_upload_size: volume.max_upload_size.map(|size| size * 1024 * 1024),
+            })
+            .collect();
+        Self::new(config.base_dir.clone(), volumes)
+    }
+
+    pub(crate) fn new(root: PathBuf, volumes: Vec<Volume>) -> anyhow::Result<Self> {
+        let canonical_root = root
+            .canonicalize()
+            .context(format!("Cannot open base directory {:?}", root))?;
+        let mut directories = vec![canonical_root];
+        for volume in &volumes {
+            if !is_plain_file_name(&volume.name) || volume.name.starts_with('.') {
+                bail!("Invalid volume name {:?}", volume.name);
+            }
+            if root.join(&volume.name).symlink_metadata().is_ok() {
+                bail!(
+                    "Volume {:?} would hide the entry with the same name in {:?}",
+                    volume.name,
+                    root
+                );
+            }
+            let directory = volume
+                .path
+                .canonicalize()
+                .ok()
+                .filter(|directory| directory.is_dir())
+                .context(format!(
+                    "Directory {:?} of volume {:?} does not exist",
+                    volume.path, volume.name
+                ))?;
+            // every entry must belong to exactly one directory
+            if let Some(other) = directories
+                .iter()
+                .find(|other| directory.starts_with(other) || other.starts_with(&directory))
+            {
+                bail!(
+                    "Directory {:?} of volume {:?} overlaps with {:?}",
+                    volume.path,
+                    volume.name,
+                    other
+                );
+            }
+            directories.push(directory);
+        }
+        Ok(Self { root, volumes })
+    }
+
+    /// Drive consisting of the `root` directory only.
+    #[cfg(test)]
+    pub(crate) fn single(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            volumes: Vec::new(),
+        }
+    }
+
+    pub(crate) fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub(crate) fn volumes(&self) -> &[Volume] {
+        &self.volumes
+    }
+
+    /// Resolves the `requested` path relative to the root of the drive, checking it
+    /// like [`resolve_path`] inside the volume it leads to.
+    pub(crate) fn resolve(
+        &self,
+        requested: &Path,
+        policy: SymlinkPolicy,
+    ) -> Result<PathBuf, PathResolutionError> {
+        let normalized = normalize(requested)?;
+        let mut components = normalized.components();
+        if let Some(volume) = components
+            .next()
+            .and_then(|first| self.named(first.as_os_str()))
+        {
+            return resolve_path(&volume.path, components.as_path(), policy);
+        }
+        resolve_path(&self.root, &normalized, policy)
+    }
+
+    /// Absolute path of a drive path known to be valid, e.g. one stored with a share link.
+    pub(crate) fn join(&self, relative: &Path) -> PathBuf {
+        let relative = relative.strip_prefix("/").unwrap_or(relative);
+        let mut components = relative.components();
+        match components
+            .next()
+            .and_then(|first| self.named(first.as_os_str()))
+        {
+            Some(volume) => volume.path.join(components.as_path()),
+            None => self.root.join(relative),
+        }
+    }
+
+    /// Path of the entry at the absolute `path` relative to the root of the drive,
+    /// e.g. "media/photos"; empty for the root itself.
+    pub(crate) fn relative(&self, path: &Path) -> Option<PathBuf> {
+        for volume in &self.volumes {
+            if let Ok(rest) = path.strip_prefix(&volume.path) {
+                return Some(Path::new(&volume.name).join(rest).components().collect());
+            }
+        }
+        path.strip_prefix(&self.root).ok().map(Path::to_path_buf)
+    }
+
+    /// Drive path of the entry at the absolute `path` as shown to users, e.g. "/media/photos".
+    pub(crate) fn drive_path(&self, path: &Path) -> String {
+        let relative = self.relative(path).unwrap_or_else(|| path.to_path_buf());
+        format!("/{}", relative.to_string_lossy())
+    }
+
+    /// The volume the entry at `path` belongs to, `None` for entries of the root directory.
+    pub(crate) fn volume_of(&self, path: &Path) -> Option<&Volume> {
+        self.volumes
+            .iter()
+            .find(|volume| path.starts_with(&volume.path))
+    }
+
+    /// Directory the entry at `path` is resolved in, i.e. the directory of its volume or the root.
+    pub(crate) fn base_of(&self, path: &Path) -> Option<&Path> {
+        match self.volume_of(path) {
+            Some(volume) => Some(&volume.path),
+            None => path.starts_with(&self.root).then_some(self.root.as_path()),
+        }
+    }
+
+    /// Checks whether `path` is the directory of a volume, which cannot be moved or deleted.
+    pub(crate) fn is_volume(&self, path: &Path) -> bool {
+        self.volumes.iter().any(|volume| volume.path == path)
+    }
+
+    /// Checks whether `path` is an entry of the root directory hidden by a volume with its name.
+    pub(crate) fn is_shadowed(&self, path: &Path) -> bool {
+        path.parent() == Some(self.root.as_path())
+            && path
+                .file_name()
+                .is_some_and(|name| self.named(name).is_some())
+    }
+
+    /// Largest file which can be uploaded to the folder at `dir`, if limited.
+    pub(crate) fn max_upload_size(&self, dir: &Path) -> Option<u64> {
+        self.volume_of(dir)
+            .and_then(|volume| volume.max_upload_size)
+    }
+
+    /// Largest upload request accepted by forms, `default` bytes unless a volume accepts
+    /// larger files.
+    pub(crate) fn max_request_size(&self, default: u64) -> usize {
+        let largest = self
+            .volumes
+            .iter()
+            .filter_map(|volume| volume.max_upload_size)
+            .max()
+            .unwrap_or(0);
+        usize::try_from(largest.max(default)).unwrap_or(usize::MAX)
+    }
+
+    fn named(&self, name: &OsStr) -> Option<&Volume> {
+        self.volumes
+            .iter()
+            .find(|volume| OsStr::new(&volume.name) == name)
+    }
+}
+
+fn load(file: &Path) -> anyhow::Result<BTreeMap<String, VolumeConfig>> {
+    let contents = std::fs::read_to_string(file).context(format!("Failed to read {:?}", file))?;
+    let config: VolumesFile =
+        toml::from_str(&contents).context(format!("Invalid volumes file {:?}", file))?;
+    Ok(config.volumes)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+
+    use super::{Volume, Volumes};
+    use crate::drive_access::path_resolution::{PathResolutionError, SymlinkPolicy};
+
+    fn volume(name: &str, path: &Path) -> Volume {
+        Volume {
+            name: name.to_owned(),
+            path: path.to_path_buf(),
+            read_only: false,
+            max_upload_size: Some(1024),
+        }
+    }
+
+    #[test]
+    fn test_paths_in_volumes() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (root, media) = (tmp.path().join("root"), tmp.path().join("disk1"));
+        std::fs::create_dir_all(root.join("docs")).unwrap();
+        std::fs::create_dir_all(media.join("photos")).unwrap();
+        let volumes = Volumes::new(root.clone(), vec![volume("media", &media)]).unwrap();
+
+        let policy = SymlinkPolicy::default();
+        let resolve = |path: &str| volumes.resolve(Path::new(path), policy);
+        assert_eq!(resolve("").unwrap(), root);
+        assert_eq!(resolve("docs/a.txt").unwrap(), root.join("docs/a.txt"));
+        assert_eq!(resolve("media").unwrap(), media);
+        assert_eq!(
+            resolve("media/photos/../a.jpg").unwrap(),
+            media.join("a.jpg")
+        );
+        assert_eq!(resolve("docs/../media/a.jpg").unwrap(), media.join("a.jpg"));
+        assert!(matches!(
+            resolve("media/../../disk1"),
+            Err(PathResolutionError::Escape(_))
+        ));
+
+        assert_eq!(
+            volumes.join(Path::new("/media/photos")),
+            media.join("photos")
+        );
+        assert_eq!(volumes.join(Path::new("docs")), root.join("docs"));
+        assert_eq!(
+            volumes.relative(&media.join("photos/a.jpg")),
+            Some(PathBuf::from("media/photos/a.jpg"))
+        );
+        assert_eq!(volumes.relative(&media), Some(PathBuf::from("media")));
+        assert_eq!(volumes.relative(&root), Some(PathBuf::new()));
+        assert_eq!(volumes.relative(tmp.path()), None);
+        assert_eq!(volumes.drive_path(&media.join("photos")), "/media/photos");
+
+        assert!(volumes.is_volume(&media));
+        assert!(!volumes.is_volume(&media.join("photos")));
+        assert!(volumes.is_shadowed(&root.join("media")));
+        assert_eq!(volumes.max_upload_size(&media.join("photos")), Some(1024));
+        assert_eq!(volumes.max_upload_size(&root.join("docs")), None);
+    }
+
+    #[test]
+    fn test_invalid_volumes() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().join("root");
+        std::fs::create_dir_all(root.join("media")).unwrap();
+        let new = |volumes| Volumes::new(root.clone(), volumes);
+        // the name is taken by an entry of the root
+        assert!(new(vec![volume("media", tmp.path())]).is_err());
+        // the root's own entries would be part of the volume
+        assert!(new(vec![volume("docs", &root.join("media"))]).is_err());
+        assert!(new(vec![volume("docs", &tmp.path().join("missing"))]).is_err());
+        assert!(new(vec![volume(".docs", &tmp.path().join("root"))]).is_err());
+        let other = tmp.path().join("other");
+        std::fs::create_dir_all(other.join("nested")).unwrap();
+        assert!(new(vec![
+            volume("a", &other),
+            volume("b", &other.join("nested"))
+        ])
+        .is_err());
+        assert!(new(vec![volume("a", &other)]).is_ok());
+    }
+}
@@ -23,6 +23,18 @@ pub(crate) fn prepare<'reg>() -> Handlebars<'reg> {
     handlebars
 }
 
+/// Renders `template`, recording the time it took.
+pub(crate) fn render<T: serde::Serialize>(
+    handlebars: &Handlebars<'_>,
+    template: &str,
+    data: &T,
+) -> Result<String, RenderError> {
+    let started = std::time::Instant::now();
+    let rendered = handlebars.render(template, data);
+    crate::telemetry::instruments().record_render(template, started.elapsed());
+    rendered
+}
+
 fn switch<'reg: 'rc, 'rc>(
     d: &Decorator,
     _: &Handlebars,
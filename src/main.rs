@@ -1,11 +1,23 @@
+use clap::Parser;
+
+mod api_tokens;
+mod audit;
+mod client_certificates;
+mod config;
 mod drive_access;
 mod handlebars_utils;
+mod metrics;
 mod server;
+mod shares;
 mod telemetry;
+mod users;
+mod webdav;
 mod webservices;
 
 #[cfg(not(feature = "ngrok"))]
 mod default_runner;
+#[cfg(not(feature = "ngrok"))]
+mod tls;
 
 #[cfg(feature = "ngrok")]
 mod ngrok_runner;
@@ -14,6 +26,19 @@ mod ngrok_runner;
 async fn main() -> anyhow::Result<()> {
     dotenv::dotenv().ok();
 
+    let cli = config::Cli::parse();
+    if let Some(config::Command::HashPassword) = cli.command {
+        // prints a password hash to be put in the USERS_FILE
+        let mut password = String::new();
+        std::io::stdin().read_line(&mut password)?;
+        println!(
+            "{}",
+            users::hash_password(password.trim_end_matches(['\r', '\n']))
+        );
+        return Ok(());
+    }
+    let config = config::Config::load(cli)?;
+
     let tracing_subscriber = telemetry::create_subscriber();
 
     telemetry::init_telemetry(tracing_subscriber);
@@ -24,7 +49,7 @@ async fn main() -> anyhow::Result<()> {
     #[cfg(feature = "ngrok")]
     use ngrok_runner::run_server;
 
-    run_server().await?;
-
-    Ok(())
+    let result = run_server(config).await;
+    telemetry::shutdown_telemetry().await;
+    result
 }
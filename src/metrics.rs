@@ -0,0 +1,162 @@
+use std::sync::LazyLock;
+
+use actix_web::HttpResponse;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use tracing::warn;
+
+use crate::{audit::AuditAction, drive_access::volumes::Volumes};
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// Metrics of the server, exposed at `/metrics` in the Prometheus text format.
+pub(crate) fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+/// Marks a response carrying the contents of a file, whose body is counted as downloaded.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Download;
+
+/// Marks `response` as a [`Download`].
+pub(crate) fn download(mut response: HttpResponse) -> HttpResponse {
+    response.extensions_mut().insert(Download);
+    response
+}
+
+pub(crate) struct Metrics {
+    registry: Registry,
+    pub(crate) requests: IntCounterVec,
+    pub(crate) request_duration: HistogramVec,
+    pub(crate) uploaded_bytes: IntCounter,
+    pub(crate) downloaded_bytes: IntCounter,
+    deletions: IntCounter,
+    disk_available: IntGaugeVec,
+    disk_total: IntGaugeVec,
+    #[cfg_attr(not(feature = "ngrok"), allow(dead_code))]
+    pub(crate) tunnel_up: IntGauge,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry =
+            Registry::new_custom(Some("my_drive".to_owned()), None).expect("the prefix is valid");
+        let metrics = Self {
+            requests: IntCounterVec::new(
+                Opts::new("http_requests_total", "HTTP requests by route and status"),
+                &["method", "route", "status"],
+            )
+            .unwrap(),
+            request_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "http_request_duration_seconds",
+                    "Time until the response headers were sent",
+                ),
+                &["method", "route"],
+            )
+            .unwrap(),
+            uploaded_bytes: IntCounter::new("uploaded_bytes_total", "Bytes of uploaded files")
+                .unwrap(),
+            downloaded_bytes: IntCounter::new(
+                "downloaded_bytes_total",
+                "Bytes of file contents sent",
+            )
+            .unwrap(),
+            deletions: IntCounter::new("deleted_entries_total", "Deleted files and folders")
+                .unwrap(),
+            disk_available: IntGaugeVec::new(
+                Opts::new(
+                    "disk_available_bytes",
+                    "Space available on the disk of a volume",
+                ),
+                &["volume"],
+            )
+            .unwrap(),
+            disk_total: IntGaugeVec::new(
+                Opts::new("disk_total_bytes", "Size of the disk of a volume"),
+                &["volume"],
+            )
+            .unwrap(),
+            tunnel_up: IntGauge::new("ngrok_tunnel_up", "Whether the ngrok tunnel is forwarding")
+                .unwrap(),
+            registry,
+        };
+        let collectors: Vec<Box<dyn prometheus::core::Collector>> = vec![
+            Box::new(metrics.requests.clone()),
+            Box::new(metrics.request_duration.clone()),
+            Box::new(metrics.uploaded_bytes.clone()),
+            Box::new(metrics.downloaded_bytes.clone()),
+            Box::new(metrics.deletions.clone()),
+            Box::new(metrics.disk_available.clone()),
+            Box::new(metrics.disk_total.clone()),
+        ];
+        for collector in collectors {
+            metrics.registry.register(collector).unwrap();
+        }
+        if cfg!(feature = "ngrok") {
+            metrics
+                .registry
+                .register(Box::new(metrics.tunnel_up.clone()))
+                .unwrap();
+        }
+        metrics
+    }
+
+    /// Counts a successful modification recorded by the [`crate::audit::Auditor`].
+    pub(crate) fn record(&self, action: AuditAction, bytes: Option<u64>) {
+        match action {
+            AuditAction::Upload => self.uploaded_bytes.inc_by(bytes.unwrap_or(0)),
+            AuditAction::Delete => self.deletions.inc(),
+            AuditAction::CreateDir | AuditAction::Move | AuditAction::Copy => {}
+        }
+    }
+
+    /// All metrics in the Prometheus text format, with the disk space of `volumes` read now.
+    pub(crate) fn render(&self, volumes: &Volumes) -> String {
+        let directories = std::iter::once(("/", volumes.root())).chain(
+            volumes
+                .volumes()
+                .iter()
+                .map(|volume| (volume.name.as_str(), volume.path.as_path())),
+        );
+        for (name, directory) in directories {
+            match fs4::statvfs(directory) {
+                Ok(stats) => {
+                    let clamp = |bytes: u64| i64::try_from(bytes).unwrap_or(i64::MAX);
+                    self.disk_available
+                        .with_label_values(&[name])
+                        .set(clamp(stats.available_space()));
+                    self.disk_total
+                        .with_label_values(&[name])
+                        .set(clamp(stats.total_space()));
+                }
+                Err(e) => warn!("Cannot read the disk space of {:?}: {:?}", directory, e),
+            }
+        }
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            warn!("Failed to encode metrics: {:?}", e);
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::metrics;
+    use crate::{audit::AuditAction, drive_access::volumes::Volumes};
+
+    #[test]
+    fn test_render() {
+        let dir = tempfile::tempdir().unwrap();
+        metrics().record(AuditAction::Upload, Some(1024));
+        metrics().record(AuditAction::Delete, None);
+        let text = metrics().render(&Volumes::single(dir.path()));
+        assert!(text.contains("# TYPE my_drive_uploaded_bytes_total counter"));
+        assert!(text.contains("my_drive_deleted_entries_total"));
+        assert!(text.contains("my_drive_disk_available_bytes{volume=\"/\"}"));
+        assert!(!text.contains("my_drive_ngrok_tunnel_up") || cfg!(feature = "ngrok"));
+    }
+}
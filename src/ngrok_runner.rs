@@ -1,66 +1,68 @@
 use anyhow::Context;
+use tracing::warn;
 
-pub(crate) async fn run_server() -> anyhow::Result<()> {
-    use futures::pin_mut;
+use crate::{
+    config::{Config, NgrokOauth},
+    server::Shutdown,
+};
 
-    let local_address = (
-        "127.0.0.1",
-        dotenv::var("PORT")
-            .unwrap_or("8080".to_owned())
-            .parse::<u16>()
-            .unwrap(),
-    );
+pub(crate) async fn run_server(config: Config) -> anyhow::Result<()> {
+    let shutdown = Shutdown::default();
+    shutdown.listen_for_signals();
 
-    let server = crate::webservices::start_http_server(&local_address);
-    let forwarding = start_ngrok(&local_address);
+    let local_address = ("127.0.0.1", config.port);
+    let authtoken = config
+        .ngrok_auth_token
+        .clone()
+        .context("NGROK_AUTH_TOKEN must be set")?;
+    let domain = config.ngrok_domain.clone();
+    let oauth = config.ngrok_oauth.clone();
 
-    pin_mut!(server);
-    pin_mut!(forwarding);
+    let mut session = ngrok::Session::builder()
+        .authtoken(authtoken)
+        .connect()
+        .await?;
 
-    futures::future::select(server, forwarding).await;
+    // whichever stops first, the other one is stopped gracefully as well
+    let server = async {
+        let result =
+            crate::webservices::start_http_server(&local_address, None, config, shutdown.clone())
+                .await;
+        shutdown.request();
+        result
+    };
+    let forwarding = async {
+        let result = forward(&session, &local_address, domain, oauth, &shutdown).await;
+        shutdown.request();
+        result
+    };
+    let (served, forwarded) = futures::join!(server, forwarding);
 
-    Ok(())
+    // forwarded connections are closed only after the server has stopped
+    if let Err(e) = session.close().await {
+        warn!("Cannot close the ngrok session: {:?}", e);
+    }
+    served.and(forwarded)
 }
 
-mod config_model;
-
-async fn start_ngrok(local_address: &(&str, u16)) -> anyhow::Result<()> {
+/// Forwards connections of a tunnel with an HTTP edge until the shutdown is requested.
+async fn forward(
+    session: &ngrok::Session,
+    local_address: &(&str, u16),
+    domain: Option<String>,
+    oauth: Option<NgrokOauth>,
+    shutdown: &Shutdown,
+) -> anyhow::Result<()> {
+    use futures::future::Either;
     use ngrok::prelude::*;
-    use tracing::{warn, info};
-
-    use config_model::NgrokConfig;
+    use tracing::info;
 
-    let ngrok_config = std::fs::read_to_string("ngrok-config.toml")
-        .context("Failed to read ngrok-config.toml")
-        .and_then(|contents| {
-            toml::from_str::<NgrokConfig>(&contents).context("Failed to parse ngrok-config.toml")
-        })
-        .unwrap_or_else(|e| {
-            warn!(
-                "Failed to read ngrok-config.toml, using environment variables: {:?}",
-                e
-            );
-            let ngrok_auth_token = dotenv::var("NGROK_AUTH_TOKEN").unwrap();
-            let ngrok_domain = dotenv::var("NGROK_DOMAIN").ok();
-            NgrokConfig {
-                authoken: ngrok_auth_token,
-                domain: ngrok_domain,
-                oauth: None,
-            }
-        });
-    let mut tun_builder = ngrok::Session::builder()
-        // Set the auth token
-        .authtoken(ngrok_config.authoken)
-        // Connect the ngrok session
-        .connect()
-        .await?
-        // Start a tunnel with an HTTP edge
-        .http_endpoint();
-    if let Some(domain) = ngrok_config.domain {
+    let mut tun_builder = session.http_endpoint();
+    if let Some(domain) = domain {
         tun_builder = tun_builder.domain(domain);
     };
 
-    if let Some(oauth) = ngrok_config.oauth {
+    if let Some(oauth) = oauth {
         let mut oauth_options = ngrok::config::OauthOptions::new(oauth.provider);
         if let Some(allowed_emails) = oauth.allowed_emails {
             for email in allowed_emails {
@@ -76,6 +78,30 @@ async fn start_ngrok(local_address: &(&str, u16)) -> anyhow::Result<()> {
     }
 
     let mut tun = tun_builder.listen().await?;
-    info!("Tunnel to {local_address:?} started on URL: {:?}", tun.url());
-    Ok(tun.forward_tcp(local_address).await?)
+    info!(
+        "Tunnel to {local_address:?} started on URL: {:?}",
+        tun.url()
+    );
+    let url = tun.url().to_owned();
+    let tunnel_up = &crate::metrics::metrics().tunnel_up;
+    tunnel_up.set(1);
+    let forwarded = {
+        let forwarding = tun.forward_tcp(*local_address);
+        let requested = shutdown.requested();
+        futures::pin_mut!(forwarding);
+        futures::pin_mut!(requested);
+        match futures::future::select(forwarding, requested).await {
+            Either::Left((result, _)) => Some(result),
+            Either::Right(_) => None,
+        }
+    };
+    tunnel_up.set(0);
+    match forwarded {
+        Some(result) => Ok(result?),
+        // connections being forwarded keep running, no new ones are accepted
+        None => {
+            info!("Closing the tunnel on URL: {:?}", url);
+            Ok(tun.close().await?)
+        }
+    }
 }
@@ -11,7 +11,41 @@ use actix_web::{
 };
 use tracing::debug;
 
-use crate::webservices::FileListInputError;
+mod access_control;
+mod audit;
+mod authentication;
+mod client_ip;
+mod csrf;
+pub(crate) mod failed_attempts;
+mod metrics;
+pub(crate) mod oidc;
+mod shutdown;
+
+pub(crate) use access_control::{access_of, AccessControl};
+
+pub(crate) use client_ip::{client_ip, TrustedProxies};
+
+pub(crate) use csrf::{csrf_token, CsrfProtection};
+
+pub(crate) use failed_attempts::{FailedAttempts, FAILURE_WINDOW};
+
+pub(crate) use metrics::RequestMetrics;
+
+pub(crate) use shutdown::{Shutdown, UploadTracking};
+
+pub(crate) use authentication::{
+    session_key, session_middleware, sign_in, sign_out, Authentication, AuthenticationRequired,
+    BasicCredentials, CurrentUser, MetricsToken, SignInMethod, SignedOutSessions, LOGIN_PATH,
+};
+
+use crate::{
+    drive_access::{
+        path_resolution::{PathResolutionError, SymlinkPolicy},
+        volumes::Volumes,
+        Visibility,
+    },
+    webservices::FileListInputError,
+};
 
 pub(crate) struct RequestPath;
 
@@ -47,20 +81,33 @@ where
     type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        let base_dir = req.app_data::<actix_web::web::Data<PathBuf>>().unwrap();
+        let volumes = req.app_data::<actix_web::web::Data<Volumes>>().unwrap();
         let path: PathBuf = req.match_info().query("path").parse().unwrap();
-        let combined_path = base_dir.join(&path).to_path_buf();
-        let is_path_valid = combined_path.starts_with(base_dir.as_path());
-
-        if !is_path_valid {
-            debug!("Invalid path: {:?}", &path);
+        let symlink_policy = req
+            .app_data::<actix_web::web::Data<SymlinkPolicy>>()
+            .map(|policy| *policy.get_ref())
+            .unwrap_or_default();
+        let combined_path = match volumes.resolve(&path, symlink_policy) {
+            Ok(combined_path) => combined_path,
+            Err(e) => {
+                debug!("Invalid path: {:?}", e);
+                let response = match e {
+                    PathResolutionError::SymlinkDenied(_) => HttpResponse::Forbidden().finish(),
+                    _ => HttpResponse::BadRequest()
+                        .body(FileListInputError::InvalidPath(path.to_path_buf()).to_string()),
+                };
+                return Box::pin(async move {
+                    actix_web::Result::Ok(req.into_response(response).map_into_right_body())
+                });
+            }
+        };
+        let is_dir = combined_path.is_dir();
+        if Visibility::new(volumes).is_hidden(&combined_path, is_dir) {
+            debug!("Hidden path requested: {:?}", &path);
             return Box::pin(async move {
                 actix_web::Result::Ok(
-                    req.into_response(
-                        HttpResponse::BadRequest()
-                            .body(FileListInputError::InvalidPath(path.to_path_buf()).to_string()),
-                    )
-                    .map_into_right_body(),
+                    req.into_response(HttpResponse::NotFound().finish())
+                        .map_into_right_body(),
                 )
             });
         }
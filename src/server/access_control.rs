@@ -0,0 +1,158 @@
+use std::{
+    future::{ready, Future, Ready},
+    pin::Pin,
+};
+
+use actix_web::{
+    body::EitherBody,
+    dev::{self, Service, ServiceRequest, ServiceResponse, Transform},
+    http::Method,
+    web, Error, FromRequest, HttpMessage, HttpRequest, HttpResponse,
+};
+use tracing::debug;
+
+use super::{AuthenticationRequired, CurrentUser, RequestedPath};
+use crate::{
+    api_tokens::ApiToken,
+    drive_access::{
+        access_control::{AccessControlList, Permission},
+        read_only::ReadOnlyPaths,
+        volumes::Volumes,
+        Permissions, UserAccess,
+    },
+    shares::ShareStore,
+};
+
+impl FromRequest for UserAccess {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut dev::Payload) -> Self::Future {
+        ready(Ok(user_access(req)))
+    }
+}
+
+fn user_access(req: &HttpRequest) -> UserAccess {
+    let user = req.extensions().get::<CurrentUser>().cloned();
+    let signed_out = user.is_none()
+        && req
+            .app_data::<web::Data<AuthenticationRequired>>()
+            .is_some();
+    let access = access_of(
+        req,
+        user.as_ref().map(|user| user.name.clone()),
+        user.is_some_and(|user| user.is_admin),
+    );
+    if signed_out {
+        // e.g. public routes never give access to the drive
+        return access.restricted(Permissions::default());
+    }
+    match req.extensions().get::<ApiToken>() {
+        // API tokens can only do what their scope allows
+        Some(token) => access.restricted(token.scope.permissions()),
+        None => access,
+    }
+}
+
+/// Access rights of the given user (e.g. the owner of a share link) with the drive settings
+/// of the application handling `req`.
+pub(crate) fn access_of(
+    req: &HttpRequest,
+    principal: Option<String>,
+    is_admin: bool,
+) -> UserAccess {
+    let acl = req
+        .app_data::<web::Data<AccessControlList>>()
+        .map(|acl| acl.clone().into_inner());
+    let volumes = req.app_data::<web::Data<Volumes>>().unwrap();
+    let mut access = UserAccess::new(acl, principal, is_admin, volumes.clone().into_inner());
+    if let Some(read_only) = req.app_data::<web::Data<ReadOnlyPaths>>() {
+        access = access.with_read_only(read_only.clone().into_inner());
+    }
+    if req.app_data::<web::Data<ShareStore>>().is_none() {
+        // nothing can be shared when share links are disabled
+        access = access.restricted(Permissions {
+            share: false,
+            ..Permissions::all()
+        });
+    }
+    access
+}
+
+/// Checks whether the user may perform the request on the [`RequestedPath`].
+///
+/// Must be registered as an inner layer of [`super::RequestPath`].
+pub(crate) struct AccessControl;
+
+impl<S, B> Transform<S, ServiceRequest> for AccessControl
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AccessControlMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AccessControlMiddleware { service }))
+    }
+}
+
+pub(crate) struct AccessControlMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for AccessControlMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let path = req
+            .extensions()
+            .get::<RequestedPath>()
+            .map(|path| path.as_ref().clone());
+        if let Some(path) = path {
+            let access = user_access(req.request());
+            let permissions = access.permissions(&path);
+            let is_dir = path.is_dir();
+            let required = match *req.method() {
+                // traversable directories can be listed, showing only readable entries
+                Method::GET | Method::HEAD => (!is_dir).then_some(Permission::Read),
+                Method::PUT => Some(Permission::Write),
+                Method::DELETE => Some(Permission::Delete),
+                _ => None,
+            };
+            let response = match required {
+                // e.g. upload-only access can write to folders it cannot list
+                Some(required) if permissions.allows(required) => None,
+                // entries which cannot be read are invisible
+                _ if !access.is_listable(&path, is_dir) => Some(HttpResponse::NotFound().finish()),
+                Some(_) => Some(HttpResponse::Forbidden().finish()),
+                None => None,
+            };
+            if let Some(response) = response {
+                debug!("Access to {:?} denied", path);
+                return Box::pin(async move {
+                    actix_web::Result::Ok(req.into_response(response).map_into_right_body())
+                });
+            }
+        }
+        let r = self.service.call(req);
+
+        Box::pin(async move {
+            // forwarded responses map to "left" body
+            r.await.map(ServiceResponse::map_into_left_body)
+        })
+    }
+
+    dev::forward_ready!(service);
+}
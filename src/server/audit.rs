@@ -0,0 +1,42 @@
+use std::future::{ready, Ready};
+
+use actix_web::{dev, web, Error, FromRequest, HttpMessage, HttpRequest};
+
+use super::CurrentUser;
+use crate::{
+    api_tokens::ApiToken,
+    audit::{AuditLog, Auditor},
+    client_certificates::CertificateLogin,
+    drive_access::volumes::Volumes,
+};
+
+impl FromRequest for Auditor {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut dev::Payload) -> Self::Future {
+        let Some(log) = req.app_data::<web::Data<AuditLog>>() else {
+            return ready(Ok(Auditor::default()));
+        };
+        let extensions = req.extensions();
+        ready(Ok(Auditor {
+            log: Some(log.clone().into_inner()),
+            volumes: req
+                .app_data::<web::Data<Volumes>>()
+                .map(|volumes| volumes.clone().into_inner())
+                .unwrap_or_default(),
+            actor: extensions
+                .get::<CurrentUser>()
+                .map(|user| user.name.clone()),
+            via: extensions
+                .get::<ApiToken>()
+                .map(|token| format!("API token {:?}", token.name))
+                .or_else(|| {
+                    extensions
+                        .get::<CertificateLogin>()
+                        .map(|login| format!("client certificate {:?}", login.0))
+                }),
+            client_ip: super::client_ip(req).map(|ip| ip.to_string()),
+        }))
+    }
+}
@@ -0,0 +1,557 @@
+use std::{
+    future::{ready, Future, Ready},
+    pin::Pin,
+};
+
+use actix_session::{
+    config::CookieContentSecurity, storage::CookieSessionStore, SessionMiddleware,
+};
+use actix_web::{
+    body::EitherBody,
+    cookie::{Key, SameSite},
+    dev::{self, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{header, Method, StatusCode},
+    web, Error, HttpMessage, HttpResponse, ResponseError,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use sha2::{Digest, Sha256};
+use tracing::{debug, warn};
+
+mod basic_credentials;
+mod session;
+
+pub(crate) use basic_credentials::BasicCredentials;
+pub(crate) use session::{sign_in, sign_out, SignInMethod, SignedOutSessions};
+
+use crate::{
+    api_tokens::{ApiToken, TokenStore},
+    client_certificates::{
+        CertificateLogin, CertificatePolicy, ClientCertificate, ClientCertificates,
+    },
+    users::UserStore,
+    webdav::is_dav_path,
+    webservices::{Problem, API_PATH, METRICS_PATH},
+};
+
+pub(crate) const LOGIN_PATH: &str = "/login";
+
+/// Paths available without signing in.
+const PUBLIC_PATHS: [&str; 6] = [
+    "/static",
+    LOGIN_PATH,
+    "/login/oidc",
+    super::oidc::OIDC_CALLBACK_PATH,
+    "/healthz",
+    "/readyz",
+];
+
+/// Prefixes of public routes taking a parameter, e.g. `/s/{token}`. Paths below them without
+/// the parameter would reach the drive, so they are not public.
+const PUBLIC_PREFIXES: [&str; 2] = ["/static", crate::shares::SHARE_PATH];
+
+/// Marks an application requiring users to sign in, whose requests without a user have no
+/// permissions at all.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct AuthenticationRequired;
+
+/// Token Prometheus sends as `Authorization: Bearer` to scrape [`METRICS_PATH`], configured
+/// with `METRICS_TOKEN`. It signs no user in and gives access to nothing else.
+pub(crate) struct MetricsToken(pub(crate) String);
+
+impl MetricsToken {
+    fn matches(&self, secret: &str) -> bool {
+        // comparing digests does not reveal how much of the token was guessed
+        Sha256::digest(self.0.as_bytes()) == Sha256::digest(secret.as_bytes())
+    }
+}
+
+/// Creates the key signing session cookies from `SESSION_SECRET` (at least 32 bytes).
+///
+/// Without the secret a random key is used, so sessions do not survive restarts.
+pub(crate) fn session_key(secret: Option<&str>) -> anyhow::Result<Key> {
+    match secret {
+        Some(secret) if secret.len() >= 32 => Ok(Key::derive_from(secret.as_bytes())),
+        Some(_) => Err(anyhow::anyhow!(
+            "SESSION_SECRET must be at least 32 bytes long"
+        )),
+        None => {
+            warn!("SESSION_SECRET not set, sessions will not survive restarts");
+            Ok(Key::generate())
+        }
+    }
+}
+
+/// Keeps sessions in signed, HTTP-only, same-site cookies, sent only over HTTPS when `secure`.
+pub(crate) fn session_middleware(key: Key, secure: bool) -> SessionMiddleware<CookieSessionStore> {
+    SessionMiddleware::builder(CookieSessionStore::default(), key)
+        .cookie_name("my-drive-session".to_owned())
+        .cookie_content_security(CookieContentSecurity::Signed)
+        .cookie_http_only(true)
+        .cookie_same_site(SameSite::Lax)
+        .cookie_secure(secure)
+        .build()
+}
+
+/// User signed in for the current request.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub(crate) struct CurrentUser {
+    pub(crate) name: String,
+    pub(crate) is_admin: bool,
+}
+
+/// Rejects requests without a signed-in user in the session, except for [`PUBLIC_PATHS`].
+/// Sessions expire and their users are looked up again on each request.
+///
+/// Requests with an `Authorization: Bearer` header are authenticated with the API token
+/// instead, which is then available as a request extension. WebDAV clients may use
+/// `Authorization: Basic` with either the user's password or an API token. Otherwise a known
+/// client certificate of the connection signs the request in, and paths requiring one reject
+/// requests without it.
+/// Requires the session middleware to be registered as an outer layer.
+pub(crate) struct Authentication;
+
+impl<S, B> Transform<S, ServiceRequest> for Authentication
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AuthenticationMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AuthenticationMiddleware { service }))
+    }
+}
+
+pub(crate) struct AuthenticationMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for AuthenticationMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        // they could turn a public path into a drive path once normalized
+        if has_dot_segments(req.path()) {
+            debug!("Path with dot segments {:?}", req.path());
+            let response = HttpResponse::BadRequest().finish();
+            return Box::pin(async move {
+                actix_web::Result::Ok(req.into_response(response).map_into_right_body())
+            });
+        }
+        let certificate = certificate_user(&req);
+        if certificate.is_none() && requires_certificate(&req) {
+            debug!("Missing client certificate for {:?}", req.path());
+            let response = certificate_required(&req);
+            return Box::pin(async move {
+                actix_web::Result::Ok(req.into_response(response).map_into_right_body())
+            });
+        }
+        if let Some(secret) = bearer_token(&req) {
+            let token = req
+                .app_data::<web::Data<TokenStore>>()
+                .and_then(|tokens| tokens.authenticate(&secret));
+            match token {
+                Some(token) => {
+                    let user = CurrentUser {
+                        name: token.owner.clone(),
+                        is_admin: token.owner_is_admin,
+                    };
+                    req.extensions_mut().insert(user);
+                    req.extensions_mut().insert(token);
+                }
+                None if is_metrics_scrape(&req, &secret) => {}
+                None => {
+                    debug!("Invalid API token for {:?}", req.path());
+                    let response = unauthorized(&req);
+                    return Box::pin(async move {
+                        actix_web::Result::Ok(req.into_response(response).map_into_right_body())
+                    });
+                }
+            }
+        } else if is_dav_path(req.path()) && has_basic_credentials(&req) {
+            let credentials = req.app_data::<web::Data<BasicCredentials>>().cloned();
+            let client = super::client_ip(req.request());
+            if let (Some(credentials), Some(client)) = (&credentials, client) {
+                if credentials.is_throttled(client) {
+                    debug!("Too many invalid credentials from {}", client);
+                    let response = HttpResponse::TooManyRequests()
+                        .insert_header((header::RETRY_AFTER, super::FAILURE_WINDOW.as_secs()))
+                        .finish();
+                    return Box::pin(async move {
+                        actix_web::Result::Ok(req.into_response(response).map_into_right_body())
+                    });
+                }
+            }
+            let authenticated = basic_authentication(
+                &req,
+                credentials
+                    .as_ref()
+                    .map(|credentials| credentials.get_ref()),
+            );
+            if let (Some(credentials), Some(client)) = (&credentials, client) {
+                match authenticated {
+                    Some(_) => credentials.record_success(client),
+                    None => credentials.record_failure(client),
+                }
+            }
+            match authenticated {
+                Some((user, token)) => {
+                    req.extensions_mut().insert(user);
+                    if let Some(token) = token {
+                        req.extensions_mut().insert(token);
+                    }
+                }
+                None => {
+                    debug!("Invalid credentials for {:?}", req.path());
+                    let response = basic_challenge();
+                    return Box::pin(async move {
+                        actix_web::Result::Ok(req.into_response(response).map_into_right_body())
+                    });
+                }
+            }
+        } else if let Some((user, login)) = certificate {
+            req.extensions_mut().insert(user);
+            req.extensions_mut().insert(login);
+        } else if !is_public_path(req.path()) {
+            match session::session_user(&req) {
+                Some(user) => {
+                    req.extensions_mut().insert(user);
+                }
+                None => {
+                    debug!("Unauthenticated request to {:?}", req.path());
+                    let response = unauthenticated_response(&req);
+                    return Box::pin(async move {
+                        actix_web::Result::Ok(req.into_response(response).map_into_right_body())
+                    });
+                }
+            }
+        }
+        let r = self.service.call(req);
+
+        Box::pin(async move {
+            // forwarded responses map to "left" body
+            r.await.map(ServiceResponse::map_into_left_body)
+        })
+    }
+
+    dev::forward_ready!(service);
+}
+
+/// The user identified by the client certificate of the connection, if known.
+fn certificate_user(req: &ServiceRequest) -> Option<(CurrentUser, CertificateLogin)> {
+    let certificate = req.conn_data::<ClientCertificate>()?;
+    let (name, user) = req
+        .app_data::<web::Data<ClientCertificates>>()?
+        .identify(certificate)?;
+    let current_user = CurrentUser {
+        name: user.user.clone(),
+        is_admin: user.admin,
+    };
+    Some((current_user, CertificateLogin(name.to_owned())))
+}
+
+fn requires_certificate(req: &ServiceRequest) -> bool {
+    req.app_data::<web::Data<ClientCertificates>>()
+        .is_some_and(|certificates| certificates.policy(req.path()) == CertificatePolicy::Required)
+}
+
+/// Rejects a request to a path requiring a client certificate, which no credentials can replace.
+fn certificate_required(req: &ServiceRequest) -> HttpResponse {
+    const DETAIL: &str = "A known client certificate is required";
+    if is_api_path(req.path()) {
+        Problem::new(StatusCode::FORBIDDEN)
+            .with_detail(DETAIL)
+            .error_response()
+    } else {
+        HttpResponse::Forbidden().body(DETAIL)
+    }
+}
+
+fn bearer_token(req: &ServiceRequest) -> Option<String> {
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_owned())
+}
+
+fn is_metrics_scrape(req: &ServiceRequest, secret: &str) -> bool {
+    req.path() == METRICS_PATH
+        && req
+            .app_data::<web::Data<MetricsToken>>()
+            .is_some_and(|token| token.matches(secret))
+}
+
+fn has_basic_credentials(req: &ServiceRequest) -> bool {
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("Basic "))
+}
+
+/// Authenticates Basic credentials, accepting an API token of the user instead of the password
+/// (e.g. for users signing in with OpenID Connect). Passwords verified recently are not verified
+/// again when `verified` is given.
+fn basic_authentication(
+    req: &ServiceRequest,
+    verified: Option<&BasicCredentials>,
+) -> Option<(CurrentUser, Option<ApiToken>)> {
+    let encoded = req
+        .headers()
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Basic ")?
+        .trim();
+    let credentials = String::from_utf8(STANDARD.decode(encoded).ok()?).ok()?;
+    let (name, password) = credentials.split_once(':')?;
+    let token = req
+        .app_data::<web::Data<TokenStore>>()
+        .and_then(|tokens| tokens.authenticate(password));
+    if let Some(token) = token {
+        let user = CurrentUser {
+            name: token.owner.clone(),
+            is_admin: token.owner_is_admin,
+        };
+        return (token.owner == name).then_some((user, Some(token)));
+    }
+    if let Some(user) = verified.and_then(|verified| verified.verified(encoded)) {
+        return Some((user, None));
+    }
+    let account = req
+        .app_data::<web::Data<UserStore>>()?
+        .verify(name, password)?;
+    let user = CurrentUser {
+        name: account.name.clone(),
+        is_admin: account.admin,
+    };
+    if let Some(verified) = verified {
+        verified.remember(encoded, &user);
+    }
+    Some((user, None))
+}
+
+/// Asks WebDAV clients for credentials.
+fn basic_challenge() -> HttpResponse {
+    HttpResponse::Unauthorized()
+        .insert_header((header::WWW_AUTHENTICATE, "Basic realm=\"My Drive\""))
+        .finish()
+}
+
+pub(super) fn is_api_path(path: &str) -> bool {
+    path == API_PATH || path.starts_with(&format!("{API_PATH}/"))
+}
+
+/// Rejects a request without valid credentials, with a problem description for API clients.
+fn unauthorized(req: &ServiceRequest) -> HttpResponse {
+    if is_api_path(req.path()) {
+        Problem::new(StatusCode::UNAUTHORIZED).error_response()
+    } else {
+        HttpResponse::Unauthorized().finish()
+    }
+}
+
+/// Checks whether `path` is a public route, matched exactly so no drive path is ever public.
+fn is_public_path(path: &str) -> bool {
+    PUBLIC_PATHS.contains(&path)
+        || PUBLIC_PREFIXES.iter().any(|prefix| {
+            path.strip_prefix(prefix)
+                .and_then(|rest| rest.strip_prefix('/'))
+                .is_some_and(|rest| !rest.is_empty() && !rest.starts_with('/'))
+        })
+}
+
+/// Checks whether `path` has `.` or `..` segments, also percent-encoded.
+fn has_dot_segments(path: &str) -> bool {
+    path.split('/').any(|segment| {
+        let segment = percent_encoding::percent_decode_str(segment).decode_utf8_lossy();
+        segment == "." || segment == ".."
+    })
+}
+
+/// Builds a login page address which brings the user back to `next` after signing in.
+pub(crate) fn login_url(next: &str) -> String {
+    match serde_urlencoded::to_string([("next", next)]) {
+        Ok(query) if next != "/" => format!("{LOGIN_PATH}?{query}"),
+        _ => LOGIN_PATH.to_owned(),
+    }
+}
+
+fn unauthenticated_response(req: &ServiceRequest) -> HttpResponse {
+    if is_dav_path(req.path()) {
+        return basic_challenge();
+    }
+    if is_api_path(req.path()) {
+        return unauthorized(req);
+    }
+    if req.headers().contains_key("HX-Request") {
+        // HTMX performs a full page redirect on this header
+        return HttpResponse::Unauthorized()
+            .insert_header(("HX-Redirect", LOGIN_PATH))
+            .finish();
+    }
+    let accepts_html = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"));
+    if req.method() == Method::GET && accepts_html {
+        HttpResponse::SeeOther()
+            .insert_header((header::LOCATION, login_url(req.path())))
+            .finish()
+    } else {
+        HttpResponse::Unauthorized().finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use actix_web::{
+        cookie::Key,
+        http::{Method, StatusCode},
+        test::{self, TestRequest},
+        web, App, FromRequest, HttpResponse,
+    };
+
+    use super::{
+        is_public_path, session_middleware, Authentication, AuthenticationRequired, MetricsToken,
+    };
+    use crate::{
+        drive_access::{volumes::Volumes, UserAccess},
+        server::{AccessControl, RequestPath},
+        users::UserStore,
+    };
+
+    #[test]
+    fn test_public_paths() {
+        for path in [
+            "/login",
+            "/login/oidc",
+            "/healthz",
+            "/static/app.js",
+            "/s/abc/a.jpg",
+        ] {
+            assert!(is_public_path(path), "{path} is not public");
+        }
+        for path in [
+            "/login/secret.txt",
+            "/healthz/sub",
+            "/s",
+            "/s/",
+            "/s//secret.txt",
+            "/",
+        ] {
+            assert!(!is_public_path(path), "{path} is public");
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_no_drive_access_through_public_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("secret.txt"), "secret").unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        let users: UserStore = toml::from_str("users = []").unwrap();
+        let app = test::init_service(
+            App::new()
+                .wrap(Authentication)
+                .wrap(session_middleware(Key::generate(), false))
+                .app_data(web::Data::new(Volumes::single(dir.path())))
+                .app_data(web::Data::new(users))
+                .app_data(web::Data::new(AuthenticationRequired))
+                .route("/healthz", web::get().to(HttpResponse::Ok))
+                .service(
+                    web::resource("/{path:.*}")
+                        .wrap(AccessControl)
+                        .wrap(RequestPath)
+                        .to(|access: UserAccess| async move {
+                            // stands for the drive handlers, which check the permissions too
+                            let root = access.volumes().root().to_path_buf();
+                            if access.permissions(&root).read {
+                                HttpResponse::Ok().finish()
+                            } else {
+                                HttpResponse::NotFound().finish()
+                            }
+                        }),
+                ),
+        )
+        .await;
+
+        for (method, uri) in [
+            (Method::GET, "/login/../secret.txt"),
+            (Method::GET, "/healthz/../secret.txt"),
+            (Method::GET, "/login/%2e%2e/secret.txt"),
+            (Method::GET, "/login/.%2E/secret.txt"),
+            (Method::DELETE, "/healthz/../sub"),
+        ] {
+            let request = TestRequest::default().method(method).uri(uri).to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{uri}");
+        }
+        for uri in [
+            "/secret.txt",
+            "/login/secret.txt",
+            "/healthz/secret.txt",
+            "/s/",
+        ] {
+            let request = TestRequest::get().uri(uri).to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{uri}");
+        }
+        assert!(dir.path().join("sub").exists());
+    }
+
+    #[actix_web::test]
+    async fn test_metrics_token() {
+        let token = "0123456789abcdef0123456789abcdef";
+        let app = test::init_service(
+            App::new()
+                .wrap(Authentication)
+                .wrap(session_middleware(Key::generate(), false))
+                .app_data(web::Data::new(MetricsToken(token.to_owned())))
+                .route("/metrics", web::get().to(HttpResponse::Ok))
+                .route("/{path:.*}", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let scrape = |uri: &str, token: &str| {
+            TestRequest::get()
+                .uri(uri)
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request()
+        };
+
+        let response = test::call_service(&app, scrape("/metrics", token)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        for (uri, token) in [
+            ("/metrics", "guess"),
+            ("/metrics/", token),
+            ("/secret.txt", token),
+            ("/api/v1/folders", token),
+        ] {
+            let response = test::call_service(&app, scrape(uri, token)).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{uri}");
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_signed_out_requests_have_no_permissions() {
+        let dir = tempfile::tempdir().unwrap();
+        let request = TestRequest::default()
+            .app_data(web::Data::new(Volumes::single(dir.path())))
+            .app_data(web::Data::new(AuthenticationRequired))
+            .to_http_request();
+        let access = UserAccess::extract(&request).await.unwrap();
+        assert_eq!(access.permissions(dir.path()), Default::default());
+    }
+}
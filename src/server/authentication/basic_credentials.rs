@@ -0,0 +1,100 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use sha2::{Digest, Sha256};
+
+use super::CurrentUser;
+use crate::server::FailedAttempts;
+
+/// How long verified Basic credentials are accepted without verifying the password again.
+const VERIFIED_FOR: Duration = Duration::from_secs(60);
+
+/// Basic credentials of WebDAV clients, which send them with every request.
+///
+/// Verifying a password with argon2 takes tens of milliseconds and megabytes of memory, so
+/// verified credentials are remembered briefly, by their hash, and clients failing repeatedly
+/// are refused for a while.
+#[derive(Debug, Default)]
+pub(crate) struct BasicCredentials {
+    verified: Mutex<HashMap<[u8; 32], (CurrentUser, Instant)>>,
+    failures: FailedAttempts<IpAddr>,
+}
+
+impl BasicCredentials {
+    /// The user whose `credentials` were verified recently.
+    pub(super) fn verified(&self, credentials: &str) -> Option<CurrentUser> {
+        let verified = self.verified.lock().unwrap();
+        verified
+            .get(&key(credentials))
+            .filter(|(_, at)| at.elapsed() < VERIFIED_FOR)
+            .map(|(user, _)| user.clone())
+    }
+
+    pub(super) fn remember(&self, credentials: &str, user: &CurrentUser) {
+        let mut verified = self.verified.lock().unwrap();
+        verified.retain(|_, (_, at)| at.elapsed() < VERIFIED_FOR);
+        verified.insert(key(credentials), (user.clone(), Instant::now()));
+    }
+
+    /// Checks whether the `client` failed too often to be allowed to try again yet.
+    pub(super) fn is_throttled(&self, client: IpAddr) -> bool {
+        self.failures.is_throttled(&client)
+    }
+
+    pub(super) fn record_failure(&self, client: IpAddr) {
+        self.failures.record_failure(client);
+    }
+
+    pub(super) fn record_success(&self, client: IpAddr) {
+        self.failures.record_success(&client);
+    }
+}
+
+fn key(credentials: &str) -> [u8; 32] {
+    Sha256::digest(credentials.as_bytes()).into()
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::BasicCredentials;
+    use crate::server::{failed_attempts::MAX_FAILURES, CurrentUser};
+
+    #[test]
+    fn test_verified_credentials() {
+        let credentials = BasicCredentials::default();
+        let user = CurrentUser {
+            name: "alice".to_owned(),
+            is_admin: false,
+        };
+        assert!(credentials.verified("YWxpY2U6c2VjcmV0").is_none());
+        credentials.remember("YWxpY2U6c2VjcmV0", &user);
+        assert_eq!(
+            credentials
+                .verified("YWxpY2U6c2VjcmV0")
+                .map(|user| user.name),
+            Some("alice".to_owned())
+        );
+        assert!(credentials.verified("YWxpY2U6b3RoZXI=").is_none());
+    }
+
+    #[test]
+    fn test_throttled_failures() {
+        let credentials = BasicCredentials::default();
+        let client = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let other = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        for _ in 0..MAX_FAILURES {
+            assert!(!credentials.is_throttled(client));
+            credentials.record_failure(client);
+        }
+        assert!(credentials.is_throttled(client));
+        assert!(!credentials.is_throttled(other));
+        credentials.record_success(client);
+        assert!(!credentials.is_throttled(client));
+    }
+}
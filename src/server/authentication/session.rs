@@ -0,0 +1,297 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use actix_session::{Session, SessionExt, SessionInsertError};
+use actix_web::{dev::ServiceRequest, web};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use super::CurrentUser;
+use crate::{server::oidc::OidcProvider, users::UserStore};
+
+/// Session key under which the signed-in user is kept.
+const SESSION_USER_KEY: &str = "user";
+
+/// Sessions not used for this long are signed out.
+pub(crate) const IDLE_TIMEOUT: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Sessions are signed out this long after signing in, even when in use.
+pub(crate) const MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// The last use of a session is updated at most this often, so not every response
+/// sets the cookie again.
+const SEEN_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How the user of a session signed in, which decides where the user is looked up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum SignInMethod {
+    Password,
+    Oidc,
+}
+
+/// Sign-in kept in the session cookie. The cookie only names the user, whose account and
+/// administrator flag are looked up again on each request.
+#[derive(Debug, Serialize, Deserialize)]
+struct SignedIn {
+    id: String,
+    name: String,
+    method: SignInMethod,
+    /// Seconds since the Unix epoch.
+    signed_in_at: u64,
+    /// Seconds since the Unix epoch.
+    seen_at: u64,
+}
+
+impl SignedIn {
+    fn is_expired(&self, now: u64) -> bool {
+        now >= self.signed_in_at.saturating_add(MAX_AGE.as_secs())
+            || now >= self.seen_at.saturating_add(IDLE_TIMEOUT.as_secs())
+    }
+}
+
+/// Sessions signed out before they expired, which are refused even if their cookie is
+/// presented again. Kept in memory, so after a restart only the timeouts apply.
+#[derive(Debug, Default)]
+pub(crate) struct SignedOutSessions {
+    sessions: Mutex<HashMap<String, u64>>,
+}
+
+impl SignedOutSessions {
+    fn contains(&self, id: &str) -> bool {
+        self.sessions.lock().unwrap().contains_key(id)
+    }
+
+    fn insert(&self, signed_in: &SignedIn) {
+        let now = unix_time();
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, signed_in_at| now < *signed_in_at + MAX_AGE.as_secs());
+        sessions.insert(signed_in.id.clone(), signed_in.signed_in_at);
+    }
+}
+
+/// Signs `name` in with a new session identifier, which prevents session fixation.
+pub(crate) fn sign_in(
+    session: &Session,
+    name: &str,
+    method: SignInMethod,
+) -> Result<(), SessionInsertError> {
+    session.renew();
+    let mut id = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut id);
+    let now = unix_time();
+    let signed_in = SignedIn {
+        id: id.iter().map(|byte| format!("{byte:02x}")).collect(),
+        name: name.to_owned(),
+        method,
+        signed_in_at: now,
+        seen_at: now,
+    };
+    session.insert(SESSION_USER_KEY, signed_in)
+}
+
+/// Ends the session, so its cookie is refused from now on.
+pub(crate) fn sign_out(session: &Session, signed_out: Option<&SignedOutSessions>) {
+    if let (Ok(Some(signed_in)), Some(signed_out)) =
+        (session.get::<SignedIn>(SESSION_USER_KEY), signed_out)
+    {
+        signed_out.insert(&signed_in);
+    }
+    session.purge();
+}
+
+/// The user signed in with the session of `req`. Expired or signed-out sessions, and those
+/// of users who no longer have access, are ended.
+pub(super) fn session_user(req: &ServiceRequest) -> Option<CurrentUser> {
+    let session = req.get_session();
+    let signed_in = session.get::<SignedIn>(SESSION_USER_KEY).ok().flatten()?;
+    let now = unix_time();
+    let signed_out = req
+        .app_data::<web::Data<SignedOutSessions>>()
+        .is_some_and(|signed_out| signed_out.contains(&signed_in.id));
+    let user = if signed_out || signed_in.is_expired(now) {
+        None
+    } else {
+        current_user(req, &signed_in)
+    };
+    match user {
+        Some(user) => {
+            if now >= signed_in.seen_at + SEEN_INTERVAL.as_secs() {
+                let signed_in = SignedIn {
+                    seen_at: now,
+                    ..signed_in
+                };
+                if let Err(e) = session.insert(SESSION_USER_KEY, signed_in) {
+                    debug!("Failed to update session: {:?}", e);
+                }
+            }
+            Some(user)
+        }
+        None => {
+            debug!("Session of {} ended", signed_in.name);
+            session.purge();
+            None
+        }
+    }
+}
+
+/// Looks the signed-in user up where they signed in.
+fn current_user(req: &ServiceRequest, signed_in: &SignedIn) -> Option<CurrentUser> {
+    match signed_in.method {
+        SignInMethod::Password => {
+            let users = req.app_data::<web::Data<UserStore>>()?;
+            let account = users.account(&signed_in.name)?;
+            Some(CurrentUser {
+                name: account.name.clone(),
+                is_admin: account.admin,
+            })
+        }
+        SignInMethod::Oidc => req
+            .app_data::<web::Data<OidcProvider>>()?
+            .user(&signed_in.name),
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+#[cfg(test)]
+mod test {
+    use actix_session::Session;
+    use actix_web::{
+        cookie::{Cookie, Key},
+        http::StatusCode,
+        test::{self, TestRequest},
+        web, App, HttpResponse,
+    };
+
+    use super::{
+        sign_in, sign_out, unix_time, SignInMethod, SignedIn, SignedOutSessions, IDLE_TIMEOUT,
+        MAX_AGE, SESSION_USER_KEY,
+    };
+    use crate::{
+        server::{session_middleware, Authentication, CurrentUser, LOGIN_PATH},
+        users::{hash_password, UserStore},
+    };
+
+    #[derive(serde::Deserialize)]
+    struct SignInQuery {
+        name: String,
+        /// Seconds since signing in.
+        age: Option<u64>,
+        /// Seconds since the last request.
+        idle: Option<u64>,
+    }
+
+    async fn test_sign_in(session: Session, query: web::Query<SignInQuery>) -> HttpResponse {
+        sign_in(&session, &query.name, SignInMethod::Password).unwrap();
+        if query.age.is_some() || query.idle.is_some() {
+            let mut signed_in = session.get::<SignedIn>(SESSION_USER_KEY).unwrap().unwrap();
+            signed_in.signed_in_at -= query.age.unwrap_or_default();
+            signed_in.seen_at -= query.idle.unwrap_or_default();
+            session.insert(SESSION_USER_KEY, signed_in).unwrap();
+        }
+        HttpResponse::Ok().finish()
+    }
+
+    async fn test_sign_out(
+        session: Session,
+        signed_out: web::Data<SignedOutSessions>,
+    ) -> HttpResponse {
+        sign_out(&session, Some(&signed_out));
+        HttpResponse::Ok().finish()
+    }
+
+    #[actix_web::test]
+    async fn test_sessions() {
+        let users: UserStore = toml::from_str(&format!(
+            "[[users]]\nname = \"alice\"\npassword_hash = \"{}\"\nadmin = true\n",
+            hash_password("secret"),
+        ))
+        .unwrap();
+        let app = test::init_service(
+            App::new()
+                .wrap(Authentication)
+                .wrap(session_middleware(Key::generate(), false))
+                .app_data(web::Data::new(users))
+                .app_data(web::Data::new(SignedOutSessions::default()))
+                .route(LOGIN_PATH, web::get().to(test_sign_in))
+                .route(LOGIN_PATH, web::post().to(test_sign_out))
+                .route(
+                    "/whoami",
+                    web::get().to(|user: web::ReqData<CurrentUser>| async move {
+                        HttpResponse::Ok().body(format!("{} {}", user.name, user.is_admin))
+                    }),
+                ),
+        )
+        .await;
+        let app = &app;
+        let sign_in = |query: String| async move {
+            let request = TestRequest::get()
+                .uri(&format!("{LOGIN_PATH}?{query}"))
+                .to_request();
+            let response = test::call_service(app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            response.response().cookies().next().unwrap().into_owned()
+        };
+        let whoami = |cookie: Cookie<'static>| async move {
+            let request = TestRequest::get()
+                .uri("/whoami")
+                .cookie(cookie)
+                .to_request();
+            let response = test::call_service(app, request).await;
+            let status = response.status();
+            (status, test::read_body(response).await)
+        };
+
+        // the administrator flag comes from the user store
+        let cookie = sign_in("name=alice".to_owned()).await;
+        assert_eq!(
+            whoami(cookie.clone()).await,
+            (StatusCode::OK, "alice true".into())
+        );
+        let cookie = sign_in("name=carol".to_owned()).await;
+        assert_eq!(whoami(cookie).await.0, StatusCode::UNAUTHORIZED);
+
+        let idle = IDLE_TIMEOUT.as_secs();
+        let cookie = sign_in(format!("name=alice&idle={}", idle - 60)).await;
+        assert_eq!(whoami(cookie).await.0, StatusCode::OK);
+        let cookie = sign_in(format!("name=alice&idle={idle}")).await;
+        assert_eq!(whoami(cookie).await.0, StatusCode::UNAUTHORIZED);
+        let cookie = sign_in(format!("name=alice&age={}", MAX_AGE.as_secs())).await;
+        assert_eq!(whoami(cookie).await.0, StatusCode::UNAUTHORIZED);
+
+        // the cookie of a signed-out session is refused when presented again
+        let cookie = sign_in("name=alice".to_owned()).await;
+        let request = TestRequest::post()
+            .uri(LOGIN_PATH)
+            .cookie(cookie.clone())
+            .to_request();
+        assert_eq!(
+            test::call_service(app, request).await.status(),
+            StatusCode::OK
+        );
+        assert_eq!(whoami(cookie).await.0, StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_expiry() {
+        let now = unix_time();
+        let signed_in = SignedIn {
+            id: "id".to_owned(),
+            name: "alice".to_owned(),
+            method: SignInMethod::Oidc,
+            signed_in_at: now,
+            seen_at: now,
+        };
+        assert!(!signed_in.is_expired(now));
+        assert!(signed_in.is_expired(now + IDLE_TIMEOUT.as_secs()));
+        assert!(!signed_in.is_expired(now + IDLE_TIMEOUT.as_secs() - 1));
+    }
+}
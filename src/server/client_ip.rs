@@ -0,0 +1,60 @@
+use std::net::{IpAddr, SocketAddr};
+
+use actix_web::{web, HttpRequest};
+
+/// Reverse proxies whose `Forwarded` or `X-Forwarded-For` headers tell the address of the client,
+/// configured with `TRUSTED_PROXIES`.
+#[derive(Debug, Default)]
+pub(crate) struct TrustedProxies(pub(crate) Vec<IpAddr>);
+
+/// Address of the client making `req`. Forwarded headers can be set by anyone, so they are only
+/// used when the connection comes from a trusted proxy.
+pub(crate) fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    let is_trusted = req
+        .app_data::<web::Data<TrustedProxies>>()
+        .is_some_and(|proxies| proxies.0.contains(&peer));
+    if !is_trusted {
+        return Some(peer);
+    }
+    let forwarded = req
+        .connection_info()
+        .realip_remote_addr()
+        .and_then(|address| {
+            address.parse::<IpAddr>().ok().or_else(|| {
+                address
+                    .parse::<SocketAddr>()
+                    .ok()
+                    .map(|address| address.ip())
+            })
+        });
+    Some(forwarded.unwrap_or(peer))
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::IpAddr;
+
+    use actix_web::{test::TestRequest, web};
+
+    use super::{client_ip, TrustedProxies};
+
+    #[test]
+    fn test_forwarded_only_by_trusted_proxies() {
+        let request = |proxies: Vec<IpAddr>| {
+            TestRequest::default()
+                .peer_addr("10.0.0.1:40000".parse().unwrap())
+                .insert_header(("X-Forwarded-For", "203.0.113.7"))
+                .app_data(web::Data::new(TrustedProxies(proxies)))
+                .to_http_request()
+        };
+        assert_eq!(
+            client_ip(&request(Vec::new())),
+            Some("10.0.0.1".parse().unwrap())
+        );
+        assert_eq!(
+            client_ip(&request(vec!["10.0.0.1".parse().unwrap()])),
+            Some("203.0.113.7".parse().unwrap())
+        );
+    }
+}
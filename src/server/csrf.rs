@@ -0,0 +1,192 @@
+use std::{
+    future::{ready, Future, Ready},
+    pin::Pin,
+    rc::Rc,
+};
+
+use actix_session::{Session, SessionExt};
+use actix_web::{
+    body::EitherBody,
+    dev::{self, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{header, Method},
+    web, Error, FromRequest, HttpResponse,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::RngCore;
+use tracing::{debug, warn};
+
+use crate::client_certificates::ClientCertificate;
+
+/// Session key of the synchronizer token.
+const SESSION_CSRF_TOKEN_KEY: &str = "csrf_token";
+/// Header carrying the token in HTMX requests.
+const CSRF_HEADER: &str = "X-CSRF-Token";
+/// Form field carrying the token in plain HTML forms.
+const CSRF_FORM_FIELD: &str = "csrf_token";
+
+/// Returns the session's CSRF token, creating it on first use.
+pub(crate) fn csrf_token(session: &Session) -> String {
+    if let Ok(Some(token)) = session.get::<String>(SESSION_CSRF_TOKEN_KEY) {
+        return token;
+    }
+    let mut token = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut token);
+    let token = URL_SAFE_NO_PAD.encode(token);
+    if let Err(e) = session.insert(SESSION_CSRF_TOKEN_KEY, &token) {
+        warn!("Failed to store CSRF token: {:?}", e);
+    }
+    token
+}
+
+/// Rejects state-changing requests which do not carry the session's CSRF token, either in
+/// the [`CSRF_HEADER`] header or in the `csrf_token` field of URL-encoded forms.
+///
+/// Requests authenticated with `Authorization: Bearer`, or WebDAV requests with any
+/// `Authorization` header, do not rely on cookies and are exempt, as are requests without
+/// an `Origin` header over connections with a client certificate.
+/// Requires the session middleware to be registered as an outer layer.
+pub(crate) struct CsrfProtection;
+
+impl<S, B> Transform<S, ServiceRequest> for CsrfProtection
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = CsrfProtectionMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(CsrfProtectionMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub(crate) struct CsrfProtectionMiddleware<S> {
+    // shared with the response future, which reads the form before calling the service
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for CsrfProtectionMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        Box::pin(async move {
+            if requires_token(&req) {
+                let expected = req
+                    .get_session()
+                    .get::<String>(SESSION_CSRF_TOKEN_KEY)
+                    .ok()
+                    .flatten();
+                let provided = match header_token(&req) {
+                    Some(token) => Some(token),
+                    None if is_form(&req) => form_token(&mut req).await,
+                    None => None,
+                };
+                if !expected.is_some_and(|expected| {
+                    provided.is_some_and(|provided| tokens_match(&expected, &provided))
+                }) {
+                    debug!("Missing or invalid CSRF token for {:?}", req.path());
+                    let response = HttpResponse::Forbidden().body("Invalid CSRF token");
+                    return Ok(req.into_response(response).map_into_right_body());
+                }
+            }
+            // forwarded responses map to "left" body
+            service
+                .call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body)
+        })
+    }
+
+    dev::forward_ready!(service);
+}
+
+fn requires_token(req: &ServiceRequest) -> bool {
+    let safe_method = matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    let authorization = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    let bearer = authorization.is_some_and(|value| value.starts_with("Bearer "));
+    let dav_client = authorization.is_some() && crate::webdav::is_dav_path(req.path());
+    // browsers send the origin of every unsafe request, other clients use their certificate
+    let certificate_client = req.conn_data::<ClientCertificate>().is_some()
+        && !req.headers().contains_key(header::ORIGIN);
+    !safe_method && !bearer && !dav_client && !certificate_client
+}
+
+fn header_token(req: &ServiceRequest) -> Option<String> {
+    req.headers()
+        .get(CSRF_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_owned())
+}
+
+fn is_form(req: &ServiceRequest) -> bool {
+    req.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"))
+}
+
+/// Reads the token from the form, putting the body back for the handler.
+async fn form_token(req: &mut ServiceRequest) -> Option<String> {
+    let (http_req, payload) = req.parts_mut();
+    let body = web::Bytes::from_request(http_req, payload).await.ok()?;
+    let token = token_from_form(&body);
+    req.set_payload(dev::Payload::from(body));
+    token
+}
+
+fn token_from_form(body: &[u8]) -> Option<String> {
+    serde_urlencoded::from_bytes::<Vec<(String, String)>>(body)
+        .ok()?
+        .into_iter()
+        .find(|(name, _)| name == CSRF_FORM_FIELD)
+        .map(|(_, value)| value)
+}
+
+/// Compares tokens in constant time.
+fn tokens_match(expected: &str, provided: &str) -> bool {
+    expected.len() == provided.len()
+        && expected
+            .bytes()
+            .zip(provided.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::{token_from_form, tokens_match};
+
+    #[test]
+    fn test_token_from_form() {
+        assert_eq!(
+            token_from_form(b"username=alice&csrf_token=abc-123&next=%2F").as_deref(),
+            Some("abc-123")
+        );
+        assert_eq!(token_from_form(b"username=alice"), None);
+    }
+
+    #[test]
+    fn test_tokens_match() {
+        assert!(tokens_match("abc", "abc"));
+        assert!(!tokens_match("abc", "abd"));
+        assert!(!tokens_match("abc", "abcd"));
+        assert!(!tokens_match("abc", ""));
+    }
+}
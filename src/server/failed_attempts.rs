@@ -0,0 +1,49 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Failed attempts after which further attempts are refused without verification.
+pub(crate) const MAX_FAILURES: u32 = 5;
+
+/// Time after the last failed attempt until a refused client may try again.
+pub(crate) const FAILURE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Failed attempts to guess a password, counted per key (e.g. the client's address), so
+/// clients failing repeatedly are refused for a while.
+#[derive(Debug)]
+pub(crate) struct FailedAttempts<K> {
+    failures: Mutex<HashMap<K, (u32, Instant)>>,
+}
+
+impl<K> Default for FailedAttempts<K> {
+    fn default() -> Self {
+        Self {
+            failures: Mutex::default(),
+        }
+    }
+}
+
+impl<K: Eq + Hash> FailedAttempts<K> {
+    /// Checks whether `key` failed too often to be allowed to try again yet.
+    pub(crate) fn is_throttled(&self, key: &K) -> bool {
+        let failures = self.failures.lock().unwrap();
+        failures
+            .get(key)
+            .is_some_and(|(count, last)| *count >= MAX_FAILURES && last.elapsed() < FAILURE_WINDOW)
+    }
+
+    pub(crate) fn record_failure(&self, key: K) {
+        let mut failures = self.failures.lock().unwrap();
+        failures.retain(|_, (_, last)| last.elapsed() < FAILURE_WINDOW);
+        let (count, last) = failures.entry(key).or_insert((0, Instant::now()));
+        *count += 1;
+        *last = Instant::now();
+    }
+
+    pub(crate) fn record_success(&self, key: &K) {
+        self.failures.lock().unwrap().remove(key);
+    }
+}
@@ -0,0 +1,109 @@
+use std::{
+    future::{ready, Future, Ready},
+    pin::Pin,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use actix_web::{
+    body::{BodySize, MessageBody},
+    dev::{self, Service, ServiceRequest, ServiceResponse, Transform},
+    web::Bytes,
+    Error,
+};
+use prometheus::IntCounter;
+
+use crate::metrics::{metrics, Download};
+
+/// Counts requests and their durations per route, and the bytes of [`Download`] responses.
+///
+/// Routes are the patterns of the matched resources, e.g. `/{path:.*}`, so the number of
+/// time series does not grow with the drive.
+pub(crate) struct RequestMetrics;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestMetrics
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + Unpin + 'static,
+{
+    type Response = ServiceResponse<CountedBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestMetricsMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestMetricsMiddleware { service }))
+    }
+}
+
+pub(crate) struct RequestMetricsMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestMetricsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + Unpin + 'static,
+{
+    type Response = ServiceResponse<CountedBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let started = Instant::now();
+        let method = req.method().to_string();
+        let route = req
+            .match_pattern()
+            .unwrap_or_else(|| "unmatched".to_owned());
+        let r = self.service.call(req);
+
+        Box::pin(async move {
+            let response = r.await?;
+            let status = response.status();
+            let metrics = metrics();
+            metrics
+                .requests
+                .with_label_values(&[&method, &route, status.as_str()])
+                .inc();
+            metrics
+                .request_duration
+                .with_label_values(&[&method, &route])
+                .observe(started.elapsed().as_secs_f64());
+            let counter = (status.is_success()
+                && response.response().extensions().contains::<Download>())
+            .then(|| metrics.downloaded_bytes.clone());
+            Ok(response.map_body(|_, body| CountedBody { body, counter }))
+        })
+    }
+
+    dev::forward_ready!(service);
+}
+
+/// Response body adding the size of every chunk sent to `counter`.
+pub(crate) struct CountedBody<B> {
+    body: B,
+    counter: Option<IntCounter>,
+}
+
+impl<B: MessageBody + Unpin> MessageBody for CountedBody<B> {
+    type Error = B::Error;
+
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let this = self.get_mut();
+        let chunk = Pin::new(&mut this.body).poll_next(cx);
+        if let (Some(counter), Poll::Ready(Some(Ok(bytes)))) = (&this.counter, &chunk) {
+            counter.inc_by(bytes.len() as u64);
+        }
+        chunk
+    }
+}
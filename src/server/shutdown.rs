@@ -0,0 +1,257 @@
+use std::{
+    future::{ready, Future, Ready},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use actix_web::{
+    body::EitherBody,
+    dev::{self, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{header, Method, StatusCode},
+    web, Error, HttpResponse, ResponseError,
+};
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+
+use super::authentication::is_api_path;
+use crate::webservices::Problem;
+
+/// Seconds after which clients refused during a shutdown are asked to retry.
+const RETRY_AFTER_SECONDS: u32 = 10;
+
+/// Coordinates stopping the server on SIGTERM or SIGINT: new uploads are refused while
+/// the ones in progress may finish within the grace period.
+#[derive(Debug, Clone)]
+pub(crate) struct Shutdown {
+    requested: Arc<watch::Sender<bool>>,
+    uploads: Arc<AtomicUsize>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self {
+            requested: Arc::new(watch::Sender::new(false)),
+            uploads: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+impl Shutdown {
+    /// Spawns a task requesting the shutdown when the process receives SIGTERM or SIGINT.
+    pub(crate) fn listen_for_signals(&self) {
+        let shutdown = self.clone();
+        actix_web::rt::spawn(async move {
+            #[cfg(unix)]
+            {
+                use actix_web::rt::signal::unix::{signal, SignalKind};
+
+                match signal(SignalKind::terminate()) {
+                    Ok(mut terminate) => {
+                        futures_util::future::select(
+                            Box::pin(terminate.recv()),
+                            Box::pin(actix_web::rt::signal::ctrl_c()),
+                        )
+                        .await;
+                    }
+                    Err(e) => {
+                        warn!("Cannot listen for SIGTERM: {:?}", e);
+                        let _ = actix_web::rt::signal::ctrl_c().await;
+                    }
+                }
+            }
+            #[cfg(not(unix))]
+            let _ = actix_web::rt::signal::ctrl_c().await;
+            info!("Received a termination signal, shutting down");
+            shutdown.request();
+        });
+    }
+
+    /// Starts the shutdown, e.g. after a signal or when the server cannot be reached anymore.
+    pub(crate) fn request(&self) {
+        self.requested.send_replace(true);
+    }
+
+    pub(crate) fn is_requested(&self) -> bool {
+        *self.requested.borrow()
+    }
+
+    /// Completes once the shutdown is requested.
+    pub(crate) async fn requested(&self) {
+        let mut requested = self.requested.subscribe();
+        // the sender lives as long as `self`
+        let _ = requested.wait_for(|requested| *requested).await;
+    }
+
+    pub(crate) fn uploads_in_progress(&self) -> usize {
+        self.uploads.load(Ordering::SeqCst)
+    }
+
+    /// Registers an upload, `None` when the server is shutting down and must not start it.
+    fn start_upload(&self, path: &str) -> Option<Upload> {
+        if self.is_requested() {
+            return None;
+        }
+        self.uploads.fetch_add(1, Ordering::SeqCst);
+        Some(Upload {
+            shutdown: self.clone(),
+            path: path.to_owned(),
+            finished: false,
+        })
+    }
+}
+
+/// Upload in progress, reported when it is dropped unfinished, i.e. cut off by the shutdown.
+struct Upload {
+    shutdown: Shutdown,
+    path: String,
+    finished: bool,
+}
+
+impl Drop for Upload {
+    fn drop(&mut self) {
+        self.shutdown.uploads.fetch_sub(1, Ordering::SeqCst);
+        if !self.finished {
+            if self.shutdown.is_requested() {
+                warn!(
+                    "Upload to {:?} did not finish within the shutdown grace period",
+                    self.path
+                );
+            } else {
+                debug!("Upload to {:?} was cancelled", self.path);
+            }
+        }
+    }
+}
+
+/// Tracks uploads for [`Shutdown`] and rejects new ones with 503 once it is requested.
+///
+/// Uploads are PUT requests (forms, upload links and WebDAV) and multipart POST requests
+/// (the API). Requires the [`Shutdown`] as application data.
+pub(crate) struct UploadTracking;
+
+impl<S, B> Transform<S, ServiceRequest> for UploadTracking
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = UploadTrackingMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(UploadTrackingMiddleware { service }))
+    }
+}
+
+pub(crate) struct UploadTrackingMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for UploadTrackingMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let shutdown = req.app_data::<web::Data<Shutdown>>().cloned();
+        let upload = match shutdown {
+            Some(shutdown) if is_upload(&req) => match shutdown.start_upload(req.path()) {
+                Some(upload) => Some(upload),
+                None => {
+                    debug!("Refused upload to {:?} during shutdown", req.path());
+                    let response = shutting_down(&req);
+                    return Box::pin(async move {
+                        actix_web::Result::Ok(req.into_response(response).map_into_right_body())
+                    });
+                }
+            },
+            _ => None,
+        };
+        let r = self.service.call(req);
+
+        Box::pin(async move {
+            let response = r.await;
+            if let Some(mut upload) = upload {
+                upload.finished = true;
+            }
+            response.map(ServiceResponse::map_into_left_body)
+        })
+    }
+
+    dev::forward_ready!(service);
+}
+
+fn is_upload(req: &ServiceRequest) -> bool {
+    match *req.method() {
+        Method::PUT => true,
+        Method::POST => req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("multipart/form-data")),
+        _ => false,
+    }
+}
+
+fn shutting_down(req: &ServiceRequest) -> HttpResponse {
+    const DETAIL: &str = "The server is restarting, try again shortly";
+    let mut response = if is_api_path(req.path()) {
+        Problem::new(StatusCode::SERVICE_UNAVAILABLE)
+            .with_detail(DETAIL)
+            .error_response()
+    } else {
+        HttpResponse::ServiceUnavailable().body(DETAIL)
+    };
+    response.headers_mut().insert(
+        header::RETRY_AFTER,
+        header::HeaderValue::from(RETRY_AFTER_SECONDS),
+    );
+    response
+}
+
+#[cfg(test)]
+mod test {
+    use actix_web::{
+        http::{Method, StatusCode},
+        test::{self, TestRequest},
+        web, App, HttpResponse,
+    };
+
+    use super::{Shutdown, UploadTracking};
+
+    #[actix_web::test]
+    async fn test_uploads_refused_during_shutdown() {
+        let shutdown = Shutdown::default();
+        let app = test::init_service(
+            App::new()
+                .wrap(UploadTracking)
+                .app_data(web::Data::new(shutdown.clone()))
+                .default_service(web::to(HttpResponse::Ok)),
+        )
+        .await;
+        let request = |method| TestRequest::default().method(method).uri("/a.txt");
+
+        let response = test::call_service(&app, request(Method::PUT).to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(shutdown.uploads_in_progress(), 0);
+
+        shutdown.request();
+        shutdown.requested().await;
+        let response = test::call_service(&app, request(Method::PUT).to_request()).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(response.headers().contains_key("Retry-After"));
+        let response = test::call_service(&app, request(Method::GET).to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,424 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+/// Address prefix of share links.
+pub(crate) const SHARE_PATH: &str = "/s";
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub(crate) enum ShareError {
+    #[error("Invalid share link")]
+    InvalidLink,
+    #[error("Share link has been revoked")]
+    Revoked,
+    #[error("Share link has expired")]
+    Expired,
+    #[error("Download limit of the share link has been reached")]
+    DownloadLimitReached,
+    #[error("Upload limit of the link has been reached")]
+    UploadLimitReached,
+    #[error("Upload exceeds the limits of the link")]
+    UploadLimitExceeded,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ShareKind {
+    /// Read-only view of the shared file or folder.
+    #[default]
+    View,
+    /// Upload form for the shared folder which shows none of its contents.
+    Upload,
+}
+
+/// File or folder made available to people without drive accounts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Share {
+    pub(crate) id: String,
+    #[serde(default)]
+    pub(crate) kind: ShareKind,
+    /// User who created the share, `None` when authentication is disabled.
+    pub(crate) owner: Option<String>,
+    #[serde(default)]
+    pub(crate) owner_is_admin: bool,
+    /// Shared entry, relative to the base directory.
+    pub(crate) path: PathBuf,
+    pub(crate) is_dir: bool,
+    pub(crate) created_at: u64,
+    pub(crate) expires_at: u64,
+    pub(crate) max_downloads: Option<u32>,
+    #[serde(default)]
+    pub(crate) downloads: u32,
+    /// Number of files which can be uploaded with an upload link.
+    #[serde(default)]
+    pub(crate) max_uploads: Option<u32>,
+    /// Total size in bytes of files which can be uploaded with an upload link.
+    #[serde(default)]
+    pub(crate) max_upload_size: Option<u64>,
+    #[serde(default)]
+    pub(crate) uploads: u32,
+    #[serde(default)]
+    pub(crate) uploaded_bytes: u64,
+    password_hash: Option<String>,
+}
+
+impl Share {
+    pub(crate) fn has_password(&self) -> bool {
+        self.password_hash.is_some()
+    }
+
+    pub(crate) fn verify_password(&self, password: &str) -> bool {
+        self.password_hash
+            .as_deref()
+            .is_none_or(|hash| crate::users::verify_password(hash, password))
+    }
+
+    pub(crate) fn is_expired(&self, now: u64) -> bool {
+        self.expires_at <= now
+    }
+
+    fn check_usable(&self, now: u64) -> Result<(), ShareError> {
+        if self.is_expired(now) {
+            return Err(ShareError::Expired);
+        }
+        if self.max_downloads.is_some_and(|max| self.downloads >= max) {
+            return Err(ShareError::DownloadLimitReached);
+        }
+        if self.max_uploads.is_some_and(|max| self.uploads >= max)
+            || self
+                .max_upload_size
+                .is_some_and(|max| self.uploaded_bytes >= max)
+        {
+            return Err(ShareError::UploadLimitReached);
+        }
+        Ok(())
+    }
+}
+
+/// Parameters of a new share.
+pub(crate) struct NewShare<'a> {
+    pub(crate) kind: ShareKind,
+    pub(crate) owner: Option<String>,
+    pub(crate) owner_is_admin: bool,
+    pub(crate) path: PathBuf,
+    pub(crate) is_dir: bool,
+    pub(crate) expires_in: Duration,
+    pub(crate) max_downloads: Option<u32>,
+    pub(crate) max_uploads: Option<u32>,
+    pub(crate) max_upload_size: Option<u64>,
+    pub(crate) password: Option<&'a str>,
+}
+
+/// Shares persisted in the JSON file configured with `SHARES_FILE`.
+///
+/// Links carry the share identifier and expiry signed with `SHARE_SECRET`, so forged or
+/// altered links are rejected before the store is consulted. Removing a share from the
+/// store revokes its links.
+#[derive(Debug)]
+pub(crate) struct ShareStore {
+    file: PathBuf,
+    key: Vec<u8>,
+    shares: Mutex<Vec<Share>>,
+}
+
+impl ShareStore {
+    pub(crate) fn load(file: &Path, secret: &str) -> anyhow::Result<Self> {
+        if secret.len() < 32 {
+            return Err(anyhow::anyhow!(
+                "SHARE_SECRET must be at least 32 bytes long"
+            ));
+        }
+        let shares = if file.exists() {
+            let contents =
+                std::fs::read_to_string(file).context(format!("Failed to read {:?}", file))?;
+            serde_json::from_str(&contents).context(format!("Failed to parse {:?}", file))?
+        } else {
+            Vec::new()
+        };
+        Ok(Self {
+            file: file.to_path_buf(),
+            key: secret.as_bytes().to_vec(),
+            shares: Mutex::new(shares),
+        })
+    }
+
+    /// Stores a new share and returns it with the token identifying it in links.
+    pub(crate) fn create(&self, new_share: NewShare) -> anyhow::Result<(Share, String)> {
+        let now = now();
+        let mut id = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut id);
+        let share = Share {
+            id: URL_SAFE_NO_PAD.encode(id),
+            kind: new_share.kind,
+            owner: new_share.owner,
+            owner_is_admin: new_share.owner_is_admin,
+            path: new_share.path,
+            is_dir: new_share.is_dir,
+            created_at: now,
+            expires_at: now + new_share.expires_in.as_secs(),
+            max_downloads: new_share.max_downloads,
+            downloads: 0,
+            max_uploads: new_share.max_uploads,
+            max_upload_size: new_share.max_upload_size,
+            uploads: 0,
+            uploaded_bytes: 0,
+            password_hash: new_share.password.map(crate::users::hash_password),
+        };
+        let mut shares = self.shares.lock().unwrap();
+        // expired shares are of no use to anyone
+        shares.retain(|share| !share.is_expired(now));
+        shares.push(share.clone());
+        self.save(&shares)?;
+        let token = self.token(&share);
+        Ok((share, token))
+    }
+
+    /// Link token: share identifier and expiry followed by their signature.
+    pub(crate) fn token(&self, share: &Share) -> String {
+        let payload = format!("{}.{}", share.id, share.expires_at);
+        let signature = URL_SAFE_NO_PAD.encode(self.mac(&payload).finalize().into_bytes());
+        format!("{payload}.{signature}")
+    }
+
+    /// Finds the share identified by a link token, as long as it can still be used.
+    pub(crate) fn resolve(&self, token: &str) -> Result<Share, ShareError> {
+        let (payload, signature) = token.rsplit_once('.').ok_or(ShareError::InvalidLink)?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| ShareError::InvalidLink)?;
+        self.mac(payload)
+            .verify_slice(&signature)
+            .map_err(|_| ShareError::InvalidLink)?;
+        let (id, expires_at) = payload.split_once('.').ok_or(ShareError::InvalidLink)?;
+        let expires_at = expires_at
+            .parse::<u64>()
+            .map_err(|_| ShareError::InvalidLink)?;
+        if expires_at <= now() {
+            return Err(ShareError::Expired);
+        }
+        let shares = self.shares.lock().unwrap();
+        let share = shares
+            .iter()
+            .find(|share| share.id == id && share.expires_at == expires_at)
+            .ok_or(ShareError::Revoked)?;
+        share.check_usable(now())?;
+        Ok(share.clone())
+    }
+
+    /// Counts a download, failing when the share's download limit is already reached.
+    pub(crate) fn record_download(&self, id: &str) -> Result<(), ShareError> {
+        let mut shares = self.shares.lock().unwrap();
+        let share = shares
+            .iter_mut()
+            .find(|share| share.id == id)
+            .ok_or(ShareError::Revoked)?;
+        share.check_usable(now())?;
+        share.downloads += 1;
+        if let Err(e) = self.save(&shares) {
+            tracing::warn!("Failed to save download count: {:?}", e);
+        }
+        Ok(())
+    }
+
+    /// Reserves `count` files of `bytes` total size from the limits of an upload link.
+    pub(crate) fn record_upload(&self, id: &str, count: u32, bytes: u64) -> Result<(), ShareError> {
+        let mut shares = self.shares.lock().unwrap();
+        let share = shares
+            .iter_mut()
+            .find(|share| share.id == id)
+            .ok_or(ShareError::Revoked)?;
+        share.check_usable(now())?;
+        let uploads = share.uploads.saturating_add(count);
+        let uploaded_bytes = share.uploaded_bytes.saturating_add(bytes);
+        if share.max_uploads.is_some_and(|max| uploads > max)
+            || share
+                .max_upload_size
+                .is_some_and(|max| uploaded_bytes > max)
+        {
+            return Err(ShareError::UploadLimitExceeded);
+        }
+        share.uploads = uploads;
+        share.uploaded_bytes = uploaded_bytes;
+        if let Err(e) = self.save(&shares) {
+            tracing::warn!("Failed to save upload count: {:?}", e);
+        }
+        Ok(())
+    }
+
+    /// Shares created by `owner`, newest first.
+    pub(crate) fn list(&self, owner: Option<&str>) -> Vec<Share> {
+        let mut shares = self
+            .shares
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|share| share.owner.as_deref() == owner)
+            .cloned()
+            .collect::<Vec<_>>();
+        shares.sort_by_key(|share| std::cmp::Reverse(share.created_at));
+        shares
+    }
+
+    /// Removes the share, returning `false` when it does not exist or belongs to someone else
+    /// (administrators can revoke any share).
+    pub(crate) fn revoke(
+        &self,
+        id: &str,
+        owner: Option<&str>,
+        is_admin: bool,
+    ) -> anyhow::Result<bool> {
+        let mut shares = self.shares.lock().unwrap();
+        let Some(index) = shares
+            .iter()
+            .position(|share| share.id == id && (is_admin || share.owner.as_deref() == owner))
+        else {
+            return Ok(false);
+        };
+        shares.remove(index);
+        self.save(&shares)?;
+        Ok(true)
+    }
+
+    fn mac(&self, payload: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC accepts any key");
+        mac.update(payload.as_bytes());
+        mac
+    }
+
+    fn save(&self, shares: &[Share]) -> anyhow::Result<()> {
+        let contents = serde_json::to_string_pretty(shares)?;
+        // replace the file atomically, so a crash cannot leave it truncated
+        let temp_file = self.file.with_extension("tmp");
+        std::fs::write(&temp_file, contents).context(format!("Failed to write {:?}", temp_file))?;
+        std::fs::rename(&temp_file, &self.file).context(format!("Failed to write {:?}", self.file))
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::PathBuf, time::Duration};
+
+    use super::{NewShare, ShareError, ShareKind, ShareStore};
+
+    const SECRET: &str = "0123456789abcdef0123456789abcdef";
+
+    fn new_share(max_downloads: Option<u32>, password: Option<&str>) -> NewShare<'_> {
+        NewShare {
+            kind: ShareKind::View,
+            owner: Some("alice".to_owned()),
+            owner_is_admin: false,
+            path: PathBuf::from("docs/report.pdf"),
+            is_dir: false,
+            expires_in: Duration::from_secs(3600),
+            max_downloads,
+            max_uploads: None,
+            max_upload_size: None,
+            password,
+        }
+    }
+
+    #[test]
+    fn test_signed_links() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ShareStore::load(&dir.path().join("shares.json"), SECRET).unwrap();
+        let (share, token) = store.create(new_share(None, None)).unwrap();
+        assert_eq!(store.resolve(&token).unwrap().id, share.id);
+
+        let (payload, _) = token.rsplit_once('.').unwrap();
+        let (id, expires_at) = payload.split_once('.').unwrap();
+        let extended = format!("{id}.{}", expires_at.parse::<u64>().unwrap() + 3600);
+        let forged = format!("{extended}.{}", token.rsplit_once('.').unwrap().1);
+        assert_eq!(store.resolve(&forged).unwrap_err(), ShareError::InvalidLink);
+        assert_eq!(
+            store.resolve("garbage").unwrap_err(),
+            ShareError::InvalidLink
+        );
+
+        let other_store =
+            ShareStore::load(&dir.path().join("other.json"), &SECRET.repeat(2)).unwrap();
+        assert_eq!(
+            other_store.resolve(&token).unwrap_err(),
+            ShareError::InvalidLink
+        );
+    }
+
+    #[test]
+    fn test_download_limit_and_revocation() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("shares.json");
+        let store = ShareStore::load(&file, SECRET).unwrap();
+        let (share, token) = store.create(new_share(Some(2), Some("secret"))).unwrap();
+        assert!(share.verify_password("secret"));
+        assert!(!share.verify_password("guess"));
+
+        store.record_download(&share.id).unwrap();
+        store.record_download(&share.id).unwrap();
+        assert_eq!(
+            store.record_download(&share.id).unwrap_err(),
+            ShareError::DownloadLimitReached
+        );
+        assert_eq!(
+            store.resolve(&token).unwrap_err(),
+            ShareError::DownloadLimitReached
+        );
+
+        // shares survive restarts
+        let store = ShareStore::load(&file, SECRET).unwrap();
+        assert_eq!(store.list(Some("alice")).len(), 1);
+        assert!(!store.revoke(&share.id, Some("bob"), false).unwrap());
+        assert!(store.revoke(&share.id, Some("alice"), false).unwrap());
+        assert_eq!(store.resolve(&token).unwrap_err(), ShareError::Revoked);
+    }
+
+    #[test]
+    fn test_expired_links() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ShareStore::load(&dir.path().join("shares.json"), SECRET).unwrap();
+        let mut expired = new_share(None, None);
+        expired.expires_in = Duration::ZERO;
+        let (_, token) = store.create(expired).unwrap();
+        assert_eq!(store.resolve(&token).unwrap_err(), ShareError::Expired);
+    }
+
+    #[test]
+    fn test_upload_limits() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ShareStore::load(&dir.path().join("shares.json"), SECRET).unwrap();
+        let mut upload = new_share(None, None);
+        upload.kind = ShareKind::Upload;
+        upload.max_uploads = Some(3);
+        upload.max_upload_size = Some(1000);
+        let (share, token) = store.create(upload).unwrap();
+
+        assert_eq!(
+            store.record_upload(&share.id, 4, 10).unwrap_err(),
+            ShareError::UploadLimitExceeded
+        );
+        assert_eq!(
+            store.record_upload(&share.id, 1, 1001).unwrap_err(),
+            ShareError::UploadLimitExceeded
+        );
+        store.record_upload(&share.id, 2, 500).unwrap();
+        store.record_upload(&share.id, 1, 100).unwrap();
+        assert_eq!(
+            store.resolve(&token).unwrap_err(),
+            ShareError::UploadLimitReached
+        );
+    }
+}
@@ -1,27 +1,51 @@
+use opentelemetry_appender_tracing::layer::OpenTelemetryTracingBridge;
 use tracing::level_filters::LevelFilter;
 use tracing::{Level, Subscriber};
+use tracing_subscriber::filter::Targets;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::prelude::*;
 
+mod instruments;
 mod log;
 mod otel;
 
+pub(crate) use instruments::instruments;
+
 pub(crate) fn create_subscriber() -> Box<dyn Subscriber + Send + Sync> {
     let log_layer = log::log_layer();
+    let resource = otel::resource();
 
-    let opentelemetry_layer = otel::init_opentelemetry_tracer()
+    let opentelemetry_layer = otel::init_opentelemetry_tracer(resource.clone())
         .map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer))
         .map(|otel_layer| otel_layer.with_filter(LevelFilter::from_level(Level::DEBUG)));
 
+    // like the other pipelines, metrics are exported when possible only
+    let _ = otel::init_opentelemetry_meter(resource.clone());
+
+    let opentelemetry_logs_layer = otel::init_opentelemetry_logger(resource).map(|provider| {
+        OpenTelemetryTracingBridge::new(&provider).with_filter(exported_log_events())
+    });
+
     let subscriber = tracing_subscriber::Registry::default();
 
     let subscriber = subscriber.with(log_layer);
 
-    if let Ok(opentelemetry_layer) = opentelemetry_layer {
-        Box::new(subscriber.with(opentelemetry_layer))
-    } else {
-        Box::new(subscriber)
-    }
+    Box::new(
+        subscriber
+            .with(opentelemetry_layer.ok())
+            .with(opentelemetry_logs_layer.ok()),
+    )
+}
+
+/// Events exported as OpenTelemetry logs; those of the exporters themselves would be
+/// exported again and again.
+fn exported_log_events() -> Targets {
+    ["h2", "hyper", "tonic", "tower", "opentelemetry"]
+        .into_iter()
+        .fold(
+            Targets::new().with_default(Level::INFO),
+            |targets, target| targets.with_target(target, LevelFilter::OFF),
+        )
 }
 
 pub(crate) fn init_telemetry(subscriber: impl Subscriber + Send + Sync) {
@@ -36,3 +60,11 @@ pub(crate) fn init_telemetry(subscriber: impl Subscriber + Send + Sync) {
     tracing::subscriber::set_global_default(subscriber)
         .expect("Subscriber failed to be set as global");
 }
+
+/// Exports the spans, metrics and logs still queued by the pipelines, called before exiting.
+pub(crate) async fn shutdown_telemetry() {
+    // the exports run on this runtime while the shutdown blocks, so it waits on another thread
+    if let Err(e) = actix_web::rt::task::spawn_blocking(otel::shutdown).await {
+        eprintln!("Failed to flush telemetry: {e}");
+    }
+}
@@ -0,0 +1,84 @@
+use std::{sync::LazyLock, time::Duration};
+
+use opentelemetry::{
+    metrics::{Counter, Histogram},
+    KeyValue,
+};
+
+static INSTRUMENTS: LazyLock<Instruments> = LazyLock::new(Instruments::new);
+
+/// Instruments exported with the OTLP metrics pipeline, no-ops until it is installed.
+pub(crate) fn instruments() -> &'static Instruments {
+    &INSTRUMENTS
+}
+
+pub(crate) struct Instruments {
+    uploads: Counter<u64>,
+    upload_size: Histogram<u64>,
+    listing_duration: Histogram<f64>,
+    search_duration: Histogram<f64>,
+    render_duration: Histogram<f64>,
+}
+
+impl Instruments {
+    fn new() -> Self {
+        let meter = opentelemetry::global::meter("my-drive");
+        Self {
+            uploads: meter
+                .u64_counter("my_drive.uploads")
+                .with_description("Uploaded files")
+                .init(),
+            upload_size: meter
+                .u64_histogram(UPLOAD_SIZE)
+                .with_description("Size of uploaded files")
+                .with_unit(opentelemetry::metrics::Unit::new("By"))
+                .init(),
+            listing_duration: duration(&meter, "my_drive.listing.duration", "Listing a folder"),
+            search_duration: duration(&meter, "my_drive.search.duration", "Searching the drive"),
+            render_duration: duration(
+                &meter,
+                "my_drive.template.render.duration",
+                "Rendering a template",
+            ),
+        }
+    }
+
+    pub(crate) fn record_upload(&self, bytes: u64) {
+        self.uploads.add(1, &[]);
+        self.upload_size.record(bytes, &[]);
+    }
+
+    pub(crate) fn record_listing(&self, elapsed: Duration) {
+        self.listing_duration.record(millis(elapsed), &[]);
+    }
+
+    pub(crate) fn record_search(&self, elapsed: Duration) {
+        self.search_duration.record(millis(elapsed), &[]);
+    }
+
+    pub(crate) fn record_render(&self, template: &str, elapsed: Duration) {
+        self.render_duration.record(
+            millis(elapsed),
+            &[KeyValue::new("template", template.to_owned())],
+        );
+    }
+}
+
+/// Name of the upload size histogram, whose buckets are set by the meter provider.
+pub(super) const UPLOAD_SIZE: &str = "my_drive.upload.size";
+
+fn duration(
+    meter: &opentelemetry::metrics::Meter,
+    name: &'static str,
+    what: &str,
+) -> Histogram<f64> {
+    meter
+        .f64_histogram(name)
+        .with_description(format!("{what}, in milliseconds"))
+        .with_unit(opentelemetry::metrics::Unit::new("ms"))
+        .init()
+}
+
+fn millis(elapsed: Duration) -> f64 {
+    elapsed.as_secs_f64() * 1000.0
+}
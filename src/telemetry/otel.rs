@@ -1,31 +1,52 @@
+use std::sync::OnceLock;
 use std::time::Duration;
 
+use opentelemetry::logs::LogError;
+use opentelemetry::metrics::MetricsError;
 use opentelemetry::trace::{TraceError, TraceState};
 
-use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_otlp::{TonicExporterBuilder, WithExportConfig};
+use opentelemetry_sdk::logs::LoggerProvider;
+use opentelemetry_sdk::metrics::reader::{DefaultAggregationSelector, DefaultTemporalitySelector};
+use opentelemetry_sdk::metrics::{
+    new_view, Aggregation, Instrument, PeriodicReader, SdkMeterProvider, Stream,
+};
 use opentelemetry_sdk::resource::{
     EnvResourceDetector, SdkProvidedResourceDetector, TelemetryResourceDetector,
 };
-use opentelemetry_sdk::trace::{Sampler, ShouldSample, SpanLimits};
+use opentelemetry_sdk::trace::{Sampler, ShouldSample};
 use opentelemetry_sdk::Resource;
 
-pub(crate) fn init_opentelemetry_tracer() -> Result<opentelemetry_sdk::trace::Tracer, TraceError> {
-    let resource = Resource::from_detectors(
+use super::instruments::UPLOAD_SIZE;
+
+/// Kept to export the remaining metrics on shutdown; the other providers are global.
+static METER_PROVIDER: OnceLock<SdkMeterProvider> = OnceLock::new();
+
+/// Describes this process in traces, metrics and logs alike.
+pub(crate) fn resource() -> Resource {
+    Resource::from_detectors(
         Duration::from_secs(0),
         vec![
             Box::new(SdkProvidedResourceDetector),
             Box::new(EnvResourceDetector::new()),
             Box::new(TelemetryResourceDetector),
         ],
-    );
+    )
+}
 
+/// Exporter of every pipeline, configured by the `OTEL_EXPORTER_OTLP_*` variables.
+fn exporter() -> TonicExporterBuilder {
+    opentelemetry_otlp::new_exporter()
+        .tonic()
+        .with_timeout(Duration::from_secs(5))
+}
+
+pub(crate) fn init_opentelemetry_tracer(
+    resource: Resource,
+) -> Result<opentelemetry_sdk::trace::Tracer, TraceError> {
     opentelemetry_otlp::new_pipeline()
         .tracing()
-        .with_exporter(
-            opentelemetry_otlp::new_exporter()
-                .tonic()
-                .with_timeout(Duration::from_secs(5)),
-        )
+        .with_exporter(exporter())
         .with_batch_config(
             opentelemetry_sdk::trace::BatchConfigBuilder::default()
                 .with_max_queue_size(30 * 1024)
@@ -49,6 +70,61 @@ pub(crate) fn init_opentelemetry_tracer() -> Result<opentelemetry_sdk::trace::Tr
         .install_batch(opentelemetry_sdk::runtime::Tokio)
 }
 
+/// Installs the global meter provider exporting the [`super::instruments`] every 30 seconds.
+pub(crate) fn init_opentelemetry_meter(resource: Resource) -> Result<(), MetricsError> {
+    let exporter = exporter().build_metrics_exporter(
+        Box::new(DefaultAggregationSelector::new()),
+        Box::new(DefaultTemporalitySelector::new()),
+    )?;
+    let reader = PeriodicReader::builder(exporter, opentelemetry_sdk::runtime::Tokio)
+        .with_interval(Duration::from_secs(30))
+        .build();
+    // the default buckets end at 10 kB
+    let upload_size = new_view(
+        Instrument::new().name(UPLOAD_SIZE),
+        Stream::new().aggregation(Aggregation::ExplicitBucketHistogram {
+            // 1 KiB to 4 GiB, growing fourfold
+            boundaries: (10..=32)
+                .step_by(2)
+                .map(|power| (1u64 << power) as f64)
+                .collect(),
+            record_min_max: true,
+        }),
+    )?;
+    let provider = SdkMeterProvider::builder()
+        .with_reader(reader)
+        .with_resource(resource)
+        .with_view(upload_size)
+        .build();
+    opentelemetry::global::set_meter_provider(provider.clone());
+    let _ = METER_PROVIDER.set(provider);
+    Ok(())
+}
+
+/// Installs the global logger provider receiving events bridged from `tracing`.
+pub(crate) fn init_opentelemetry_logger(resource: Resource) -> Result<LoggerProvider, LogError> {
+    let provider = LoggerProvider::builder()
+        .with_batch_exporter(
+            exporter().build_log_exporter()?,
+            opentelemetry_sdk::runtime::Tokio,
+        )
+        .with_config(opentelemetry_sdk::logs::config().with_resource(resource))
+        .build();
+    opentelemetry::global::set_logger_provider(provider.clone());
+    Ok(provider)
+}
+
+/// Exports what is still queued in all pipelines; blocks until the exports finished.
+pub(crate) fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+    if let Some(provider) = METER_PROVIDER.get() {
+        if let Err(e) = provider.shutdown() {
+            eprintln!("Failed to export metrics: {e}");
+        }
+    }
+    opentelemetry::global::shutdown_logger_provider();
+}
+
 #[derive(Debug, Clone)]
 struct SpanKindSampler<S: std::fmt::Debug + Clone> {
     parent: Box<S>,
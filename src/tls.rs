@@ -0,0 +1,218 @@
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use actix_web::{dev::Server, http::header, web, App, HttpRequest, HttpResponse, HttpServer};
+use anyhow::Context;
+use rustls::{
+    server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier},
+    sign::CertifiedKey,
+    RootCertStore, ServerConfig,
+};
+use tracing::{info, warn};
+
+/// How often the certificate files are checked for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Certificate of the server read from PEM files, reloaded when they change or on SIGHUP,
+/// so renewed certificates are used without a restart.
+#[derive(Debug)]
+pub(crate) struct ReloadingCertificate {
+    cert_file: PathBuf,
+    key_file: PathBuf,
+    current: RwLock<Arc<CertifiedKey>>,
+    modified: Mutex<Option<SystemTime>>,
+}
+
+impl ReloadingCertificate {
+    pub(crate) fn new(cert_file: &Path, key_file: &Path) -> anyhow::Result<Self> {
+        let certificate = Self {
+            cert_file: cert_file.to_path_buf(),
+            key_file: key_file.to_path_buf(),
+            current: RwLock::new(Arc::new(load_certified_key(cert_file, key_file)?)),
+            modified: Mutex::new(None),
+        };
+        *certificate.modified.lock().unwrap() = certificate.last_modified();
+        Ok(certificate)
+    }
+
+    /// Reads the files again, keeping the current certificate if they are invalid.
+    pub(crate) fn reload(&self) {
+        match load_certified_key(&self.cert_file, &self.key_file) {
+            Ok(key) => {
+                *self.current.write().unwrap() = Arc::new(key);
+                info!("Reloaded TLS certificate from {:?}", self.cert_file);
+            }
+            Err(e) => warn!("Failed to reload TLS certificate: {:?}", e),
+        }
+    }
+
+    /// Reloads the certificate if any of its files changed since the last check.
+    fn reload_if_modified(&self) {
+        let modified = self.last_modified();
+        let mut last = self.modified.lock().unwrap();
+        if modified != *last {
+            *last = modified;
+            drop(last);
+            self.reload();
+        }
+    }
+
+    fn last_modified(&self) -> Option<SystemTime> {
+        [&self.cert_file, &self.key_file]
+            .into_iter()
+            .filter_map(|file| file.metadata().and_then(|m| m.modified()).ok())
+            .max()
+    }
+
+    /// Spawns tasks reloading the certificate on SIGHUP and when its files change.
+    pub(crate) fn watch(self: &Arc<Self>) {
+        let certificate = self.clone();
+        actix_web::rt::spawn(async move {
+            let mut interval = actix_web::rt::time::interval(RELOAD_INTERVAL);
+            loop {
+                interval.tick().await;
+                certificate.reload_if_modified();
+            }
+        });
+        #[cfg(unix)]
+        {
+            use actix_web::rt::signal::unix::{signal, SignalKind};
+
+            let certificate = self.clone();
+            match signal(SignalKind::hangup()) {
+                Ok(mut hangups) => {
+                    actix_web::rt::spawn(async move {
+                        while hangups.recv().await.is_some() {
+                            certificate.reload();
+                        }
+                    });
+                }
+                Err(e) => warn!("Cannot listen for SIGHUP: {:?}", e),
+            }
+        }
+    }
+}
+
+impl ResolvesServerCert for ReloadingCertificate {
+    fn resolve(&self, _: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().clone())
+    }
+}
+
+/// TLS settings of the server, offering HTTP/2 and HTTP/1.1.
+///
+/// With `client_ca_file` clients may present a certificate signed by one of its CAs;
+/// whether one is required is decided per request by [`crate::client_certificates`].
+pub(crate) fn server_config(
+    certificate: Arc<ReloadingCertificate>,
+    client_ca_file: Option<&Path>,
+) -> anyhow::Result<ServerConfig> {
+    let builder = ServerConfig::builder();
+    let builder = match client_ca_file {
+        Some(client_ca_file) => {
+            let mut roots = RootCertStore::empty();
+            for ca in rustls_pemfile::certs(&mut open(client_ca_file)?) {
+                let ca = ca.context(format!("Invalid CA file {:?}", client_ca_file))?;
+                roots
+                    .add(ca)
+                    .context(format!("Invalid CA certificate in {:?}", client_ca_file))?;
+            }
+            let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+                .allow_unauthenticated()
+                .build()
+                .context(format!("No usable CA certificate in {:?}", client_ca_file))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    // actix-web adds the ALPN protocols for HTTP/2 and HTTP/1.1
+    Ok(builder.with_cert_resolver(certificate))
+}
+
+fn open(file: &Path) -> anyhow::Result<BufReader<File>> {
+    File::open(file)
+        .map(BufReader::new)
+        .context(format!("Failed to read {:?}", file))
+}
+
+fn load_certified_key(cert_file: &Path, key_file: &Path) -> anyhow::Result<CertifiedKey> {
+    let certs = rustls_pemfile::certs(&mut open(cert_file)?)
+        .collect::<Result<Vec<_>, _>>()
+        .context(format!("Invalid certificate file {:?}", cert_file))?;
+    if certs.is_empty() {
+        anyhow::bail!("No certificate in {:?}", cert_file);
+    }
+    let key = rustls_pemfile::private_key(&mut open(key_file)?)
+        .context(format!("Invalid key file {:?}", key_file))?
+        .context(format!("No private key in {:?}", key_file))?;
+    let signing_key = rustls::crypto::ring::sign::any_supported_type(&key)
+        .context(format!("Unsupported private key in {:?}", key_file))?;
+    Ok(CertifiedKey::new(certs, signing_key))
+}
+
+/// Plain HTTP server redirecting every request to the HTTPS server on `https_port`,
+/// stopped through its handle rather than by signals.
+pub(crate) fn redirect_server(
+    local_address: impl std::net::ToSocketAddrs,
+    https_port: u16,
+) -> anyhow::Result<Server> {
+    let server = HttpServer::new(move || {
+        App::new().default_service(web::to(move |req: HttpRequest| async move {
+            let location = https_location(
+                req.connection_info().host(),
+                https_port,
+                req.uri()
+                    .path_and_query()
+                    .map(|path| path.as_str())
+                    .unwrap_or("/"),
+            );
+            HttpResponse::PermanentRedirect()
+                .insert_header((header::LOCATION, location))
+                .finish()
+        }))
+    })
+    .workers(1)
+    .disable_signals()
+    .bind(local_address)
+    .context("Cannot bind the HTTP redirect server")?
+    .run();
+    Ok(server)
+}
+
+/// URL of `path` on the HTTPS server, with the port of `host` replaced by `https_port`.
+fn https_location(host: &str, https_port: u16, path: &str) -> String {
+    let hostname = match host.rsplit_once(':') {
+        // an IPv6 address without a port, e.g. "[::1]"
+        Some((_, port)) if port.ends_with(']') => host,
+        Some((hostname, _)) => hostname,
+        None => host,
+    };
+    match https_port {
+        443 => format!("https://{hostname}{path}"),
+        port => format!("https://{hostname}:{port}{path}"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::https_location;
+
+    #[test]
+    fn test_https_location() {
+        assert_eq!(
+            https_location("drive.local:8080", 8443, "/list?path=a"),
+            "https://drive.local:8443/list?path=a"
+        );
+        assert_eq!(
+            https_location("drive.local", 443, "/"),
+            "https://drive.local/"
+        );
+        assert_eq!(https_location("[::1]:80", 8443, "/"), "https://[::1]:8443/");
+        assert_eq!(https_location("[::1]", 443, "/"), "https://[::1]/");
+    }
+}
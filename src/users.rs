@@ -0,0 +1,127 @@
+use std::{path::Path, sync::OnceLock};
+
+use anyhow::Context;
+use argon2::{
+    password_hash::{rand_core::OsRng, SaltString},
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+};
+use serde::Deserialize;
+
+/// Local user accounts loaded from the file configured with `USERS_FILE`.
+///
+/// The file is a TOML document with a list of accounts:
+/// ```toml
+/// [[users]]
+/// name = "alice"
+/// # argon2 PHC string printed by `my-drive hash-password`
+/// password_hash = "$argon2id$v=19$m=19456,t=2,p=1$..."
+/// admin = true
+/// ```
+#[derive(Debug, Deserialize)]
+pub(crate) struct UserStore {
+    users: Vec<UserAccount>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct UserAccount {
+    pub(crate) name: String,
+    password_hash: String,
+    #[serde(default)]
+    pub(crate) admin: bool,
+}
+
+impl UserStore {
+    pub(crate) fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents =
+            std::fs::read_to_string(path).context(format!("Failed to read {:?}", path))?;
+        let store = toml::from_str::<UserStore>(&contents)
+            .context(format!("Failed to parse {:?}", path))?;
+        for user in &store.users {
+            PasswordHash::new(&user.password_hash)
+                .map_err(|e| anyhow::anyhow!("Invalid password hash of user {}: {e}", user.name))?;
+        }
+        Ok(store)
+    }
+
+    pub(crate) fn account(&self, name: &str) -> Option<&UserAccount> {
+        self.users.iter().find(|user| user.name == name)
+    }
+
+    /// Returns the account matching both `name` and `password`.
+    #[tracing::instrument(skip(self, password))]
+    pub(crate) fn verify(&self, name: &str, password: &str) -> Option<&UserAccount> {
+        match self.users.iter().find(|user| user.name == name) {
+            Some(user) => verify_password(&user.password_hash, password).then_some(user),
+            None => {
+                // spend the same time as for existing users so the response time
+                // does not reveal which user names exist
+                verify_password(dummy_hash(), password);
+                None
+            }
+        }
+    }
+}
+
+/// Checks `password` against an argon2 PHC string.
+pub(crate) fn verify_password(hash: &str, password: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    })
+}
+
+/// Hashes `password` with argon2id and a random salt, returning a PHC string.
+pub(crate) fn hash_password(password: &str) -> String {
+    Argon2::default()
+        .hash_password(password.as_bytes(), &SaltString::generate(&mut OsRng))
+        .expect("hashing to succeed")
+        .to_string()
+}
+
+fn dummy_hash() -> &'static str {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+    DUMMY_HASH.get_or_init(|| hash_password("dummy password"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{hash_password, UserStore};
+
+    #[test]
+    fn test_verify_user() {
+        let store: UserStore = toml::from_str(&format!(
+            r#"
+            [[users]]
+            name = "alice"
+            password_hash = "{}"
+            admin = true
+
+            [[users]]
+            name = "bob"
+            password_hash = "{}"
+            "#,
+            hash_password("alice's password"),
+            hash_password("bob's password"),
+        ))
+        .unwrap();
+
+        let alice = store.verify("alice", "alice's password").unwrap();
+        assert_eq!(alice.name, "alice");
+        assert!(alice.admin);
+        assert!(!store.verify("bob", "bob's password").unwrap().admin);
+        assert!(store.verify("alice", "bob's password").is_none());
+        assert!(store.verify("carol", "alice's password").is_none());
+    }
+
+    #[test]
+    fn test_load_rejects_invalid_hashes() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(
+            file.path(),
+            "[[users]]\nname = \"alice\"\npassword_hash = \"plain text\"\n",
+        )
+        .unwrap();
+        assert!(UserStore::load(file.path()).is_err());
+    }
+}
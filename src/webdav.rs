@@ -0,0 +1,545 @@
+use std::{
+    ffi::OsStr,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use dav_server::{
+    davpath::DavPath,
+    fs::{
+        DavDirEntry, DavFile, DavFileSystem, DavMetaData, FsError, FsFuture, FsResult, FsStream,
+        GuardedFileSystem, OpenOptions, ReadDirMeta,
+    },
+    localfs::LocalFs,
+    memls::MemLs,
+    DavHandler,
+};
+use futures_util::{stream, FutureExt, StreamExt};
+use tracing::debug;
+
+use crate::{
+    audit::{AuditAction, Auditor},
+    drive_access::{
+        self,
+        access_control::{AccessCheck, Permission},
+        path_resolution::{PathResolutionError, SymlinkPolicy},
+        volumes::Volumes,
+        UserAccess, Visibility,
+    },
+};
+
+/// Mount point of the WebDAV service.
+pub(crate) const DAV_PATH: &str = "/dav";
+
+/// Checks whether `path` is served by the WebDAV service.
+pub(crate) fn is_dav_path(path: &str) -> bool {
+    path == DAV_PATH || path.starts_with(&format!("{DAV_PATH}/"))
+}
+
+/// Access rights and audit context of the user making a WebDAV request.
+#[derive(Debug, Clone)]
+pub(crate) struct DavUser {
+    pub(crate) access: UserAccess,
+    pub(crate) auditor: Auditor,
+}
+
+/// Creates the handler serving the drive under [`DAV_PATH`], with locks kept in memory.
+pub(crate) fn handler(volumes: &Volumes, symlink_policy: SymlinkPolicy) -> DavHandler<DavUser> {
+    DavHandler::builder()
+        .strip_prefix(DAV_PATH)
+        .filesystem(Box::new(DriveFs::new(volumes, symlink_policy)))
+        .locksystem(MemLs::new())
+        .build_handler()
+}
+
+/// The drive as seen by a WebDAV user: paths are resolved and hidden the same way as in
+/// the HTML UI, and every operation is checked against the user's permissions.
+#[derive(Clone)]
+struct DriveFs {
+    volumes: Volumes,
+    symlink_policy: SymlinkPolicy,
+    /// Reads files and directories of the root once access was granted.
+    local: Box<LocalFs>,
+    /// Reads files and directories of every volume, by its name.
+    volume_fs: Vec<(String, Box<LocalFs>)>,
+}
+
+impl DriveFs {
+    fn new(volumes: &Volumes, symlink_policy: SymlinkPolicy) -> Self {
+        Self {
+            volumes: volumes.clone(),
+            symlink_policy,
+            local: LocalFs::new(volumes.root(), true, false, false),
+            volume_fs: volumes
+                .volumes()
+                .iter()
+                .map(|volume| {
+                    let fs = LocalFs::new(&volume.path, true, false, false);
+                    (volume.name.clone(), fs)
+                })
+                .collect(),
+        }
+    }
+
+    /// The file system serving `path` and the path inside of it.
+    fn local(&self, path: &DavPath) -> FsResult<(&LocalFs, DavPath)> {
+        let url = path.as_url_string();
+        let segment = url[1..].split('/').next().unwrap_or_default();
+        if let Ok(name) = DavPath::new(&format!("/{segment}")) {
+            let name = &name.as_bytes()[1..];
+            if let Some((_, fs)) = self
+                .volume_fs
+                .iter()
+                .find(|(volume, _)| volume.as_bytes() == name)
+            {
+                let rest = &url[1 + segment.len()..];
+                let rest = DavPath::new(if rest.is_empty() { "/" } else { rest })
+                    .map_err(|_| FsError::NotFound)?;
+                return Ok((fs, rest));
+            }
+        }
+        let path = DavPath::new(&url).map_err(|_| FsError::NotFound)?;
+        Ok((&self.local, path))
+    }
+
+    fn resolve(&self, path: &DavPath) -> FsResult<PathBuf> {
+        let resolved = self
+            .volumes
+            .resolve(path.as_rel_ospath(), self.symlink_policy)
+            .map_err(|e| {
+                debug!("Invalid path: {:?}", e);
+                match e {
+                    PathResolutionError::SymlinkDenied(_) => FsError::Forbidden,
+                    _ => FsError::NotFound,
+                }
+            })?;
+        if Visibility::new(&self.volumes).is_hidden(&resolved, resolved.is_dir()) {
+            debug!("Hidden path requested: {:?}", resolved);
+            return Err(FsError::NotFound);
+        }
+        Ok(resolved)
+    }
+
+    /// Resolves the target of a copy or move, which may only replace entries the user
+    /// could delete.
+    fn resolve_target(&self, path: &DavPath, user: &DavUser) -> FsResult<PathBuf> {
+        let target = self.resolve(path)?;
+        check(user, &target, Permission::Write)?;
+        if target.exists() {
+            check(user, &target, Permission::Delete)?;
+        }
+        Ok(target)
+    }
+
+    fn remove<'a>(&'a self, path: &'a DavPath, user: &'a DavUser) -> FsFuture<'a, ()> {
+        async move {
+            let path = self.resolve(path)?;
+            check(user, &path, Permission::Delete)?;
+            let bytes = file_size(&path);
+            let result = drive_access::delete_file_or_directory(&path);
+            user.auditor
+                .record(AuditAction::Delete, &path, bytes, &result);
+            result.map_err(fs_error)
+        }
+        .boxed()
+    }
+}
+
+impl GuardedFileSystem<DavUser> for DriveFs {
+    fn open<'a>(
+        &'a self,
+        path: &'a DavPath,
+        options: OpenOptions,
+        user: &'a DavUser,
+    ) -> FsFuture<'a, Box<dyn DavFile>> {
+        async move {
+            let writes = options.write
+                || options.append
+                || options.truncate
+                || options.create
+                || options.create_new;
+            let (local, local_path) = self.local(path)?;
+            if !writes {
+                check(user, &self.resolve(path)?, Permission::Read)?;
+                return DavFileSystem::open(local, &local_path, options).await;
+            }
+            let fs_path = self.resolve_target(path, user)?;
+            let file = DavFileSystem::open(local, &local_path, options).await?;
+            Ok(Box::new(AuditedFile {
+                file,
+                max_size: self.volumes.max_upload_size(&fs_path),
+                written: 0,
+                path: fs_path,
+                auditor: user.auditor.clone(),
+            }) as Box<dyn DavFile>)
+        }
+        .boxed()
+    }
+
+    fn read_dir<'a>(
+        &'a self,
+        path: &'a DavPath,
+        meta: ReadDirMeta,
+        user: &'a DavUser,
+    ) -> FsFuture<'a, FsStream<Box<dyn DavDirEntry>>> {
+        async move {
+            let dir = self.resolve(path)?;
+            if !user.access.is_listable(&dir, true) {
+                return Err(FsError::NotFound);
+            }
+            let (local, local_path) = self.local(path)?;
+            let mut entries = DavFileSystem::read_dir(local, &local_path, meta).await?;
+            let mut visibility = Visibility::new(&self.volumes);
+            let mut listed = Vec::new();
+            while let Some(entry) = entries.next().await {
+                let entry = entry?;
+                let entry_path = dir.join(OsStr::from_bytes(&entry.name()));
+                let is_dir = entry.is_dir().await.unwrap_or(false);
+                if !visibility.is_hidden(&entry_path, is_dir)
+                    && user.access.is_listable(&entry_path, is_dir)
+                {
+                    listed.push(Ok(entry));
+                }
+            }
+            if dir == self.volumes.root() {
+                // volumes are shown as folders of the root
+                for (volume, (name, fs)) in self.volumes.volumes().iter().zip(&self.volume_fs) {
+                    if !visibility.is_hidden(&volume.path, true)
+                        && user.access.is_listable(&volume.path, true)
+                    {
+                        listed.push(Ok(Box::new(VolumeEntry {
+                            name: name.clone(),
+                            fs: fs.clone(),
+                        }) as Box<dyn DavDirEntry>));
+                    }
+                }
+            }
+            Ok(stream::iter(listed).boxed())
+        }
+        .boxed()
+    }
+
+    fn metadata<'a>(
+        &'a self,
+        path: &'a DavPath,
+        user: &'a DavUser,
+    ) -> FsFuture<'a, Box<dyn DavMetaData>> {
+        async move {
+            let fs_path = self.resolve(path)?;
+            let (local, local_path) = self.local(path)?;
+            let metadata = DavFileSystem::metadata(local, &local_path).await?;
+            // entries which cannot be read are invisible
+            if !user.access.is_listable(&fs_path, metadata.is_dir()) {
+                return Err(FsError::NotFound);
+            }
+            Ok(metadata)
+        }
+        .boxed()
+    }
+
+    fn create_dir<'a>(&'a self, path: &'a DavPath, user: &'a DavUser) -> FsFuture<'a, ()> {
+        async move {
+            let path = self.resolve(path)?;
+            check(user, &path, Permission::Write)?;
+            let result = drive_access::create_dir(&path);
+            user.auditor
+                .record(AuditAction::CreateDir, &path, None, &result);
+            result.map_err(fs_error)
+        }
+        .boxed()
+    }
+
+    fn remove_dir<'a>(&'a self, path: &'a DavPath, user: &'a DavUser) -> FsFuture<'a, ()> {
+        self.remove(path, user)
+    }
+
+    fn remove_file<'a>(&'a self, path: &'a DavPath, user: &'a DavUser) -> FsFuture<'a, ()> {
+        self.remove(path, user)
+    }
+
+    fn rename<'a>(
+        &'a self,
+        from: &'a DavPath,
+        to: &'a DavPath,
+        user: &'a DavUser,
+    ) -> FsFuture<'a, ()> {
+        async move {
+            let from = self.resolve(from)?;
+            check(user, &from, Permission::Delete)?;
+            let to = self.resolve_target(to, user)?;
+            if self.volumes.base_of(&from) != self.volumes.base_of(&to) {
+                // volumes may be on other disks, where entries cannot be renamed to
+                let max_size = self.volumes.max_upload_size(&to);
+                if max_size
+                    .is_some_and(|max_size| drive_access::largest_file_size(&from) > max_size)
+                {
+                    return Err(FsError::TooLarge);
+                }
+                let result = drive_access::move_between_volumes(&from, &to);
+                let bytes = result.as_ref().ok().copied();
+                user.auditor
+                    .record_transfer(AuditAction::Move, &from, &to, bytes, &result);
+                return result.map(|_| ()).map_err(fs_error);
+            }
+            let bytes = file_size(&from);
+            let result = drive_access::move_entry(&from, &to);
+            user.auditor
+                .record_transfer(AuditAction::Move, &from, &to, bytes, &result);
+            result.map_err(fs_error)
+        }
+        .boxed()
+    }
+
+    fn copy<'a>(
+        &'a self,
+        from: &'a DavPath,
+        to: &'a DavPath,
+        user: &'a DavUser,
+    ) -> FsFuture<'a, ()> {
+        async move {
+            let from = self.resolve(from)?;
+            check(user, &from, Permission::Read)?;
+            let to = self.resolve_target(to, user)?;
+            let max_size = self.volumes.max_upload_size(&to);
+            if max_size.is_some_and(|max_size| file_size(&from).unwrap_or(0) > max_size) {
+                return Err(FsError::TooLarge);
+            }
+            let result = drive_access::copy_file(&from, &to);
+            let bytes = result.as_ref().ok().copied();
+            user.auditor
+                .record_transfer(AuditAction::Copy, &from, &to, bytes, &result);
+            result.map(|_| ()).map_err(fs_error)
+        }
+        .boxed()
+    }
+}
+
+/// Volume shown as a folder of the root directory.
+struct VolumeEntry {
+    name: String,
+    fs: Box<LocalFs>,
+}
+
+impl DavDirEntry for VolumeEntry {
+    fn name(&self) -> Vec<u8> {
+        self.name.as_bytes().to_vec()
+    }
+
+    fn metadata(&self) -> FsFuture<'_, Box<dyn DavMetaData>> {
+        async move {
+            let root = DavPath::new("/").unwrap();
+            DavFileSystem::metadata(&*self.fs, &root).await
+        }
+        .boxed()
+    }
+}
+
+/// File opened for writing, recording an upload once its contents are flushed.
+#[derive(Debug)]
+struct AuditedFile {
+    file: Box<dyn DavFile>,
+    path: PathBuf,
+    auditor: Auditor,
+    /// Upload limit of the volume, in bytes.
+    max_size: Option<u64>,
+    written: u64,
+}
+
+impl AuditedFile {
+    /// Counts the bytes to be written, removing the file when it gets too large.
+    fn check_size(&mut self, len: usize) -> FsResult<()> {
+        self.written += len as u64;
+        if self
+            .max_size
+            .is_some_and(|max_size| self.written > max_size)
+        {
+            let result = std::fs::remove_file(&self.path).map_err(anyhow::Error::from);
+            self.auditor.record(
+                AuditAction::Upload,
+                &self.path,
+                None,
+                &Err::<(), _>("File is too large"),
+            );
+            return result.map_err(fs_error).and(Err(FsError::TooLarge));
+        }
+        Ok(())
+    }
+}
+
+impl DavFile for AuditedFile {
+    fn metadata(&mut self) -> FsFuture<'_, Box<dyn DavMetaData>> {
+        self.file.metadata()
+    }
+
+    fn write_buf(&mut self, buf: Box<dyn bytes::Buf + Send>) -> FsFuture<'_, ()> {
+        if let Err(e) = self.check_size(buf.remaining()) {
+            return futures_util::future::ready(Err(e)).boxed();
+        }
+        self.file.write_buf(buf)
+    }
+
+    fn write_bytes(&mut self, buf: bytes::Bytes) -> FsFuture<'_, ()> {
+        if let Err(e) = self.check_size(buf.len()) {
+            return futures_util::future::ready(Err(e)).boxed();
+        }
+        self.file.write_bytes(buf)
+    }
+
+    fn read_bytes(&mut self, count: usize) -> FsFuture<'_, bytes::Bytes> {
+        self.file.read_bytes(count)
+    }
+
+    fn seek(&mut self, pos: std::io::SeekFrom) -> FsFuture<'_, u64> {
+        self.file.seek(pos)
+    }
+
+    fn flush(&mut self) -> FsFuture<'_, ()> {
+        async move {
+            let result = self.file.flush().await;
+            let bytes = match result {
+                Ok(()) => self
+                    .file
+                    .metadata()
+                    .await
+                    .ok()
+                    .map(|metadata| metadata.len()),
+                Err(_) => None,
+            };
+            self.auditor
+                .record(AuditAction::Upload, &self.path, bytes, &result);
+            result
+        }
+        .boxed()
+    }
+}
+
+/// Checks whether the user may perform an operation requiring `required` on `path`.
+fn check(user: &DavUser, path: &Path, required: Permission) -> FsResult<()> {
+    match user.access.check(path, required) {
+        AccessCheck::Allowed => Ok(()),
+        AccessCheck::Forbidden => Err(FsError::Forbidden),
+        AccessCheck::Hidden => Err(FsError::NotFound),
+    }
+}
+
+fn file_size(path: &Path) -> Option<u64> {
+    path.metadata()
+        .ok()
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+}
+
+fn fs_error(e: anyhow::Error) -> FsError {
+    debug!("WebDAV operation failed: {:?}", e);
+    e.downcast_ref::<std::io::Error>()
+        .map(FsError::from)
+        .unwrap_or(FsError::GeneralFailure)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use dav_server::{
+        davpath::DavPath,
+        fs::{FsError, GuardedFileSystem, ReadDirMeta},
+    };
+    use futures_util::StreamExt;
+
+    use super::{DavUser, DriveFs};
+    use crate::{
+        audit::Auditor,
+        drive_access::{
+            path_resolution::SymlinkPolicy,
+            volumes::{Volume, Volumes},
+            Permissions, UserAccess,
+        },
+    };
+
+    #[actix_web::test]
+    async fn test_access_checks() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "notes").unwrap();
+        std::fs::write(dir.path().join(".env"), "secret").unwrap();
+        let volumes = Volumes::single(dir.path());
+        let fs = DriveFs::new(&volumes, SymlinkPolicy::default());
+        let user = DavUser {
+            access: UserAccess::new(None, None, false, Arc::new(volumes))
+                .restricted(Permissions::read_only()),
+            auditor: Auditor::default(),
+        };
+        let root = DavPath::new("/").unwrap();
+
+        let entries = fs
+            .read_dir(&root, ReadDirMeta::None, &user)
+            .await
+            .unwrap()
+            .map(|entry| String::from_utf8(entry.unwrap().name()).unwrap())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(entries, ["notes.txt"]);
+
+        let hidden = DavPath::new("/.env").unwrap();
+        assert!(matches!(
+            fs.metadata(&hidden, &user).await,
+            Err(FsError::NotFound)
+        ));
+        let new_dir = DavPath::new("/photos").unwrap();
+        assert!(matches!(
+            fs.create_dir(&new_dir, &user).await,
+            Err(FsError::Forbidden)
+        ));
+        assert!(!dir.path().join("photos").exists());
+    }
+
+    #[actix_web::test]
+    async fn test_volumes() {
+        let (root, disk) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        std::fs::write(root.path().join("notes.txt"), "notes").unwrap();
+        std::fs::write(disk.path().join("a.jpg"), "image").unwrap();
+        let volume = Volume {
+            name: "media".to_owned(),
+            path: disk.path().to_path_buf(),
+            read_only: false,
+            max_upload_size: None,
+        };
+        let volumes = Arc::new(Volumes::new(root.path().to_path_buf(), vec![volume]).unwrap());
+        let fs = DriveFs::new(&volumes, SymlinkPolicy::default());
+        let user = DavUser {
+            access: UserAccess::new(None, None, false, volumes),
+            auditor: Auditor::default(),
+        };
+
+        let list = |path: &'static str| {
+            let (fs, user) = (&fs, &user);
+            async move {
+                let mut names = fs
+                    .read_dir(&DavPath::new(path).unwrap(), ReadDirMeta::None, user)
+                    .await
+                    .unwrap()
+                    .map(|entry| String::from_utf8(entry.unwrap().name()).unwrap())
+                    .collect::<Vec<_>>()
+                    .await;
+                names.sort();
+                names
+            }
+        };
+        assert_eq!(list("/").await, ["media", "notes.txt"]);
+        assert_eq!(list("/media/").await, ["a.jpg"]);
+        let image = DavPath::new("/media/a.jpg").unwrap();
+        assert_eq!(fs.metadata(&image, &user).await.unwrap().len(), 5);
+
+        let moved = DavPath::new("/a.jpg").unwrap();
+        fs.rename(&image, &moved, &user).await.unwrap();
+        assert!(!disk.path().join("a.jpg").exists());
+        assert_eq!(
+            std::fs::read_to_string(root.path().join("a.jpg")).unwrap(),
+            "image"
+        );
+        let media = DavPath::new("/media").unwrap();
+        assert!(matches!(
+            fs.remove_dir(&media, &user).await,
+            Err(FsError::Forbidden)
+        ));
+    }
+}
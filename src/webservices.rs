@@ -1,18 +1,46 @@
 use std::path::PathBuf;
 
-use actix_web::{guard, web, App, HttpServer};
+use actix_web::{guard, middleware::Condition, web, App, HttpServer};
 use anyhow::Context;
+use tracing::{info, warn};
 
+use crate::{
+    api_tokens::TokenStore,
+    audit::AuditLog,
+    client_certificates::ClientCertificates,
+    config::Config,
+    drive_access::{access_control::AccessControlList, read_only::ReadOnlyPaths, volumes::Volumes},
+    server::{
+        oidc::{OidcConfig, OidcProvider},
+        Shutdown,
+    },
+    shares::{ShareStore, SHARE_PATH},
+    users::UserStore,
+    webdav::DAV_PATH,
+};
 
+mod activity;
+mod api;
+mod api_tokens;
+mod audit_log;
+mod conditional;
 mod create_dir;
 mod delete_file;
 mod folder_contents;
+mod health;
 mod index;
 mod list_files;
+mod login;
+mod page_context;
 mod query_files;
 mod response_renderer;
+mod shares;
 mod upload_file;
 mod utilities;
+mod webdav;
+
+pub(crate) use api::{Problem, API_PATH};
+pub(crate) use health::METRICS_PATH;
 
 #[derive(Debug, thiserror::Error)]
 pub(crate) enum FileListInputError {
@@ -20,9 +48,13 @@ pub(crate) enum FileListInputError {
     InvalidPath(PathBuf),
 }
 
-/// Starts HTTP server.
+/// Starts HTTP server, running until `shutdown` is requested and uploads in progress
+/// finished or the grace period elapsed.
 pub(crate) async fn start_http_server(
     local_address: &impl std::net::ToSocketAddrs,
+    tls: Option<rustls::ServerConfig>,
+    config: Config,
+    shutdown: Shutdown,
 ) -> anyhow::Result<()> {
     // Handlebars uses a repository for the compiled templates. This object must be
     // shared between the application threads, and is therefore passed to the
@@ -31,16 +63,182 @@ pub(crate) async fn start_http_server(
     let handlebars = crate::handlebars_utils::prepare();
     let handlebars_ref = web::Data::new(handlebars);
 
-    let base_dir = PathBuf::from(dotenv::var("BASE_DIR").unwrap());
-    let base_dir_data = web::Data::new(base_dir);
+    crate::drive_access::ordering::configure(&config.sort_locale);
+    crate::drive_access::visibility::configure(
+        config.show_hidden_files,
+        config.global_ignore_file.clone(),
+    );
+    let volumes = Volumes::from_config(&config)?;
+    let max_request_size = volumes.max_request_size(config.max_upload_size);
+
+    let symlink_policy = config.symlink_policy;
+    let symlink_policy_data = web::Data::new(symlink_policy);
+
+    let users_data = match &config.users_file {
+        Some(users_file) => Some(web::Data::new(UserStore::load(users_file)?)),
+        None => None,
+    };
+    let client_certificates_data = match &config.client_certs_file {
+        Some(client_certs_file) => {
+            Some(web::Data::new(ClientCertificates::load(client_certs_file)?))
+        }
+        None => None,
+    };
+    let oidc_data = match &config.oidc_config {
+        Some(oidc_config) => {
+            let oidc_config = OidcConfig::load(oidc_config)?;
+            Some(web::Data::new(OidcProvider::discover(oidc_config).await?))
+        }
+        None => None,
+    };
+    let acl_data = match &config.acl_file {
+        Some(acl_file) => Some(web::Data::new(AccessControlList::load(acl_file)?)),
+        None => None,
+    };
+
+    let read_only = ReadOnlyPaths::from_config(&config, &volumes);
+    let read_only_data = read_only.is_enabled().then(|| web::Data::new(read_only));
+
+    let shares_data = match &config.share_secret {
+        Some(secret) => Some(web::Data::new(ShareStore::load(
+            &config.shares_file,
+            secret,
+        )?)),
+        None => None,
+    };
+
+    let audit_log_data = AuditLog::from_config(&config).map(web::Data::new);
+
+    let authentication_enabled =
+        users_data.is_some() || oidc_data.is_some() || client_certificates_data.is_some();
+    if !authentication_enabled {
+        warn!("Neither USERS_FILE, OIDC_CONFIG nor CLIENT_CERTS_FILE set, the drive is available without signing in");
+    }
+    // API tokens belong to users, so they need authentication
+    let tokens_data = if authentication_enabled {
+        Some(web::Data::new(TokenStore::load(&config.api_tokens_file)?))
+    } else {
+        None
+    };
+    let metrics_token_data = config
+        .metrics_token
+        .clone()
+        .map(|token| web::Data::new(crate::server::MetricsToken(token)));
+    let dav_data = web::Data::new(crate::webdav::handler(&volumes, symlink_policy));
+    let basic_credentials_data = web::Data::new(crate::server::BasicCredentials::default());
+    let signed_out_sessions_data = web::Data::new(crate::server::SignedOutSessions::default());
+    let share_unlock_failures_data = web::Data::new(shares::ShareUnlockFailures::default());
+    let trusted_proxies_data = web::Data::new(crate::server::TrustedProxies(
+        config.trusted_proxies.clone(),
+    ));
+    let volumes_data = web::Data::new(volumes);
+    let session_key = crate::server::session_key(config.session_secret.as_deref())?;
+    let session_cookie_secure = config.session_cookie_secure;
+    let grace_period = config.shutdown_grace_period;
+    let shutdown_data = web::Data::new(shutdown.clone());
 
-    
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         App::new()
+            .wrap(crate::server::UploadTracking)
+            .wrap(crate::server::CsrfProtection)
+            .wrap(Condition::new(
+                authentication_enabled,
+                crate::server::Authentication,
+            ))
+            .wrap(crate::server::session_middleware(
+                session_key.clone(),
+                session_cookie_secure,
+            ))
             .wrap(tracing_actix_web::TracingLogger::default())
+            .wrap(crate::server::RequestMetrics)
             .service(actix_files::Files::new("/static", "./static"))
-            .app_data(base_dir_data.clone())
+            .app_data(volumes_data.clone())
+            .app_data(symlink_policy_data.clone())
             .app_data(handlebars_ref.clone())
+            .app_data(dav_data.clone())
+            .app_data(basic_credentials_data.clone())
+            .app_data(signed_out_sessions_data.clone())
+            .app_data(share_unlock_failures_data.clone())
+            .app_data(trusted_proxies_data.clone())
+            .app_data(shutdown_data.clone())
+            .configure(|cfg| {
+                if authentication_enabled {
+                    cfg.app_data(web::Data::new(crate::server::AuthenticationRequired));
+                }
+                if let Some(users_data) = &users_data {
+                    cfg.app_data(users_data.clone());
+                }
+                if let Some(client_certificates_data) = &client_certificates_data {
+                    cfg.app_data(client_certificates_data.clone());
+                }
+                if let Some(oidc_data) = &oidc_data {
+                    cfg.app_data(oidc_data.clone());
+                }
+                if let Some(acl_data) = &acl_data {
+                    cfg.app_data(acl_data.clone());
+                }
+                if let Some(shares_data) = &shares_data {
+                    cfg.app_data(shares_data.clone());
+                }
+                if let Some(read_only_data) = &read_only_data {
+                    cfg.app_data(read_only_data.clone());
+                }
+                if let Some(tokens_data) = &tokens_data {
+                    cfg.app_data(tokens_data.clone());
+                }
+                if let Some(audit_log_data) = &audit_log_data {
+                    cfg.app_data(audit_log_data.clone());
+                }
+                if let Some(metrics_token_data) = &metrics_token_data {
+                    cfg.app_data(metrics_token_data.clone());
+                }
+            })
+            .service(
+                web::resource(crate::server::LOGIN_PATH)
+                    .route(web::get().to(login::show))
+                    .route(web::post().to(login::login)),
+            )
+            .service(web::resource("/login/oidc").route(web::get().to(login::oidc_start)))
+            .service(
+                web::resource(crate::server::oidc::OIDC_CALLBACK_PATH)
+                    .route(web::get().to(login::oidc_callback)),
+            )
+            .service(web::resource("/logout").route(web::post().to(login::logout)))
+            .service(
+                web::resource("/shares")
+                    .route(web::get().to(shares::list))
+                    .route(web::post().to(shares::create)),
+            )
+            .service(web::resource("/shares/{id}").route(web::delete().to(shares::revoke)))
+            .service(
+                web::resource("/tokens")
+                    .route(web::get().to(api_tokens::list))
+                    .route(web::post().to(api_tokens::create)),
+            )
+            .service(web::resource("/tokens/{id}").route(web::delete().to(api_tokens::revoke)))
+            .service(web::resource("/audit").route(web::get().to(audit_log::show)))
+            .route("/healthz", web::get().to(health::healthz))
+            .route("/readyz", web::get().to(health::readyz))
+            .route(METRICS_PATH, web::get().to(health::metrics))
+            .service(api::scope(max_request_size))
+            .service(
+                web::resource([DAV_PATH.to_owned(), format!("{DAV_PATH}/{{tail:.*}}")])
+                    .to(webdav::handle),
+            )
+            .service(
+                web::resource(format!("{SHARE_PATH}/{{token}}"))
+                    .app_data(
+                        actix_multipart::form::MultipartFormConfig::default()
+                            .total_limit(max_request_size),
+                    )
+                    .route(web::get().to(shares::open))
+                    .route(web::post().to(shares::unlock))
+                    .route(web::put().to(upload_file::handle_drop)),
+            )
+            .service(
+                web::resource(format!("{SHARE_PATH}/{{token}}/{{path:.*}}"))
+                    .route(web::get().to(shares::open)),
+            )
             .service(
                 web::resource("/")
                     .guard(guard::Post())
@@ -48,10 +246,20 @@ pub(crate) async fn start_http_server(
             )
             .service(
                 web::resource("/{path:.*}")
+                    .wrap(crate::server::AccessControl)
                     .wrap(crate::server::RequestPath)
                     .app_data(
                         actix_multipart::form::MultipartFormConfig::default()
-                            .total_limit(1024 * 1024 * 128),
+                            .total_limit(max_request_size),
+                    )
+                    .route(
+                        web::get()
+                            .guard(guard::fn_guard(|ctx| {
+                                ctx.head().uri.query().is_some_and(|query| {
+                                    query.split('&').any(|param| param == "activity")
+                                })
+                            }))
+                            .to(activity::handle),
                     )
                     .route(
                         web::get()
@@ -68,8 +276,24 @@ pub(crate) async fn start_http_server(
                     .route(web::delete().to(delete_file::handle)),
             )
     })
-    .bind(local_address)?
-.run()
-.await
-.context("Cannot run the server")
+    .on_connect(crate::client_certificates::on_connect)
+    // signals are handled by the shutdown coordinator
+    .disable_signals()
+    .shutdown_timeout(grace_period);
+    let server = match tls {
+        Some(tls) => server.bind_rustls_0_22(local_address, tls)?,
+        None => server.bind(local_address)?,
+    }
+    .run();
+    let handle = server.handle();
+    actix_web::rt::spawn(async move {
+        shutdown.requested().await;
+        info!(
+            "Stopping the server, waiting up to {}s for {} uploads in progress",
+            grace_period,
+            shutdown.uploads_in_progress()
+        );
+        handle.stop(true).await;
+    });
+    server.await.context("Cannot run the server")
 }
@@ -0,0 +1,249 @@
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use actix_web::{http::header, web, HttpResponse, Responder};
+use handlebars::Handlebars;
+use serde_json::json;
+use tracing::warn;
+
+use super::shares::describe_age;
+use crate::{
+    audit::{AuditAction, AuditFilter, AuditLog, AuditRecord, Outcome},
+    drive_access::{volumes::Volumes, UserAccess, Visibility},
+};
+
+/// Groups shown at once, older ones are loaded with a "Show more" button.
+const PAGE_SIZE: usize = 10;
+/// Records read for a page; it shows fewer groups when most records are not visible.
+const BATCH_SIZE: usize = 200;
+/// Operations of the same kind are grouped when they happened within this period.
+const GROUP_PERIOD: u64 = 60 * 60;
+
+#[derive(Debug, serde::Deserialize)]
+pub(super) struct ActivityQuery {
+    /// Number of records shown on previous pages.
+    #[serde(default)]
+    offset: usize,
+}
+
+/// Operations of a user in a folder, e.g. several uploaded files.
+#[derive(Debug, PartialEq, serde::Serialize)]
+struct Activity {
+    actor: Option<String>,
+    action: AuditAction,
+    /// Drive path of the folder, e.g. "/photos".
+    folder: String,
+    names: Vec<String>,
+    /// Unix time of the newest operation.
+    time: u64,
+}
+
+impl Activity {
+    fn new(record: &AuditRecord) -> Self {
+        let (folder, name) = split_path(&record.path);
+        Self {
+            actor: record.actor.clone(),
+            action: record.action,
+            folder: folder.to_owned(),
+            names: vec![name.to_owned()],
+            time: record.time,
+        }
+    }
+
+    fn accepts(&self, record: &AuditRecord) -> bool {
+        record.actor == self.actor
+            && record.action == self.action
+            && split_path(&record.path).0 == self.folder
+            && self.time.saturating_sub(record.time) <= GROUP_PERIOD
+    }
+
+    /// E.g. "alice uploaded 3 files to /photos".
+    fn describe(&self) -> String {
+        let actor = self.actor.as_deref().unwrap_or("Someone");
+        let entries = match self.names.as_slice() {
+            [name] => name.clone(),
+            names => {
+                let kind = match self.action {
+                    AuditAction::Upload => "files",
+                    AuditAction::CreateDir => "folders",
+                    AuditAction::Delete | AuditAction::Move | AuditAction::Copy => "items",
+                };
+                format!("{} {kind}", names.len())
+            }
+        };
+        match self.action {
+            AuditAction::Upload => format!("{actor} uploaded {entries} to {}", self.folder),
+            AuditAction::CreateDir => format!("{actor} created {entries} in {}", self.folder),
+            AuditAction::Delete => format!("{actor} deleted {entries} from {}", self.folder),
+            AuditAction::Move => format!("{actor} moved {entries} out of {}", self.folder),
+            AuditAction::Copy => format!("{actor} copied {entries} from {}", self.folder),
+        }
+    }
+}
+
+/// Recent activity in the requested folder and below, newest first.
+pub(super) async fn handle(
+    hb: web::Data<Handlebars<'_>>,
+    volumes: web::Data<Volumes>,
+    audit_log: Option<web::Data<AuditLog>>,
+    path: web::ReqData<crate::server::RequestedPath>,
+    access: UserAccess,
+    query: web::Query<ActivityQuery>,
+) -> impl Responder {
+    // the panel disappears when there is nothing to show
+    let Some(audit_log) = audit_log else {
+        return HttpResponse::Ok().finish();
+    };
+    let path = path.into_inner();
+    let relative = volumes.relative(path.as_ref()).unwrap_or_default();
+    let filter = AuditFilter {
+        path: format!("/{}", relative.to_string_lossy()),
+        outcome: Some(Outcome::Success),
+        ..Default::default()
+    };
+    let records = match audit_log.query(&filter, query.offset, BATCH_SIZE) {
+        Ok(records) => records,
+        Err(e) => {
+            warn!("Failed to read audit log: {:?}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    // only entries the user could see in the listings are mentioned
+    let mut visibility = Visibility::new(&volumes);
+    let (activities, consumed) = group(&records, PAGE_SIZE, |record| {
+        let path = volumes.join(Path::new(&record.path));
+        !visibility.is_hidden(&path, path.is_dir()) && access.permissions(&path).read
+    });
+    let has_more = consumed < records.len() || records.len() == BATCH_SIZE;
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let activities = activities
+        .iter()
+        .map(|activity| {
+            json!({
+                "action": activity.action,
+                "description": activity.describe(),
+                "names": activity.names,
+                "age": describe_age(now.saturating_sub(activity.time)),
+            })
+        })
+        .collect::<Vec<_>>();
+    let body = crate::handlebars_utils::render(
+        &hb,
+        "activity",
+        &json!({
+            "first_page": query.offset == 0,
+            "activities": activities,
+            "more": has_more.then(|| format!(
+                "/{}?activity&offset={}",
+                relative.to_string_lossy(),
+                query.offset + consumed
+            )),
+        }),
+    )
+    .unwrap();
+    HttpResponse::Ok()
+        .insert_header(header::ContentType::html())
+        .body(body)
+}
+
+/// Groups up to `max_groups` of the `visible` records, returning the groups and the number
+/// of records they cover.
+fn group(
+    records: &[AuditRecord],
+    max_groups: usize,
+    mut visible: impl FnMut(&AuditRecord) -> bool,
+) -> (Vec<Activity>, usize) {
+    let mut activities: Vec<Activity> = Vec::new();
+    for (index, record) in records.iter().enumerate() {
+        if !visible(record) {
+            continue;
+        }
+        if let Some(activity) = activities
+            .last_mut()
+            .filter(|activity| activity.accepts(record))
+        {
+            let name = split_path(&record.path).1;
+            if !activity.names.iter().any(|n| n == name) {
+                activity.names.push(name.to_owned());
+            }
+            continue;
+        }
+        if activities.len() == max_groups {
+            return (activities, index);
+        }
+        activities.push(Activity::new(record));
+    }
+    (activities, records.len())
+}
+
+/// Splits a drive path into its folder and name, e.g. "/photos" and "cat.jpg".
+fn split_path(path: &str) -> (&str, &str) {
+    match path.rsplit_once('/') {
+        Some(("", name)) => ("/", name),
+        Some((folder, name)) => (folder, name),
+        None => ("/", path),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{group, split_path};
+    use crate::audit::{AuditAction, AuditRecord, Outcome};
+
+    fn record(time: u64, actor: &str, action: AuditAction, path: &str) -> AuditRecord {
+        AuditRecord {
+            time,
+            actor: Some(actor.to_owned()),
+            via: None,
+            client_ip: None,
+            action,
+            path: path.to_owned(),
+            target: None,
+            bytes: None,
+            outcome: Outcome::Success,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_group() {
+        let records = [
+            record(5000, "alice", AuditAction::Upload, "/photos/c.jpg"),
+            record(4990, "alice", AuditAction::Upload, "/photos/b.jpg"),
+            record(4980, "alice", AuditAction::Upload, "/photos/a.jpg"),
+            record(4970, "bob", AuditAction::Delete, "/secret/x"),
+            record(4960, "alice", AuditAction::Delete, "/photos/old.jpg"),
+            record(10, "alice", AuditAction::Delete, "/photos/older.jpg"),
+            record(5, "alice", AuditAction::CreateDir, "/docs"),
+        ];
+        let (activities, consumed) =
+            group(&records, 2, |record| !record.path.starts_with("/secret"));
+        assert_eq!(consumed, 5);
+        assert_eq!(
+            activities
+                .iter()
+                .map(|activity| activity.describe())
+                .collect::<Vec<_>>(),
+            [
+                "alice uploaded 3 files to /photos",
+                "alice deleted old.jpg from /photos"
+            ]
+        );
+
+        let (activities, consumed) = group(&records[consumed..], 10, |_| true);
+        assert_eq!(consumed, 2);
+        assert_eq!(activities[1].describe(), "alice created docs in /");
+    }
+
+    #[test]
+    fn test_split_path() {
+        assert_eq!(split_path("/photos/cat.jpg"), ("/photos", "cat.jpg"));
+        assert_eq!(split_path("/docs"), ("/", "docs"));
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    future::{ready, Ready},
+    path::{Path, PathBuf},
+};
+
+use actix_web::{dev, web, FromRequest, HttpRequest, HttpResponse};
+use tracing::debug;
+use utoipa::{
+    openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi,
+};
+
+use crate::drive_access::{
+    access_control::{AccessCheck, Permission},
+    path_resolution::{PathResolutionError, SymlinkPolicy},
+    volumes::Volumes,
+    UserAccess, Visibility,
+};
+
+mod files;
+mod folders;
+mod model;
+mod moves;
+mod problem;
+mod search;
+
+pub(crate) use problem::Problem;
+
+/// Prefix of the current API version.
+pub(crate) const API_PATH: &str = "/api/v1";
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "My Drive API",
+        version = "1",
+        description = "Files and folders of the drive. Errors are described with RFC 7807 problem details."
+    ),
+    servers((url = "/api/v1")),
+    paths(
+        files::show,
+        files::delete,
+        files::download,
+        folders::show,
+        folders::create,
+        folders::delete,
+        folders::upload,
+        moves::create,
+        search::search
+    ),
+    modifiers(&Security),
+    security(("api_token" = []))
+)]
+struct ApiDoc;
+
+/// Documents authentication with personal API tokens.
+struct Security;
+
+impl Modify for Security {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+/// Routes of the current API version, accepting upload requests up to `max_upload_size` bytes.
+pub(super) fn scope(max_upload_size: usize) -> actix_web::Scope {
+    web::scope(API_PATH)
+        .app_data(
+            web::QueryConfig::default()
+                .error_handler(|e, _| Problem::bad_request(e.to_string()).into()),
+        )
+        .app_data(
+            web::JsonConfig::default()
+                .error_handler(|e, _| Problem::bad_request(e.to_string()).into()),
+        )
+        .app_data(
+            actix_multipart::form::MultipartFormConfig::default()
+                .total_limit(max_upload_size)
+                .error_handler(|e, _| Problem::bad_request(e.to_string()).into()),
+        )
+        .route("/openapi.json", web::get().to(openapi))
+        .service(
+            web::resource("/files/{path:.*}")
+                .route(web::get().to(files::show))
+                .route(web::delete().to(files::delete)),
+        )
+        .route("/downloads/{path:.*}", web::get().to(files::download))
+        .service(
+            web::resource(["/folders", "/folders/{path:.*}"])
+                .route(web::get().to(folders::show))
+                .route(web::post().to(folders::create))
+                .route(web::delete().to(folders::delete)),
+        )
+        .service(
+            web::resource(["/uploads", "/uploads/{path:.*}"])
+                .route(web::post().to(folders::upload)),
+        )
+        .route("/moves", web::post().to(moves::create))
+        .route("/search", web::get().to(search::search))
+        .default_service(web::to(|| async {
+            Err::<HttpResponse, _>(Problem::not_found())
+        }))
+}
+
+/// OpenAPI specification of the API.
+async fn openapi() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+/// Existing or new drive entry addressed by the `path` of the URL, resolved and hidden the
+/// same way as in the HTML UI.
+pub(super) struct ApiPath(PathBuf);
+
+impl FromRequest for ApiPath {
+    type Error = Problem;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut dev::Payload) -> Self::Future {
+        ready(api_path(req))
+    }
+}
+
+fn api_path(req: &HttpRequest) -> Result<ApiPath, Problem> {
+    resolve(req, req.match_info().query("path")).map(ApiPath)
+}
+
+/// Resolves a drive path given by the client, e.g. "/photos/cat.jpg" or "photos/cat.jpg".
+fn resolve(req: &HttpRequest, requested: &str) -> Result<PathBuf, Problem> {
+    let volumes = req.app_data::<web::Data<Volumes>>().unwrap();
+    let symlink_policy = req
+        .app_data::<web::Data<SymlinkPolicy>>()
+        .map(|policy| *policy.get_ref())
+        .unwrap_or_default();
+    let requested = PathBuf::from(requested.trim_start_matches('/'));
+    let path = volumes.resolve(&requested, symlink_policy).map_err(|e| {
+        debug!("Invalid path: {:?}", e);
+        match e {
+            PathResolutionError::SymlinkDenied(_) => Problem::forbidden(),
+            _ => Problem::bad_request(format!("Invalid path {:?}", requested)),
+        }
+    })?;
+    if Visibility::new(volumes).is_hidden(&path, path.is_dir()) {
+        debug!("Hidden path requested: {:?}", path);
+        return Err(Problem::not_found());
+    }
+    Ok(path)
+}
+
+impl AsRef<Path> for ApiPath {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+/// Checks whether the user may perform an operation requiring `required` on `path`.
+fn require(access: &UserAccess, path: &Path, required: Permission) -> Result<(), Problem> {
+    match access.check(path, required) {
+        AccessCheck::Allowed => Ok(()),
+        AccessCheck::Forbidden => Err(Problem::forbidden()),
+        AccessCheck::Hidden => Err(Problem::not_found()),
+    }
+}
+
+/// Rejects a request changing the entry at `path` if its `If-Match` or `If-None-Match` fails.
+async fn check_preconditions(
+    req: &HttpRequest,
+    path: &Path,
+    access: &UserAccess,
+) -> Result<(), Problem> {
+    if super::conditional::entry_preconditions_met(req, path, access).await {
+        Ok(())
+    } else {
+        Err(Problem::precondition_failed())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use utoipa::OpenApi;
+
+    use super::ApiDoc;
+
+    #[test]
+    fn test_specification() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        for path in [
+            "/files/{path}",
+            "/folders/{path}",
+            "/uploads/{path}",
+            "/search",
+        ] {
+            assert!(spec["paths"][path].is_object(), "{path} is not documented");
+        }
+        assert!(spec["components"]["schemas"]["Problem"].is_object());
+    }
+}
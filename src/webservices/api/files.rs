@@ -0,0 +1,105 @@
+use std::path::Path;
+
+use actix_files::NamedFile;
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+
+use super::{check_preconditions, model::Entry, problem::Problem, require, ApiPath};
+use crate::webservices::conditional;
+use crate::{
+    audit::{AuditAction, Auditor},
+    drive_access::{access_control::Permission, volumes::Volumes, UserAccess},
+};
+
+/// Describes a file. The `ETag` header is the entity tag of its contents, which can be sent
+/// with `If-Match` when changing the file.
+#[utoipa::path(
+    get,
+    path = "/files/{path}",
+    tag = "files",
+    params(("path" = String, Path, description = "Drive path of the file, e.g. photos/cat.jpg")),
+    responses(
+        (status = 200, body = Entry, headers(("ETag" = String, description = "Entity tag of the contents"))),
+        (status = 404, body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub(super) async fn show(
+    volumes: web::Data<Volumes>,
+    path: ApiPath,
+    access: UserAccess,
+) -> Result<HttpResponse, Problem> {
+    let path = existing_file(&path)?;
+    require(&access, path, Permission::Read)?;
+    let mut response = HttpResponse::Ok();
+    if let Some(etag) = path
+        .metadata()
+        .ok()
+        .and_then(|metadata| conditional::file_etag(&metadata))
+    {
+        response.insert_header(header::ETag(etag));
+    }
+    Ok(response.json(Entry::new(
+        volumes.drive_path(path),
+        crate::drive_access::file_info(path, &access),
+    )))
+}
+
+/// Downloads the contents of a file.
+#[utoipa::path(
+    get,
+    path = "/downloads/{path}",
+    tag = "files",
+    params(("path" = String, Path, description = "Drive path of the file, e.g. photos/cat.jpg")),
+    responses(
+        (status = 200, description = "Contents of the file, ranges are supported", content_type = "application/octet-stream"),
+        (status = 404, body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub(super) async fn download(
+    req: HttpRequest,
+    path: ApiPath,
+    access: UserAccess,
+) -> Result<HttpResponse, Problem> {
+    let path = existing_file(&path)?;
+    require(&access, path, Permission::Read)?;
+    let file = NamedFile::open(path).map_err(Problem::internal)?;
+    Ok(crate::metrics::download(file.into_response(&req)))
+}
+
+/// Deletes a file, if it still matches `If-Match` when given.
+#[utoipa::path(
+    delete,
+    path = "/files/{path}",
+    tag = "files",
+    params(("path" = String, Path, description = "Drive path of the file, e.g. photos/cat.jpg")),
+    responses(
+        (status = 204, description = "The file was deleted"),
+        (status = 403, body = Problem, content_type = "application/problem+json"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+        (status = 412, description = "The file was changed in the meantime", body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub(super) async fn delete(
+    req: HttpRequest,
+    path: ApiPath,
+    access: UserAccess,
+    auditor: Auditor,
+) -> Result<HttpResponse, Problem> {
+    let path = existing_file(&path)?;
+    require(&access, path, Permission::Delete)?;
+    check_preconditions(&req, path, &access).await?;
+    let bytes = path.metadata().ok().map(|metadata| metadata.len());
+    let result = crate::drive_access::delete_file_or_directory(&path.to_path_buf());
+    auditor.record(AuditAction::Delete, path, bytes, &result);
+    result.map_err(Problem::internal)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Folders are available under `/folders` instead.
+fn existing_file(path: &ApiPath) -> Result<&Path, Problem> {
+    let path = path.as_ref();
+    if path.is_file() {
+        Ok(path)
+    } else {
+        Err(Problem::not_found())
+    }
+}
@@ -0,0 +1,299 @@
+use std::path::Path;
+
+use actix_multipart::form::{tempfile::TempFile, MultipartForm};
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+
+use super::{
+    check_preconditions,
+    model::{
+        DeleteQuery, Entry, FolderPage, NewFolder, PageQuery, UploadFailure, UploadForm,
+        UploadResult,
+    },
+    problem::Problem,
+    require, ApiPath, API_PATH,
+};
+use crate::webservices::conditional;
+use crate::{
+    audit::{AuditAction, Auditor},
+    drive_access::{
+        self, access_control::Permission, compare_entries, path_resolution::is_plain_file_name,
+        volumes::Volumes, UserAccess, Visibility,
+    },
+};
+
+#[derive(Debug, MultipartForm)]
+pub(super) struct UploadFiles {
+    #[multipart(rename = "file")]
+    files: Vec<TempFile>,
+}
+
+/// Describes a folder and lists its entries. The `ETag` header changes with the entries, so
+/// `If-None-Match` gets `304 Not Modified` while the folder stays the same.
+#[utoipa::path(
+    get,
+    path = "/folders/{path}",
+    tag = "folders",
+    params(
+        ("path" = String, Path, description = "Drive path of the folder, e.g. photos; empty for the root folder"),
+        PageQuery
+    ),
+    responses(
+        (status = 200, body = FolderPage, headers(("ETag" = String, description = "Weak entity tag of the folder"))),
+        (status = 304, description = "The folder did not change"),
+        (status = 400, body = Problem, content_type = "application/problem+json"),
+        (status = 404, body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub(super) async fn show(
+    req: HttpRequest,
+    volumes: web::Data<Volumes>,
+    path: ApiPath,
+    access: UserAccess,
+    query: web::Query<PageQuery>,
+) -> Result<HttpResponse, Problem> {
+    let dir = existing_folder(&path)?;
+    // traversable folders can be listed, showing only readable entries
+    if !access.is_listable(dir, true) {
+        return Err(Problem::not_found());
+    }
+    let listing = drive_access::list_files(&dir.to_path_buf(), &access)
+        .await
+        .map_err(Problem::internal)?;
+    let etag = conditional::listing_etag(dir, &listing);
+    if conditional::is_not_modified(&req, &etag) {
+        return Ok(HttpResponse::NotModified()
+            .insert_header(header::ETag(etag))
+            .finish());
+    }
+    let folder_path = volumes.drive_path(dir);
+    let (files, next_cursor) = query.paginate(
+        listing.files,
+        |info| format!("{}{}", if info.is_dir { 'd' } else { 'f' }, info.name),
+        |info, after| {
+            let (kind, name) = after.split_at(after.chars().next().map_or(0, char::len_utf8));
+            compare_entries((info.is_dir, &info.name), (kind == "d", name)).is_gt()
+        },
+    )?;
+    let entries = files
+        .into_iter()
+        .map(|info| Entry::new(child_path(&folder_path, &info.name), info))
+        .collect();
+    let folder = Entry::new(folder_path, drive_access::file_info(dir, &access));
+    Ok(HttpResponse::Ok()
+        .insert_header(header::ETag(etag))
+        .insert_header(header::CacheControl(vec![header::CacheDirective::NoCache]))
+        .json(FolderPage::new(folder, (entries, next_cursor))))
+}
+
+/// Creates a folder in a folder.
+#[utoipa::path(
+    post,
+    path = "/folders/{path}",
+    tag = "folders",
+    params(("path" = String, Path, description = "Drive path of the parent folder, empty for the root folder")),
+    request_body = NewFolder,
+    responses(
+        (status = 201, body = Entry),
+        (status = 400, body = Problem, content_type = "application/problem+json"),
+        (status = 403, body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "An entry with the name exists already", body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub(super) async fn create(
+    volumes: web::Data<Volumes>,
+    path: ApiPath,
+    access: UserAccess,
+    auditor: Auditor,
+    new_folder: web::Json<NewFolder>,
+) -> Result<HttpResponse, Problem> {
+    let dir = existing_folder(&path)?;
+    let name = new_folder.name.as_str();
+    if !is_plain_file_name(name) {
+        return Err(Problem::bad_request("Invalid folder name"));
+    }
+    let new_dir = dir.join(name);
+    // the folder could not be used afterwards
+    if Visibility::new(&volumes).is_hidden(&new_dir, true) {
+        return Err(Problem::bad_request("Folders with this name are hidden"));
+    }
+    require(&access, &new_dir, Permission::Write)?;
+    if new_dir.exists() {
+        return Err(Problem::conflict("An entry with this name exists already"));
+    }
+    let result = drive_access::create_dir(&new_dir);
+    auditor.record(AuditAction::CreateDir, &new_dir, None, &result);
+    result.map_err(Problem::internal)?;
+
+    let new_dir_path = volumes.drive_path(&new_dir);
+    Ok(HttpResponse::Created()
+        .insert_header((
+            header::LOCATION,
+            format!("{API_PATH}/folders{}", new_dir_path),
+        ))
+        .json(Entry::new(
+            new_dir_path,
+            drive_access::file_info(&new_dir, &access),
+        )))
+}
+
+/// Deletes a folder with all its contents, or only when it is empty with `recursive=false`,
+/// if it still matches `If-Match` when given.
+#[utoipa::path(
+    delete,
+    path = "/folders/{path}",
+    tag = "folders",
+    params(
+        ("path" = String, Path, description = "Drive path of the folder, e.g. photos"),
+        DeleteQuery
+    ),
+    responses(
+        (status = 204, description = "The folder was deleted"),
+        (status = 403, body = Problem, content_type = "application/problem+json"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "The folder is not empty", body = Problem, content_type = "application/problem+json"),
+        (status = 412, description = "The folder was changed in the meantime", body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub(super) async fn delete(
+    req: HttpRequest,
+    volumes: web::Data<Volumes>,
+    path: ApiPath,
+    access: UserAccess,
+    auditor: Auditor,
+    query: web::Query<DeleteQuery>,
+) -> Result<HttpResponse, Problem> {
+    let dir = existing_folder(&path)?;
+    if volumes.drive_path(dir) == "/" {
+        return Err(Problem::forbidden().with_detail("The root folder cannot be deleted"));
+    }
+    require(&access, dir, Permission::Delete)?;
+    check_preconditions(&req, dir, &access).await?;
+    let result = if query.recursive == Some(false) {
+        drive_access::delete_empty_directory(dir)
+    } else {
+        drive_access::delete_file_or_directory(&dir.to_path_buf())
+    };
+    let not_empty = result.as_ref().is_err_and(|e| {
+        e.downcast_ref::<std::io::Error>()
+            .is_some_and(|e| e.kind() == std::io::ErrorKind::DirectoryNotEmpty)
+    });
+    if not_empty {
+        return Err(Problem::conflict("The folder is not empty"));
+    }
+    auditor.record(AuditAction::Delete, dir, None, &result);
+    result.map_err(Problem::internal)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Uploads files to a folder. Existing files are not overwritten, a number is added to
+/// the names of new files instead, unless the request is conditional: with `If-Match` the
+/// file with the uploaded name is replaced if it matches, and `If-None-Match: *` fails
+/// when it exists.
+#[utoipa::path(
+    post,
+    path = "/uploads/{path}",
+    tag = "uploads",
+    params(("path" = String, Path, description = "Drive path of the folder, empty for the root folder")),
+    request_body(content = UploadForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "Files were processed, some of them may have failed", body = UploadResult),
+        (status = 403, body = Problem, content_type = "application/problem+json"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+        (status = 412, description = "A file was changed in the meantime, nothing was saved", body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub(super) async fn upload(
+    req: HttpRequest,
+    volumes: web::Data<Volumes>,
+    path: ApiPath,
+    access: UserAccess,
+    auditor: Auditor,
+    form: MultipartForm<UploadFiles>,
+) -> Result<web::Json<UploadResult>, Problem> {
+    let dir = existing_folder(&path)?;
+    // e.g. upload-only access can write to folders it cannot list
+    require(&access, dir, Permission::Write)?;
+    let folder_path = volumes.drive_path(dir);
+    let mut result = UploadResult {
+        saved: Vec::new(),
+        failed: Vec::new(),
+    };
+    let files = form.into_inner().files;
+    if !conditional::upload_preconditions_met(&req, dir, &files, &access).await {
+        return Err(Problem::precondition_failed());
+    }
+    let max_size = volumes.max_upload_size(dir);
+    let on_conflict = conditional::upload_conflicts(&req);
+    for (name, saved) in drive_access::save_files(files, dir, None, max_size, on_conflict) {
+        let file_path = dir.join(&name);
+        let bytes = saved
+            .as_ref()
+            .ok()
+            .and_then(|file| file.metadata().ok())
+            .map(|metadata| metadata.len());
+        auditor.record(AuditAction::Upload, &file_path, bytes, &saved);
+        match saved {
+            Ok(_) => result.saved.push(Entry::new(
+                child_path(&folder_path, &name),
+                drive_access::file_info(&file_path, &access),
+            )),
+            Err(e) => result.failed.push(UploadFailure {
+                name,
+                detail: e.to_string(),
+            }),
+        }
+    }
+    Ok(web::Json(result))
+}
+
+/// Files are available under `/files` instead.
+fn existing_folder(path: &ApiPath) -> Result<&Path, Problem> {
+    let path = path.as_ref();
+    if path.is_dir() {
+        Ok(path)
+    } else {
+        Err(Problem::not_found())
+    }
+}
+
+fn child_path(folder_path: &str, name: &str) -> String {
+    format!("{}/{}", folder_path.trim_end_matches('/'), name)
+}
+
+#[cfg(test)]
+mod test {
+    use actix_web::{
+        http::StatusCode,
+        test::{self, TestRequest},
+        web, App,
+    };
+
+    use crate::drive_access::volumes::Volumes;
+
+    #[actix_web::test]
+    async fn test_delete_empty_folder() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("gone")).unwrap();
+        std::fs::write(dir.path().join("gone/.hidden"), "kept").unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(Volumes::single(dir.path())))
+                .service(super::super::scope(1024)),
+        )
+        .await;
+        let delete = || {
+            TestRequest::delete()
+                .uri("/api/v1/folders/gone?recursive=false")
+                .to_request()
+        };
+
+        let response = test::call_service(&app, delete()).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert!(dir.path().join("gone/.hidden").exists());
+
+        std::fs::remove_file(dir.path().join("gone/.hidden")).unwrap();
+        let response = test::call_service(&app, delete()).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert!(!dir.path().join("gone").exists());
+    }
+}
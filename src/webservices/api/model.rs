@@ -0,0 +1,259 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
+use super::problem::Problem;
+use crate::drive_access::{FileInfo, Permissions};
+
+/// Entries returned when the `limit` is not given.
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+
+/// File or folder of the drive.
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub(super) struct Entry {
+    /// Drive path, e.g. "/photos/cat.jpg"; the root folder is "/".
+    path: String,
+    /// Empty for the root folder.
+    name: String,
+    kind: EntryKind,
+    /// Size in bytes, files only.
+    size: Option<u64>,
+    /// Unix time of the last modification.
+    modified_at: Option<u64>,
+    /// Detected from the contents, files only.
+    media_type: Option<String>,
+    permissions: EntryPermissions,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum EntryKind {
+    File,
+    Folder,
+}
+
+/// Operations the user may perform on an entry.
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub(super) struct EntryPermissions {
+    read: bool,
+    write: bool,
+    delete: bool,
+    share: bool,
+}
+
+impl From<Permissions> for EntryPermissions {
+    fn from(permissions: Permissions) -> Self {
+        Self {
+            read: permissions.read,
+            write: permissions.write,
+            delete: permissions.delete,
+            share: permissions.share,
+        }
+    }
+}
+
+impl Entry {
+    /// Describes an entry found at the drive path `path`.
+    pub(super) fn new(path: String, info: FileInfo) -> Self {
+        let metadata = info.metadata.as_ref();
+        Self {
+            name: if path == "/" {
+                String::new()
+            } else {
+                info.name
+            },
+            path,
+            kind: if info.is_dir {
+                EntryKind::Folder
+            } else {
+                EntryKind::File
+            },
+            size: metadata
+                .and_then(|metadata| metadata.size)
+                .filter(|_| !info.is_dir),
+            modified_at: metadata.and_then(|metadata| metadata.modified_at),
+            media_type: info.file_type.map(|file_type| file_type.mime),
+            permissions: info.permissions.into(),
+        }
+    }
+}
+
+/// Folder with a page of its entries, folders first, then ordered by name.
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub(super) struct FolderPage {
+    folder: Entry,
+    entries: Vec<Entry>,
+    /// Cursor of the following page, missing on the last one.
+    next_cursor: Option<String>,
+}
+
+impl FolderPage {
+    pub(super) fn new(folder: Entry, (entries, next_cursor): (Vec<Entry>, Option<String>)) -> Self {
+        Self {
+            folder,
+            entries,
+            next_cursor,
+        }
+    }
+}
+
+/// Page of search results, ordered by path.
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub(super) struct SearchPage {
+    entries: Vec<Entry>,
+    /// Cursor of the following page, missing on the last one.
+    next_cursor: Option<String>,
+}
+
+impl SearchPage {
+    pub(super) fn new((entries, next_cursor): (Vec<Entry>, Option<String>)) -> Self {
+        Self {
+            entries,
+            next_cursor,
+        }
+    }
+}
+
+/// Outcome of an upload, files are saved independently of each other.
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub(super) struct UploadResult {
+    /// Saved files, renamed when their names were taken already.
+    pub(super) saved: Vec<Entry>,
+    pub(super) failed: Vec<UploadFailure>,
+}
+
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub(super) struct UploadFailure {
+    pub(super) name: String,
+    pub(super) detail: String,
+}
+
+/// Multipart form of uploads, for the specification only.
+#[derive(utoipa::ToSchema)]
+#[allow(dead_code)]
+pub(super) struct UploadForm {
+    /// Files to upload, the field can be repeated.
+    #[schema(value_type = Vec<String>, format = Binary)]
+    file: Vec<Vec<u8>>,
+}
+
+/// New folder, created in the folder given by the URL.
+#[derive(Debug, serde::Deserialize, utoipa::ToSchema)]
+pub(super) struct NewFolder {
+    pub(super) name: String,
+}
+
+/// How a folder is deleted.
+#[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(super) struct DeleteQuery {
+    /// With `false` only an empty folder is deleted, otherwise `409 Conflict` is returned;
+    /// hidden entries count as contents. `true` by default.
+    pub(super) recursive: Option<bool>,
+}
+
+#[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(super) struct PageQuery {
+    /// Maximum number of entries, 100 by default and at most 1000.
+    limit: Option<usize>,
+    /// `next_cursor` of the previous page.
+    cursor: Option<String>,
+}
+
+impl PageQuery {
+    /// Returns a page of the `items`, which must be ordered by their `key`s.
+    ///
+    /// The cursor holds the key of the last returned item, so pages stay consistent when
+    /// entries are added or removed in the meantime.
+    pub(super) fn paginate<T>(
+        &self,
+        items: Vec<T>,
+        key: impl Fn(&T) -> String,
+        follows: impl Fn(&T, &str) -> bool,
+    ) -> Result<(Vec<T>, Option<String>), Problem> {
+        let limit = self.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+        if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+            return Err(Problem::bad_request(format!(
+                "Limit must be between 1 and {MAX_PAGE_SIZE}"
+            )));
+        }
+        let after = match &self.cursor {
+            Some(cursor) => Some(decode_cursor(cursor)?),
+            None => None,
+        };
+        let mut items = items
+            .into_iter()
+            .skip_while(|item| after.as_deref().is_some_and(|after| !follows(item, after)))
+            .take(limit + 1)
+            .collect::<Vec<_>>();
+        let next_cursor = if items.len() > limit {
+            items.truncate(limit);
+            items.last().map(|item| URL_SAFE_NO_PAD.encode(key(item)))
+        } else {
+            None
+        };
+        Ok((items, next_cursor))
+    }
+}
+
+fn decode_cursor(cursor: &str) -> Result<String, Problem> {
+    URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|key| String::from_utf8(key).ok())
+        .ok_or_else(|| Problem::bad_request("Invalid cursor"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::PageQuery;
+
+    fn page(items: &[u32], limit: usize, cursor: Option<String>) -> (Vec<u32>, Option<String>) {
+        let query = PageQuery {
+            limit: Some(limit),
+            cursor,
+        };
+        query
+            .paginate(
+                items.to_vec(),
+                |item| item.to_string(),
+                |item, after| *item > after.parse().unwrap(),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn test_pagination() {
+        let items = [1, 2, 3, 4, 5];
+        let (first, cursor) = page(&items, 2, None);
+        assert_eq!(first, [1, 2]);
+        let (second, cursor) = page(&items, 2, cursor);
+        assert_eq!(second, [3, 4]);
+        // the page continues after the last seen item even if it was removed
+        let (last, cursor) = page(&[1, 2, 5], 2, cursor);
+        assert_eq!(last, [5]);
+        assert!(cursor.is_none());
+
+        let (all, cursor) = page(&items, 5, None);
+        assert_eq!(all.len(), 5);
+        assert!(cursor.is_none());
+    }
+
+    #[test]
+    fn test_invalid_pages() {
+        let query = PageQuery {
+            limit: Some(0),
+            cursor: None,
+        };
+        assert!(query
+            .paginate(vec![1], |item| item.to_string(), |_, _| true)
+            .is_err());
+        let query = PageQuery {
+            limit: None,
+            cursor: Some("not base64!".to_owned()),
+        };
+        assert!(query
+            .paginate(vec![1], |item| item.to_string(), |_, _| true)
+            .is_err());
+    }
+}
@@ -0,0 +1,79 @@
+use actix_web::{web, HttpRequest};
+
+use super::{check_preconditions, model::Entry, problem::Problem, require, resolve};
+use crate::{
+    audit::{AuditAction, Auditor},
+    drive_access::{self, access_control::Permission, volumes::Volumes, UserAccess},
+};
+
+/// Move or rename of a file or folder.
+#[derive(Debug, serde::Deserialize, utoipa::ToSchema)]
+pub(super) struct Move {
+    /// Drive path of the entry, e.g. "/photos/cat.jpg".
+    from: String,
+    /// New drive path of the entry, which must not exist yet.
+    to: String,
+}
+
+/// Moves or renames a file or folder, if it still matches `If-Match` when given.
+#[utoipa::path(
+    post,
+    path = "/moves",
+    tag = "files",
+    request_body = Move,
+    responses(
+        (status = 200, description = "The entry at its new path", body = Entry),
+        (status = 400, body = Problem, content_type = "application/problem+json"),
+        (status = 403, body = Problem, content_type = "application/problem+json"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "The target exists already, its folder does not exist or it is in another volume", body = Problem, content_type = "application/problem+json"),
+        (status = 412, description = "The entry was changed in the meantime", body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub(super) async fn create(
+    req: HttpRequest,
+    volumes: web::Data<Volumes>,
+    access: UserAccess,
+    auditor: Auditor,
+    request: web::Json<Move>,
+) -> Result<web::Json<Entry>, Problem> {
+    let from = resolve(&req, &request.from)?;
+    let to = resolve(&req, &request.to)?;
+    if !from.exists() {
+        return Err(Problem::not_found());
+    }
+    if volumes.drive_path(&from) == "/" {
+        return Err(Problem::forbidden().with_detail("The root folder cannot be moved"));
+    }
+    require(&access, &from, Permission::Delete)?;
+    require(&access, &to, Permission::Write)?;
+    check_preconditions(&req, &from, &access).await?;
+    if to.starts_with(&from) {
+        return Err(Problem::bad_request("A folder cannot be moved into itself"));
+    }
+    if to.exists() {
+        return Err(Problem::conflict(
+            "An entry exists at the target path already",
+        ));
+    }
+    if !to.parent().is_some_and(|parent| parent.is_dir()) {
+        return Err(Problem::conflict("The target folder does not exist"));
+    }
+    if volumes.base_of(&from) != volumes.base_of(&to) {
+        return Err(Problem::conflict(
+            "Entries cannot be moved between volumes, copy them instead",
+        ));
+    }
+    let bytes = from
+        .metadata()
+        .ok()
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len());
+    let result = drive_access::move_entry(&from, &to);
+    auditor.record_transfer(AuditAction::Move, &from, &to, bytes, &result);
+    result.map_err(Problem::internal)?;
+    Ok(web::Json(Entry::new(
+        volumes.drive_path(&to),
+        drive_access::file_info(&to, &access),
+    )))
+}
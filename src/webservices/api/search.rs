@@ -0,0 +1,55 @@
+use actix_web::web;
+
+use super::{
+    model::{Entry, PageQuery, SearchPage},
+    problem::Problem,
+};
+use crate::drive_access::{self, volumes::Volumes, UserAccess};
+
+#[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(super) struct SearchQuery {
+    /// Beginning of the names to look for, ignoring case.
+    q: String,
+}
+
+/// Finds readable files and folders anywhere in the drive by the beginning of their names.
+#[utoipa::path(
+    get,
+    path = "/search",
+    tag = "search",
+    params(SearchQuery, PageQuery),
+    responses(
+        (status = 200, body = SearchPage),
+        (status = 400, body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub(super) async fn search(
+    volumes: web::Data<Volumes>,
+    access: UserAccess,
+    query: web::Query<SearchQuery>,
+    page: web::Query<PageQuery>,
+) -> Result<web::Json<SearchPage>, Problem> {
+    let q = query.q.trim();
+    if q.is_empty() {
+        return Err(Problem::bad_request("The search query must not be empty"));
+    }
+    // only the glob pattern built from the query can be invalid
+    let paths = drive_access::search_paths(q, &access)
+        .map_err(|e| Problem::bad_request(format!("Invalid search query: {e}")))?;
+    let mut found = paths
+        .into_iter()
+        .map(|path| (volumes.drive_path(&path), path))
+        .collect::<Vec<_>>();
+    found.sort_by(|(left, _), (right, _)| left.cmp(right));
+    let (found, next_cursor) = page.paginate(
+        found,
+        |(path, _)| path.clone(),
+        |(path, _), after| path.as_str() > after,
+    )?;
+    let entries = found
+        .into_iter()
+        .map(|(drive_path, path)| Entry::new(drive_path, drive_access::file_info(&path, &access)))
+        .collect();
+    Ok(web::Json(SearchPage::new((entries, next_cursor))))
+}
@@ -0,0 +1,170 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix_web::{http::header, web, HttpMessage, HttpRequest, HttpResponse, Responder};
+use handlebars::Handlebars;
+use serde_json::json;
+use tracing::{info, warn};
+
+use super::{
+    page_context::PageContext,
+    response_renderer::ResponseRenderer,
+    shares::{describe_age, describe_duration},
+};
+use crate::{
+    api_tokens::{ApiToken, TokenScope, TokenStore},
+    server::CurrentUser,
+};
+
+const MAX_EXPIRY_DAYS: u32 = 365;
+
+#[derive(Debug, serde::Deserialize)]
+pub(super) struct NewTokenForm {
+    name: String,
+    scope: TokenScope,
+    /// Empty for tokens which never expire.
+    #[serde(default)]
+    expires_in_days: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct TokenSummary {
+    id: String,
+    name: String,
+    scope: TokenScope,
+    created: String,
+    expires_in: Option<String>,
+    expired: bool,
+    last_used: Option<String>,
+}
+
+/// Token management page.
+pub(super) async fn list(
+    req: HttpRequest,
+    hb: web::Data<Handlebars<'_>>,
+    tokens: Option<web::Data<TokenStore>>,
+    user: Option<web::ReqData<CurrentUser>>,
+    page: PageContext,
+) -> impl Responder {
+    let (Some(tokens), Some(user)) = (tokens, user) else {
+        return HttpResponse::NotFound().finish();
+    };
+    if uses_api_token(&req) {
+        return HttpResponse::Forbidden().finish();
+    }
+    let summaries = tokens
+        .list(&user.name)
+        .iter()
+        .map(summary)
+        .collect::<Vec<_>>();
+    ResponseRenderer::new(
+        json!({ "tokens": summaries }),
+        "tokens",
+        hb.into_inner().clone(),
+    )
+    .with_page(page)
+    .respond_to(&req)
+    .map_into_boxed_body()
+}
+
+/// Creates a token, showing its secret once.
+pub(super) async fn create(
+    req: HttpRequest,
+    hb: web::Data<Handlebars<'_>>,
+    tokens: Option<web::Data<TokenStore>>,
+    user: Option<web::ReqData<CurrentUser>>,
+    form: web::Form<NewTokenForm>,
+) -> impl Responder {
+    let (Some(tokens), Some(user)) = (tokens, user) else {
+        return HttpResponse::NotFound().finish();
+    };
+    // a leaked token must not be able to create more of them
+    if uses_api_token(&req) {
+        return HttpResponse::Forbidden().finish();
+    }
+    let name = form.name.trim();
+    if name.is_empty() {
+        return HttpResponse::BadRequest().body("Token name is required");
+    }
+    let expires_in = match form.expires_in_days.trim() {
+        "" => None,
+        days => match days.parse::<u32>() {
+            Ok(days) if (1..=MAX_EXPIRY_DAYS).contains(&days) => {
+                Some(Duration::from_secs(u64::from(days) * 24 * 60 * 60))
+            }
+            _ => {
+                return HttpResponse::BadRequest().body(format!(
+                    "Expiry must be between 1 and {MAX_EXPIRY_DAYS} days"
+                ))
+            }
+        },
+    };
+    match tokens.create(&user.name, user.is_admin, name, form.scope, expires_in) {
+        Ok((token, secret)) => {
+            info!("API token {} created for {}", token.id, user.name);
+            let body = crate::handlebars_utils::render(
+                &hb,
+                "token_created",
+                &json!({ "secret": secret, "token": summary(&token) }),
+            )
+            .unwrap();
+            HttpResponse::Ok()
+                .insert_header(header::ContentType::html())
+                .body(body)
+        }
+        Err(e) => {
+            warn!("Failed to create API token: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub(super) async fn revoke(
+    req: HttpRequest,
+    tokens: Option<web::Data<TokenStore>>,
+    user: Option<web::ReqData<CurrentUser>>,
+    id: web::Path<String>,
+) -> impl Responder {
+    let (Some(tokens), Some(user)) = (tokens, user) else {
+        return HttpResponse::NotFound().finish();
+    };
+    if uses_api_token(&req) {
+        return HttpResponse::Forbidden().finish();
+    }
+    match tokens.revoke(&id, &user.name) {
+        Ok(true) => {
+            info!("API token {} revoked", id);
+            // HTMX removes the revoked token's row
+            HttpResponse::Ok().finish()
+        }
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(e) => {
+            warn!("Failed to revoke API token: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Checks whether the request is authenticated with an API token instead of a session.
+fn uses_api_token(req: &HttpRequest) -> bool {
+    req.extensions().contains::<ApiToken>()
+}
+
+fn summary(token: &ApiToken) -> TokenSummary {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let ago = |time: u64| describe_age(now.saturating_sub(time));
+    TokenSummary {
+        id: token.id.clone(),
+        name: token.name.clone(),
+        scope: token.scope,
+        created: ago(token.created_at),
+        expires_in: token
+            .expires_at
+            .filter(|_| !token.is_expired(now))
+            .map(|expires_at| describe_duration(expires_at - now)),
+        expired: token.is_expired(now),
+        last_used: token.last_used_at.map(ago),
+    }
+}
@@ -0,0 +1,65 @@
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use handlebars::Handlebars;
+use serde_json::json;
+use tracing::warn;
+
+use super::{page_context::PageContext, response_renderer::ResponseRenderer};
+use crate::{
+    audit::{AuditFilter, AuditLog},
+    server::CurrentUser,
+};
+
+const PAGE_SIZE: usize = 100;
+
+#[derive(Debug, serde::Deserialize)]
+pub(super) struct AuditQuery {
+    #[serde(flatten)]
+    filter: AuditFilter,
+    #[serde(default)]
+    page: usize,
+}
+
+/// Audit page for administrators, or for everybody when authentication is disabled.
+pub(super) async fn show(
+    req: HttpRequest,
+    hb: web::Data<Handlebars<'_>>,
+    audit_log: Option<web::Data<AuditLog>>,
+    user: Option<web::ReqData<CurrentUser>>,
+    page: PageContext,
+    query: web::Query<AuditQuery>,
+) -> impl Responder {
+    let Some(audit_log) = audit_log else {
+        return HttpResponse::NotFound().finish();
+    };
+    if user.is_some_and(|user| !user.is_admin) {
+        return HttpResponse::Forbidden().finish();
+    }
+    let query = query.into_inner();
+    let mut records = match audit_log.query(&query.filter, query.page * PAGE_SIZE, PAGE_SIZE + 1) {
+        Ok(records) => records,
+        Err(e) => {
+            warn!("Failed to read audit log: {:?}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let has_next = records.len() > PAGE_SIZE;
+    records.truncate(PAGE_SIZE);
+    let page_url = |page: usize| {
+        let mut query = serde_urlencoded::to_string(&query.filter).unwrap_or_default();
+        query.push_str(&format!("&page={page}"));
+        format!("/audit?{query}")
+    };
+    ResponseRenderer::new(
+        json!({
+            "records": records,
+            "filter": query.filter,
+            "previous": (query.page > 0).then(|| page_url(query.page - 1)),
+            "next": has_next.then(|| page_url(query.page + 1)),
+        }),
+        "audit",
+        hb.into_inner().clone(),
+    )
+    .with_page(page)
+    .respond_to(&req)
+    .map_into_boxed_body()
+}
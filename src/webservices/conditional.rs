@@ -0,0 +1,236 @@
+use std::{fs::Metadata, path::Path, time::UNIX_EPOCH};
+
+use actix_multipart::form::tempfile::TempFile;
+use actix_web::{
+    http::header::{self, EntityTag},
+    HttpMessage, HttpRequest,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use sha2::{Digest, Sha256};
+
+use crate::drive_access::{
+    path_resolution::is_plain_file_name, FilesResult, OnConflict, UserAccess,
+};
+
+/// Entity tag of a file, the same as `NamedFile` sends with its contents.
+pub(super) fn file_etag(metadata: &Metadata) -> Option<EntityTag> {
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    #[cfg(unix)]
+    let ino = std::os::unix::fs::MetadataExt::ino(metadata);
+    #[cfg(not(unix))]
+    let ino = 0;
+    Some(EntityTag::new_strong(format!(
+        "{:x}:{:x}:{:x}:{:x}",
+        ino,
+        metadata.len(),
+        modified.as_secs(),
+        modified.subsec_nanos()
+    )))
+}
+
+/// Weak entity tag of the `listing` of the folder `dir`, changing with its entries, their
+/// metadata and the permissions of the user.
+pub(super) fn listing_etag(dir: &Path, listing: &FilesResult) -> EntityTag {
+    let mut hasher = Sha256::new();
+    // the listing is rendered differently by other versions
+    hasher.update(env!("CARGO_PKG_VERSION"));
+    hasher.update(serde_json::to_vec(listing).unwrap_or_default());
+    // listed times are in seconds, files changed within a second differ in their tags
+    for file in listing.files.iter().filter(|file| !file.is_dir) {
+        if let Some(etag) = dir
+            .join(&file.name)
+            .metadata()
+            .ok()
+            .and_then(|metadata| file_etag(&metadata))
+        {
+            hasher.update(etag.tag());
+        }
+    }
+    EntityTag::new_weak(URL_SAFE_NO_PAD.encode(&hasher.finalize()[..16]))
+}
+
+/// Current entity tag of the entry at `path` as seen by the user, `None` if it does not exist.
+async fn current_etag(path: &Path, access: &UserAccess) -> Option<EntityTag> {
+    let metadata = path.metadata().ok()?;
+    if metadata.is_dir() {
+        let listing = crate::drive_access::list_files(&path.to_path_buf(), access).await;
+        Some(listing.map_or_else(
+            |_| EntityTag::new_weak(String::new()),
+            |l| listing_etag(path, &l),
+        ))
+    } else {
+        file_etag(&metadata)
+    }
+}
+
+/// Checks `If-None-Match` of a GET request, true when the client has the `current` representation.
+pub(super) fn is_not_modified(req: &HttpRequest, current: &EntityTag) -> bool {
+    match if_none_match(req) {
+        Some(header::IfNoneMatch::Any) => true,
+        Some(header::IfNoneMatch::Items(items)) => items.iter().any(|item| item.weak_eq(current)),
+        None => false,
+    }
+}
+
+/// Evaluates `If-Match` and `If-None-Match` of a request changing the entry with the
+/// `current` entity tag (`None` if it does not exist), true when the request may proceed.
+///
+/// Files are compared strongly; folders only have weak tags, which are compared weakly.
+pub(super) fn preconditions_met(req: &HttpRequest, current: Option<&EntityTag>) -> bool {
+    let matches = |items: &[EntityTag], current: &EntityTag| {
+        items.iter().any(|item| {
+            if current.weak {
+                item.weak_eq(current)
+            } else {
+                item.strong_eq(current)
+            }
+        })
+    };
+    let if_match = match if_match(req) {
+        Some(header::IfMatch::Any) => current.is_some(),
+        Some(header::IfMatch::Items(items)) => {
+            current.is_some_and(|current| matches(&items, current))
+        }
+        None => true,
+    };
+    let if_none_match = match if_none_match(req) {
+        Some(header::IfNoneMatch::Any) => current.is_none(),
+        Some(header::IfNoneMatch::Items(items)) => {
+            !current.is_some_and(|current| items.iter().any(|item| item.weak_eq(current)))
+        }
+        None => true,
+    };
+    if_match && if_none_match
+}
+
+/// Checks the preconditions of a request changing the entry at `path`. Computing the entity tag
+/// of a folder lists it, so it is only done for conditional requests.
+pub(super) async fn entry_preconditions_met(
+    req: &HttpRequest,
+    path: &Path,
+    access: &UserAccess,
+) -> bool {
+    if !is_conditional(req) {
+        return true;
+    }
+    let current = current_etag(path, access).await;
+    preconditions_met(req, current.as_ref())
+}
+
+/// Checks the preconditions of an upload against the files in `dir` named like the uploaded ones.
+pub(super) async fn upload_preconditions_met(
+    req: &HttpRequest,
+    dir: &Path,
+    files: &[TempFile],
+    access: &UserAccess,
+) -> bool {
+    if !is_conditional(req) {
+        return true;
+    }
+    for name in files.iter().filter_map(|file| file.file_name.as_deref()) {
+        // invalid names are rejected when saving
+        if is_plain_file_name(name) {
+            let current = current_etag(&dir.join(name), access).await;
+            if !preconditions_met(req, current.as_ref()) {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// How uploads treat existing files: conditional requests address the file with the uploaded
+/// name, so it is replaced, or never overwritten with `If-None-Match: *`. Without conditions
+/// a number is added to the names of new files.
+pub(super) fn upload_conflicts(req: &HttpRequest) -> OnConflict {
+    match (if_match(req), if_none_match(req)) {
+        (_, Some(header::IfNoneMatch::Any)) => OnConflict::Fail,
+        (None, None) => OnConflict::Rename,
+        _ => OnConflict::Replace,
+    }
+}
+
+fn is_conditional(req: &HttpRequest) -> bool {
+    if_match(req).is_some() || if_none_match(req).is_some()
+}
+
+fn if_match(req: &HttpRequest) -> Option<header::IfMatch> {
+    req.headers()
+        .contains_key(header::IF_MATCH)
+        .then(|| req.get_header())
+        .flatten()
+}
+
+fn if_none_match(req: &HttpRequest) -> Option<header::IfNoneMatch> {
+    req.headers()
+        .contains_key(header::IF_NONE_MATCH)
+        .then(|| req.get_header())
+        .flatten()
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use actix_web::{http::header::EntityTag, test::TestRequest};
+
+    use super::{entry_preconditions_met, is_not_modified, preconditions_met, upload_conflicts};
+    use crate::drive_access::{volumes::Volumes, OnConflict, UserAccess};
+
+    #[test]
+    fn test_preconditions() {
+        let file = EntityTag::new_strong("1:2:3:4".to_owned());
+        let folder = EntityTag::new_weak("abc".to_owned());
+        let request = |name, value| {
+            TestRequest::default()
+                .insert_header((name, value))
+                .to_http_request()
+        };
+
+        let req = request("If-Match", "\"1:2:3:4\"");
+        assert!(preconditions_met(&req, Some(&file)));
+        assert!(!preconditions_met(&req, None));
+        let req = request("If-Match", "\"1:2:3:5\", W/\"1:2:3:4\"");
+        assert!(!preconditions_met(&req, Some(&file)));
+        assert!(preconditions_met(
+            &request("If-Match", "W/\"abc\""),
+            Some(&folder)
+        ));
+        assert!(preconditions_met(&request("If-Match", "*"), Some(&file)));
+
+        let req = request("If-None-Match", "*");
+        assert!(!preconditions_met(&req, Some(&file)));
+        assert!(preconditions_met(&req, None));
+        assert_eq!(upload_conflicts(&req), OnConflict::Fail);
+        let req = request("If-None-Match", "W/\"abc\"");
+        assert!(is_not_modified(&req, &folder));
+        assert!(!preconditions_met(&req, Some(&folder)));
+        assert!(!is_not_modified(
+            &req,
+            &EntityTag::new_weak("abd".to_owned())
+        ));
+
+        let req = TestRequest::default().to_http_request();
+        assert!(preconditions_met(&req, Some(&file)));
+        assert!(!is_not_modified(&req, &folder));
+        assert_eq!(upload_conflicts(&req), OnConflict::Rename);
+        assert_eq!(
+            upload_conflicts(&request("If-Match", "\"1:2:3:4\"")),
+            OnConflict::Replace
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_entry_preconditions() {
+        let dir = tempfile::tempdir().unwrap();
+        let access = UserAccess::new(None, None, false, Arc::new(Volumes::single(dir.path())));
+        let missing = dir.path().join("missing");
+        let req = TestRequest::default().to_http_request();
+        assert!(entry_preconditions_met(&req, &missing, &access).await);
+        let req = TestRequest::default()
+            .insert_header(("If-Match", "*"))
+            .to_http_request();
+        assert!(!entry_preconditions_met(&req, &missing, &access).await);
+        assert!(entry_preconditions_met(&req, dir.path(), &access).await);
+    }
+}
@@ -1,6 +1,11 @@
 use actix_multipart::form::text::Text;
 use actix_web::{web, Either, HttpResponse, Responder};
 use handlebars::Handlebars;
+
+use crate::{
+    audit::{AuditAction, Auditor},
+    drive_access::UserAccess,
+};
 use tracing::trace_span;
 
 use std::path::PathBuf;
@@ -20,12 +25,13 @@ pub(super) struct NewDirRequest {
 
 pub(super) async fn handle(
     hb: web::Data<Handlebars<'_>>,
-    base_dir: web::Data<PathBuf>,
     form: EitherInputExtended<NewDirRequest, NewDirForm>,
     path: web::ReqData<crate::server::RequestedPath>,
+    access: UserAccess,
+    auditor: Auditor,
 ) -> impl Responder {
     let path = path.as_ref();
-    let dir_path = base_dir.join(path).to_path_buf();
+    let dir_path = path.to_path_buf();
     // create new folder
     let form_wrapper = EitherInputExtendedWrapper(form);
     let form = (&form_wrapper).into();
@@ -34,19 +40,24 @@ pub(super) async fn handle(
         Either::Right(form) => form.new_folder_name.as_str(),
     };
 
+    if !crate::drive_access::path_resolution::is_plain_file_name(new_dir_name) {
+        return HttpResponse::BadRequest()
+            .body(super::FileListInputError::InvalidPath(PathBuf::from(new_dir_name)).to_string());
+    }
     let new_dir_path = dir_path.join(new_dir_name);
-    let data = 
-    { 
-        let span = trace_span!("create dir", path=new_dir_path.to_str());
+    let data = {
+        let span = trace_span!("create dir", path = new_dir_path.to_str());
         let _enter = span.enter();
         crate::drive_access::create_dir(&new_dir_path)
     };
+    auditor.record(AuditAction::CreateDir, &new_dir_path, None, &data);
     match data {
         Ok(_) => {
-            let data = crate::drive_access::list_files(&dir_path, &base_dir).await;
+            let data = crate::drive_access::list_files(&dir_path, &access).await;
             match data {
                 Ok(data) => {
-                    let body = hb.render("files_listing", &data).unwrap();
+                    let body =
+                        crate::handlebars_utils::render(&hb, "files_listing", &data).unwrap();
                     HttpResponse::Ok().body(body)
                 }
                 Err(_) => HttpResponse::InternalServerError().finish(),
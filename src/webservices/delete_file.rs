@@ -1,23 +1,38 @@
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
 use handlebars::Handlebars;
+
+use super::conditional;
+use crate::{
+    audit::{AuditAction, Auditor},
+    drive_access::UserAccess,
+};
 use serde_json::json;
 use tracing::trace_span;
 
-use std::path::PathBuf;
-
 pub(super) async fn handle(
+    req: HttpRequest,
     hb: web::Data<Handlebars<'_>>,
-    base_dir: web::Data<PathBuf>,
     path: web::ReqData<crate::server::RequestedPath>,
+    access: UserAccess,
+    auditor: Auditor,
 ) -> impl Responder {
     let path = path.as_ref();
-    let dir_path = base_dir.join(path).to_path_buf();
+    let dir_path = path.to_path_buf();
+    if !conditional::entry_preconditions_met(&req, &dir_path, &access).await {
+        return HttpResponse::PreconditionFailed().body("The entry was changed in the meantime");
+    }
 
+    let bytes = dir_path
+        .metadata()
+        .ok()
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len());
     let data = {
         let span = trace_span!("delete file or directory", path = path.to_str());
         let _enter = span.enter();
         crate::drive_access::delete_file_or_directory(&dir_path)
     };
+    auditor.record(AuditAction::Delete, &dir_path, bytes, &data);
     match data {
         Ok(_) => {
             let span = trace_span!(
@@ -25,17 +40,19 @@ pub(super) async fn handle(
                 path = dir_path.parent().and_then(|parent| parent.to_str())
             );
             let _enter = span.enter();
-            let data = crate::drive_access::list_files(
-                &dir_path.parent().unwrap().to_path_buf(),
-                &base_dir,
-            )
-            .await;
+            let data =
+                crate::drive_access::list_files(&dir_path.parent().unwrap().to_path_buf(), &access)
+                    .await;
             match data {
                 Ok(data) => {
-                    let body = hb.render("files_listing", &data).unwrap();
-                    let confirmation_toast = hb
-                        .render("confirmation_toast", &json!({ "message": "File deleted" }))
-                        .unwrap();
+                    let body =
+                        crate::handlebars_utils::render(&hb, "files_listing", &data).unwrap();
+                    let confirmation_toast = crate::handlebars_utils::render(
+                        &hb,
+                        "confirmation_toast",
+                        &json!({ "message": "File deleted" }),
+                    )
+                    .unwrap();
                     HttpResponse::Ok().body(format!("{}{}", body, confirmation_toast))
                 }
                 Err(_) => HttpResponse::InternalServerError()
@@ -1,26 +1,35 @@
 use actix_web::{http::header, web, Either, HttpResponse, Responder};
 use handlebars::Handlebars;
-use std::path::PathBuf;
 
-use super::list_files::list_files_or_file_contents;
+use super::{conditional, list_files::list_files_or_file_contents};
+use crate::drive_access::UserAccess;
 
 pub(super) async fn handle(
     hb: web::Data<Handlebars<'_>>,
-    base_dir: web::Data<PathBuf>,
     path: web::ReqData<crate::server::RequestedPath>,
     req: actix_web::HttpRequest,
+    access: UserAccess,
 ) -> impl Responder {
     let path = path.into_inner().into();
-    let data = list_files_or_file_contents(&path, &base_dir).await;
+    let data = list_files_or_file_contents(&path, &access).await;
     match data {
         Ok(data) => match data {
             Either::Left(data) => {
-                let body = hb.render("files_listing", &data).unwrap();
+                // lets polling clients revalidate the listing instead of downloading it again
+                let etag = conditional::listing_etag(&path, &data);
+                if conditional::is_not_modified(&req, &etag) {
+                    return HttpResponse::NotModified()
+                        .insert_header(header::ETag(etag))
+                        .finish();
+                }
+                let body = crate::handlebars_utils::render(&hb, "files_listing", &data).unwrap();
                 HttpResponse::Ok()
                     .insert_header(header::ContentType::html())
+                    .insert_header(header::ETag(etag))
+                    .insert_header(header::CacheControl(vec![header::CacheDirective::NoCache]))
                     .body(body)
             }
-            Either::Right(file) => file.into_response(&req),
+            Either::Right(file) => crate::metrics::download(file.into_response(&req)),
         },
         Err(anyhow_err) => match anyhow_err.downcast_ref::<super::FileListInputError>() {
             Some(err) => HttpResponse::BadRequest().body(err.to_string()),
@@ -0,0 +1,92 @@
+use std::path::Path;
+
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use serde_json::json;
+
+use crate::{
+    drive_access::{read_only::ReadOnlyPaths, volumes::Volumes},
+    server::{CurrentUser, Shutdown},
+};
+
+pub(crate) const METRICS_PATH: &str = "/metrics";
+
+/// Free space below which a disk is reported as full.
+const MIN_AVAILABLE_SPACE: u64 = 64 * 1024 * 1024;
+
+/// Liveness probe, answering as long as the process serves requests.
+pub(super) async fn healthz() -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "status": "ok" }))
+}
+
+/// Readiness probe: the directories of the drive can be read, and written unless read-only,
+/// their disks are not full and the server is not shutting down.
+pub(super) async fn readyz(
+    volumes: web::Data<Volumes>,
+    read_only: Option<web::Data<ReadOnlyPaths>>,
+    shutdown: web::Data<Shutdown>,
+) -> HttpResponse {
+    let mut problems = Vec::new();
+    if shutdown.is_requested() {
+        problems.push("The server is shutting down".to_owned());
+    }
+    let directories = std::iter::once(("/".to_owned(), volumes.root().to_path_buf())).chain(
+        volumes
+            .volumes()
+            .iter()
+            .map(|volume| (format!("/{}", volume.name), volume.path.clone())),
+    );
+    for (name, directory) in directories {
+        let writable = !read_only.as_ref().is_some_and(|read_only| {
+            read_only.is_read_only(Path::new(name.trim_start_matches('/')))
+        });
+        let checked = web::block(move || check_directory(&directory, writable)).await;
+        match checked {
+            Ok(Ok(())) => {}
+            Ok(Err(problem)) => problems.push(format!("{name}: {problem}")),
+            Err(e) => problems.push(format!("{name}: {e}")),
+        }
+    }
+    if problems.is_empty() {
+        HttpResponse::Ok().json(json!({ "status": "ready" }))
+    } else {
+        HttpResponse::ServiceUnavailable()
+            .json(json!({ "status": "not ready", "problems": problems }))
+    }
+}
+
+fn check_directory(directory: &Path, writable: bool) -> Result<(), String> {
+    std::fs::read_dir(directory).map_err(|e| format!("cannot be read: {e}"))?;
+    if writable {
+        // hidden from listings and removed when dropped
+        tempfile::Builder::new()
+            .prefix(".my-drive-ready")
+            .tempfile_in(directory)
+            .map_err(|e| format!("cannot be written: {e}"))?;
+        let available =
+            fs4::available_space(directory).map_err(|e| format!("unknown free space: {e}"))?;
+        if available < MIN_AVAILABLE_SPACE {
+            return Err(format!("disk is full, {available} bytes available"));
+        }
+    }
+    Ok(())
+}
+
+/// Metrics in the Prometheus text format, for administrators and scrapers with the
+/// `METRICS_TOKEN` when authentication is enabled.
+pub(super) async fn metrics(
+    volumes: web::Data<Volumes>,
+    user: Option<web::ReqData<CurrentUser>>,
+) -> HttpResponse {
+    if user.is_some_and(|user| !user.is_admin) {
+        return HttpResponse::Forbidden().finish();
+    }
+    let text = web::block(move || crate::metrics::metrics().render(&volumes)).await;
+    match text {
+        Ok(text) => HttpResponse::Ok()
+            .content_type(ContentType(
+                "text/plain; version=0.0.4; charset=utf-8".parse().unwrap(),
+            ))
+            .body(text),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
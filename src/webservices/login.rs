@@ -0,0 +1,200 @@
+use actix_session::Session;
+use actix_web::{http::header, web, HttpResponse, HttpResponseBuilder, Responder};
+use handlebars::Handlebars;
+use serde_json::json;
+use tracing::{info, warn};
+
+use crate::{
+    server::{
+        csrf_token,
+        oidc::{OidcLoginState, OidcProvider},
+        sign_in, sign_out, SignInMethod, SignedOutSessions, LOGIN_PATH,
+    },
+    users::UserStore,
+};
+
+/// Session key of the pending OpenID Connect login.
+const SESSION_OIDC_LOGIN_KEY: &str = "oidc_login";
+
+#[derive(Debug, serde::Deserialize)]
+pub(super) struct LoginQuery {
+    next: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub(super) struct LoginForm {
+    username: String,
+    password: String,
+    next: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub(super) struct OidcCallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+pub(super) async fn show(
+    hb: web::Data<Handlebars<'_>>,
+    users: Option<web::Data<UserStore>>,
+    oidc: Option<web::Data<OidcProvider>>,
+    session: Session,
+    query: web::Query<LoginQuery>,
+) -> impl Responder {
+    render_login_page(
+        &hb,
+        &session,
+        users.is_some(),
+        oidc.as_ref().map(|oidc| oidc.get_ref()),
+        json!({ "next": safe_next(query.next.as_deref()) }),
+        HttpResponse::Ok(),
+    )
+}
+
+pub(super) async fn login(
+    hb: web::Data<Handlebars<'_>>,
+    users: Option<web::Data<UserStore>>,
+    oidc: Option<web::Data<OidcProvider>>,
+    session: Session,
+    form: web::Form<LoginForm>,
+) -> impl Responder {
+    let next = safe_next(form.next.as_deref());
+    let Some(users) = users else {
+        // password login is disabled
+        return HttpResponse::NotFound().finish();
+    };
+    match users.verify(&form.username, &form.password) {
+        Some(user) => {
+            info!("User {} signed in", user.name);
+            if let Err(e) = sign_in(&session, &user.name, SignInMethod::Password) {
+                warn!("Failed to store session: {:?}", e);
+                return HttpResponse::InternalServerError().finish();
+            }
+            redirect(next)
+        }
+        None => {
+            warn!("Failed sign in attempt for user {:?}", form.username);
+            render_login_page(
+                &hb,
+                &session,
+                true,
+                oidc.as_ref().map(|oidc| oidc.get_ref()),
+                json!({
+                    "next": next,
+                    "username": form.username,
+                    "error": "Invalid user name or password",
+                }),
+                HttpResponse::Unauthorized(),
+            )
+        }
+    }
+}
+
+/// Redirects to the OpenID Connect provider's authorization page.
+pub(super) async fn oidc_start(
+    oidc: Option<web::Data<OidcProvider>>,
+    session: Session,
+    query: web::Query<LoginQuery>,
+) -> impl Responder {
+    let Some(oidc) = oidc else {
+        return HttpResponse::NotFound().finish();
+    };
+    let next = safe_next(query.next.as_deref()).to_owned();
+    let (auth_url, login_state) = oidc.start_login(next);
+    if let Err(e) = session.insert(SESSION_OIDC_LOGIN_KEY, login_state) {
+        warn!("Failed to store session: {:?}", e);
+        return HttpResponse::InternalServerError().finish();
+    }
+    redirect(&auth_url)
+}
+
+/// Completes the OpenID Connect login when the provider redirects the user back.
+pub(super) async fn oidc_callback(
+    hb: web::Data<Handlebars<'_>>,
+    users: Option<web::Data<UserStore>>,
+    oidc: Option<web::Data<OidcProvider>>,
+    session: Session,
+    query: web::Query<OidcCallbackQuery>,
+) -> impl Responder {
+    let Some(oidc) = oidc else {
+        return HttpResponse::NotFound().finish();
+    };
+    let login_state = session.remove_as::<OidcLoginState>(SESSION_OIDC_LOGIN_KEY);
+    let result = match (login_state, &query.code, &query.state) {
+        (Some(Ok(login_state)), Some(code), Some(state)) => oidc
+            .finish_login(&login_state, code.clone(), state)
+            .await
+            .map(|user| (user, login_state.next)),
+        _ => Err(anyhow::anyhow!(
+            "Invalid authorization response, error: {:?}",
+            query.error
+        )),
+    };
+    match result {
+        Ok((user, next)) => {
+            info!("User {} signed in with {}", user.name, oidc.name());
+            if let Err(e) = sign_in(&session, &user.name, SignInMethod::Oidc) {
+                warn!("Failed to store session: {:?}", e);
+                return HttpResponse::InternalServerError().finish();
+            }
+            redirect(&next)
+        }
+        Err(e) => {
+            warn!("Failed OIDC sign in: {:?}", e);
+            render_login_page(
+                &hb,
+                &session,
+                users.is_some(),
+                Some(oidc.get_ref()),
+                json!({ "error": format!("Signing in with {} failed", oidc.name()) }),
+                HttpResponse::Unauthorized(),
+            )
+        }
+    }
+}
+
+fn render_login_page(
+    hb: &Handlebars<'_>,
+    session: &Session,
+    password_login: bool,
+    oidc: Option<&OidcProvider>,
+    mut data: serde_json::Value,
+    mut response: HttpResponseBuilder,
+) -> HttpResponse {
+    data["password_login"] = json!(password_login);
+    data["oidc_provider"] = json!(oidc.map(|oidc| oidc.name()));
+    data["csrf_token"] = json!(csrf_token(session));
+    let body = crate::handlebars_utils::render(hb, "login", &data).unwrap();
+    response
+        .insert_header(header::ContentType::html())
+        .body(body)
+}
+
+pub(super) async fn logout(
+    session: Session,
+    signed_out: Option<web::Data<SignedOutSessions>>,
+) -> impl Responder {
+    sign_out(
+        &session,
+        signed_out.as_ref().map(|signed_out| signed_out.get_ref()),
+    );
+    redirect(LOGIN_PATH)
+}
+
+fn redirect(location: &str) -> HttpResponse {
+    HttpResponse::SeeOther()
+        .insert_header((header::LOCATION, location))
+        .finish()
+}
+
+/// Accepts only local paths as redirection targets, so the login page cannot be used
+/// to send users to other sites.
+fn safe_next(next: Option<&str>) -> &str {
+    match next {
+        Some(next) if next.starts_with('/') && !next.starts_with("//") && !next.contains('\\') => {
+            next
+        }
+        _ => "/",
+    }
+}
@@ -0,0 +1,46 @@
+use std::future::{ready, Ready};
+
+use actix_session::SessionExt;
+use actix_web::{FromRequest, HttpMessage};
+
+use crate::{
+    api_tokens::TokenStore,
+    audit::AuditLog,
+    server::{csrf_token, CurrentUser},
+    shares::ShareStore,
+};
+
+/// Data shared by all pages, available in templates under the `page` key.
+#[derive(Debug, Default, Clone, serde::Serialize)]
+pub(crate) struct PageContext {
+    user: Option<CurrentUser>,
+    /// Share links are enabled.
+    sharing: bool,
+    /// API tokens can be managed.
+    api_tokens: bool,
+    /// The audit log is enabled and can be viewed by the user.
+    audit_log: bool,
+    /// Token sent with state-changing requests, see [`crate::server::CsrfProtection`].
+    csrf_token: String,
+}
+
+impl FromRequest for PageContext {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &actix_web::HttpRequest, _: &mut actix_web::dev::Payload) -> Self::Future {
+        let user = req.extensions().get::<CurrentUser>().cloned();
+        let api_tokens =
+            user.is_some() && req.app_data::<actix_web::web::Data<TokenStore>>().is_some();
+        // without authentication there are no administrators, everybody can see the log
+        let audit_log = req.app_data::<actix_web::web::Data<AuditLog>>().is_some()
+            && user.as_ref().is_none_or(|user| user.is_admin);
+        ready(Ok(PageContext {
+            user,
+            sharing: req.app_data::<actix_web::web::Data<ShareStore>>().is_some(),
+            api_tokens,
+            audit_log,
+            csrf_token: csrf_token(&req.get_session()),
+        }))
+    }
+}
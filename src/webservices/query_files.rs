@@ -1,8 +1,8 @@
-use std::path::PathBuf;
-
 use actix_multipart::form::text::Text;
 use actix_web::{web, Either, HttpResponse, Responder};
 use handlebars::Handlebars;
+
+use crate::drive_access::UserAccess;
 use serde_json::json;
 
 use super::utilities::multitype_input::{EitherInputExtended, EitherInputExtendedWrapper};
@@ -18,9 +18,9 @@ pub(super) struct QueryFilterRequestMultipart {
 }
 
 pub(super) async fn handle(
-    request: EitherInputExtended<QueryFilterRequest,QueryFilterRequestMultipart>,
+    request: EitherInputExtended<QueryFilterRequest, QueryFilterRequestMultipart>,
     hb: web::Data<Handlebars<'_>>,
-    base_dir: web::Data<PathBuf>,
+    access: UserAccess,
 ) -> impl Responder + '_ {
     let request_wrapper = EitherInputExtendedWrapper(request);
     let request = (&request_wrapper).into();
@@ -28,8 +28,8 @@ pub(super) async fn handle(
         Either::Left(query) => query.query.as_str(),
         Either::Right(query) => query.query.as_str(),
     };
-    
-    let files = crate::drive_access::query_files(query, base_dir.as_ref());
+
+    let files = crate::drive_access::query_files(query, &access);
     match files {
         Ok(files) => {
             let response = super::response_renderer::ResponseRenderer::new(
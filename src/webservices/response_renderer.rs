@@ -1,11 +1,22 @@
 use actix_web::http::header;
 use tracing::debug_span;
 
+use super::page_context::PageContext;
+
 #[derive(Debug)]
 pub(super) struct ResponseRenderer<'a, T: serde::ser::Serialize> {
     data: T,
     template: &'static str,
     hb: std::sync::Arc<handlebars::Handlebars<'a>>,
+    page: Option<PageContext>,
+}
+
+/// Template data extended with the page context, which is not a part of JSON responses.
+#[derive(serde::Serialize)]
+struct TemplateData<'a, T: serde::ser::Serialize> {
+    #[serde(flatten)]
+    data: &'a T,
+    page: &'a Option<PageContext>,
 }
 
 impl<'a, T: serde::ser::Serialize> ResponseRenderer<'a, T> {
@@ -14,7 +25,17 @@ impl<'a, T: serde::ser::Serialize> ResponseRenderer<'a, T> {
         template: &'static str,
         hb: std::sync::Arc<handlebars::Handlebars<'a>>,
     ) -> Self {
-        Self { data, template, hb }
+        Self {
+            data,
+            template,
+            hb,
+            page: None,
+        }
+    }
+
+    pub fn with_page(mut self, page: PageContext) -> Self {
+        self.page = Some(page);
+        self
     }
 }
 
@@ -36,7 +57,12 @@ where
             }) {
             let hb_span = debug_span!("render_template", meta.hb.template = self.template);
             let _enter = hb_span.enter();
-            let body = self.hb.render(self.template, &self.data).unwrap();
+            let template_data = TemplateData {
+                data: &self.data,
+                page: &self.page,
+            };
+            let body =
+                crate::handlebars_utils::render(&self.hb, self.template, &template_data).unwrap();
             drop(_enter);
             actix_web::Either::Left(
                 actix_web::HttpResponse::Ok()
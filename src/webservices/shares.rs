@@ -0,0 +1,606 @@
+use std::{
+    net::IpAddr,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use actix_files::NamedFile;
+use actix_session::Session;
+use actix_web::{http::header, web, HttpRequest, HttpResponse, Responder};
+use handlebars::Handlebars;
+use serde_json::json;
+use tracing::{info, warn};
+
+use super::{page_context::PageContext, response_renderer::ResponseRenderer};
+use crate::{
+    api_tokens::{ApiToken, TokenScope},
+    drive_access::{
+        path_resolution::{resolve_path, SymlinkPolicy},
+        volumes::Volumes,
+        FileInfo, Permissions, UserAccess, Visibility,
+    },
+    server::{access_of, client_ip, csrf_token, CurrentUser, FailedAttempts, FAILURE_WINDOW},
+    shares::{NewShare, Share, ShareError, ShareKind, ShareStore, SHARE_PATH},
+};
+
+/// Session key of the password protected shares unlocked by the visitor.
+const SESSION_UNLOCKED_SHARES_KEY: &str = "unlocked_shares";
+/// Limits the number of remembered shares, so the session fits in a cookie.
+const MAX_UNLOCKED_SHARES: usize = 20;
+const MAX_EXPIRY_DAYS: u32 = 365;
+
+/// Invalid share passwords per share and client, so passwords cannot be guessed quickly.
+pub(crate) type ShareUnlockFailures = FailedAttempts<(String, IpAddr)>;
+
+#[derive(Debug, serde::Deserialize)]
+pub(super) struct NewShareForm {
+    path: String,
+    #[serde(default)]
+    kind: ShareKind,
+    expires_in_days: u32,
+    #[serde(default)]
+    max_downloads: String,
+    #[serde(default)]
+    max_uploads: String,
+    /// Upload size limit in megabytes.
+    #[serde(default)]
+    max_upload_size: String,
+    #[serde(default)]
+    password: String,
+}
+
+#[derive(serde::Deserialize)]
+pub(super) struct UnlockForm {
+    password: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct ShareSummary {
+    id: String,
+    kind: ShareKind,
+    path: String,
+    is_dir: bool,
+    url: String,
+    expires_in: Option<String>,
+    downloads: u32,
+    max_downloads: Option<u32>,
+    uploads: u32,
+    max_uploads: Option<u32>,
+    uploaded_bytes: u64,
+    max_upload_size: Option<u64>,
+    has_password: bool,
+}
+
+/// Read-only listing of a shared folder.
+#[derive(Debug, serde::Serialize)]
+struct SharedListing {
+    name: String,
+    path: String,
+    parent: Option<String>,
+    files: Vec<FileInfo>,
+}
+
+/// Creates a share link for the file or folder at `path`.
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn create(
+    req: HttpRequest,
+    hb: web::Data<Handlebars<'_>>,
+    volumes: web::Data<Volumes>,
+    symlink_policy: Option<web::Data<SymlinkPolicy>>,
+    shares: Option<web::Data<ShareStore>>,
+    user: Option<web::ReqData<CurrentUser>>,
+    access: UserAccess,
+    form: web::Form<NewShareForm>,
+) -> impl Responder {
+    let Some(shares) = shares else {
+        return HttpResponse::NotFound().finish();
+    };
+    if !(1..=MAX_EXPIRY_DAYS).contains(&form.expires_in_days) {
+        return HttpResponse::BadRequest().body(format!(
+            "Expiry must be between 1 and {MAX_EXPIRY_DAYS} days"
+        ));
+    }
+    let (Ok(max_downloads), Ok(max_uploads), Ok(max_upload_size)) = (
+        parse_limit(&form.max_downloads),
+        parse_limit(&form.max_uploads),
+        parse_limit::<u64>(&form.max_upload_size),
+    ) else {
+        return HttpResponse::BadRequest().body("Invalid limit");
+    };
+    let requested = Path::new(form.path.trim_start_matches('/'));
+    let policy = symlink_policy.map(|p| *p.get_ref()).unwrap_or_default();
+    let Ok(path) = volumes.resolve(requested, policy) else {
+        return HttpResponse::BadRequest()
+            .body(super::FileListInputError::InvalidPath(requested.to_path_buf()).to_string());
+    };
+    let is_dir = path.is_dir();
+    if !path.exists() || Visibility::new(&volumes).is_hidden(&path, is_dir) {
+        return HttpResponse::NotFound().finish();
+    }
+    let permissions = access.permissions(&path);
+    if !permissions.share {
+        return HttpResponse::Forbidden().finish();
+    }
+    if form.kind == ShareKind::Upload {
+        if !is_dir {
+            return HttpResponse::BadRequest().body("Files can be uploaded only to folders");
+        }
+        if !permissions.write {
+            return HttpResponse::Forbidden().finish();
+        }
+    }
+
+    let user = user.map(|user| user.into_inner());
+    let new_share = NewShare {
+        kind: form.kind,
+        owner: user.as_ref().map(|user| user.name.clone()),
+        owner_is_admin: user.as_ref().is_some_and(|user| user.is_admin),
+        path: volumes.relative(&path).unwrap_or_default(),
+        is_dir,
+        expires_in: Duration::from_secs(u64::from(form.expires_in_days) * 24 * 60 * 60),
+        max_downloads,
+        max_uploads,
+        max_upload_size: max_upload_size.map(|megabytes| megabytes.saturating_mul(1024 * 1024)),
+        password: Some(form.password.as_str()).filter(|password| !password.is_empty()),
+    };
+    match shares.create(new_share) {
+        Ok((share, token)) => {
+            info!("Shared {:?} until {}", share.path, share.expires_at);
+            let body = crate::handlebars_utils::render(
+                &hb,
+                "share_link",
+                &json!({ "url": share_url(&req, &token), "share": summary(&req, &shares, &share) }),
+            )
+            .unwrap();
+            HttpResponse::Ok()
+                .insert_header(header::ContentType::html())
+                .body(body)
+        }
+        Err(e) => {
+            warn!("Failed to create share: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// "My shares" page.
+pub(super) async fn list(
+    req: HttpRequest,
+    hb: web::Data<Handlebars<'_>>,
+    shares: Option<web::Data<ShareStore>>,
+    user: Option<web::ReqData<CurrentUser>>,
+    page: PageContext,
+) -> impl Responder {
+    let Some(shares) = shares else {
+        return HttpResponse::NotFound().finish();
+    };
+    let owner = user.as_ref().map(|user| user.name.as_str());
+    let summaries = shares
+        .list(owner)
+        .iter()
+        .map(|share| summary(&req, &shares, share))
+        .collect::<Vec<_>>();
+    ResponseRenderer::new(
+        json!({ "shares": summaries }),
+        "shares",
+        hb.into_inner().clone(),
+    )
+    .with_page(page)
+    .respond_to(&req)
+    .map_into_boxed_body()
+}
+
+pub(super) async fn revoke(
+    shares: Option<web::Data<ShareStore>>,
+    user: Option<web::ReqData<CurrentUser>>,
+    token: Option<web::ReqData<ApiToken>>,
+    id: web::Path<String>,
+) -> impl Responder {
+    let Some(shares) = shares else {
+        return HttpResponse::NotFound().finish();
+    };
+    if token.is_some_and(|token| token.scope != TokenScope::Full) {
+        return HttpResponse::Forbidden().finish();
+    }
+    let owner = user.as_ref().map(|user| user.name.as_str());
+    let is_admin = user.as_ref().is_some_and(|user| user.is_admin);
+    match shares.revoke(&id, owner, is_admin) {
+        Ok(true) => {
+            info!("Share {} revoked", id);
+            // HTMX removes the revoked share's row
+            HttpResponse::Ok().finish()
+        }
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(e) => {
+            warn!("Failed to revoke share: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Read-only view of a shared file or folder (or the upload form of an upload link),
+/// available without signing in.
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn open(
+    req: HttpRequest,
+    hb: web::Data<Handlebars<'_>>,
+    volumes: web::Data<Volumes>,
+    symlink_policy: Option<web::Data<SymlinkPolicy>>,
+    shares: Option<web::Data<ShareStore>>,
+    session: Session,
+) -> impl Responder {
+    let Some(shares) = shares else {
+        return HttpResponse::NotFound().finish();
+    };
+    let token = req.match_info().query("token");
+    let share = match shares.resolve(token) {
+        Ok(share) => share,
+        Err(e) => return share_error(&hb, e),
+    };
+    if share.has_password() && !is_unlocked(&session, &share.id) {
+        return render_access_page(&hb, &session, &share, token, None, HttpResponse::Ok());
+    }
+
+    let sub_path = Path::new(req.match_info().get("path").unwrap_or(""));
+    if share.kind == ShareKind::Upload {
+        if !sub_path.as_os_str().is_empty() {
+            return HttpResponse::NotFound().finish();
+        }
+        return render_upload_page(&hb, &session, &share, token);
+    }
+
+    let root = volumes.join(&share.path);
+    let path = if sub_path.as_os_str().is_empty() {
+        root.clone()
+    } else if share.is_dir {
+        // the shared folder is the root of the view, nothing outside it can be reached
+        let policy = symlink_policy.map(|p| *p.get_ref()).unwrap_or_default();
+        let resolved = if root == volumes.root() {
+            // volumes are folders of the shared root
+            volumes.resolve(sub_path, policy)
+        } else {
+            resolve_path(&root, sub_path, policy)
+        };
+        match resolved {
+            Ok(path) => path,
+            Err(_) => return HttpResponse::NotFound().finish(),
+        }
+    } else {
+        return HttpResponse::NotFound().finish();
+    };
+    let is_dir = path.is_dir();
+    if Visibility::new(&volumes).is_hidden(&path, is_dir) {
+        return HttpResponse::NotFound().finish();
+    }
+    // the view never shows more than the owner can see
+    let access = access_of(&req, share.owner.clone(), share.owner_is_admin)
+        .restricted(Permissions::read_only());
+
+    if path.is_file() && access.permissions(&path).read {
+        if let Err(e) = shares.record_download(&share.id) {
+            return share_error(&hb, e);
+        }
+        return match NamedFile::open(&path) {
+            Ok(file) => crate::metrics::download(file.into_response(&req)),
+            Err(_) => HttpResponse::NotFound().finish(),
+        };
+    }
+    if !is_dir || !access.is_listable(&path, true) {
+        return HttpResponse::NotFound().finish();
+    }
+    let files = match crate::drive_access::list_files(&path, &access).await {
+        Ok(data) => data.files,
+        Err(_) => return HttpResponse::NotFound().finish(),
+    };
+    let relative = path.strip_prefix(&root).unwrap_or(Path::new(""));
+    let share_root = format!("{SHARE_PATH}/{token}");
+    let listing = SharedListing {
+        name: share
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "My Drive".to_owned()),
+        path: join_url(&share_root, relative),
+        parent: relative
+            .parent()
+            .map(|parent| join_url(&share_root, parent)),
+        files,
+    };
+    let template = if req.headers().contains_key("HX-Request") {
+        "shared_listing"
+    } else {
+        "shared_index"
+    };
+    let body = crate::handlebars_utils::render(&hb, template, &listing).unwrap();
+    HttpResponse::Ok()
+        .insert_header(header::ContentType::html())
+        .body(body)
+}
+
+/// Unlocks a password protected share for the rest of the visitor's session.
+/// Clients entering invalid passwords repeatedly are refused for a while.
+pub(super) async fn unlock(
+    req: HttpRequest,
+    hb: web::Data<Handlebars<'_>>,
+    shares: Option<web::Data<ShareStore>>,
+    failures: Option<web::Data<ShareUnlockFailures>>,
+    session: Session,
+    token: web::Path<String>,
+    form: web::Form<UnlockForm>,
+) -> impl Responder {
+    let Some(shares) = shares else {
+        return HttpResponse::NotFound().finish();
+    };
+    let share = match shares.resolve(&token) {
+        Ok(share) => share,
+        Err(e) => return share_error(&hb, e),
+    };
+    let attempt = failures
+        .zip(client_ip(&req))
+        .map(|(failures, client)| (failures, (share.id.clone(), client)));
+    if let Some((failures, key)) = &attempt {
+        if failures.is_throttled(key) {
+            warn!(
+                "Too many invalid passwords for share {} from {}",
+                key.0, key.1
+            );
+            let mut response = HttpResponse::TooManyRequests();
+            response.insert_header((header::RETRY_AFTER, FAILURE_WINDOW.as_secs()));
+            return render_access_page(
+                &hb,
+                &session,
+                &share,
+                &token,
+                Some("Too many invalid passwords, try again later"),
+                response,
+            );
+        }
+    }
+    if !share.verify_password(&form.password) {
+        warn!("Invalid password for share {}", share.id);
+        if let Some((failures, key)) = attempt {
+            failures.record_failure(key);
+        }
+        return render_access_page(
+            &hb,
+            &session,
+            &share,
+            &token,
+            Some("Invalid password"),
+            HttpResponse::Unauthorized(),
+        );
+    }
+    if let Some((failures, key)) = &attempt {
+        failures.record_success(key);
+    }
+    let mut unlocked = session
+        .get::<Vec<String>>(SESSION_UNLOCKED_SHARES_KEY)
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    unlocked.retain(|id| *id != share.id);
+    unlocked.push(share.id);
+    if unlocked.len() > MAX_UNLOCKED_SHARES {
+        unlocked.remove(0);
+    }
+    if let Err(e) = session.insert(SESSION_UNLOCKED_SHARES_KEY, unlocked) {
+        warn!("Failed to store session: {:?}", e);
+        return HttpResponse::InternalServerError().finish();
+    }
+    HttpResponse::SeeOther()
+        .insert_header((header::LOCATION, format!("{SHARE_PATH}/{token}")))
+        .finish()
+}
+
+pub(super) fn is_unlocked(session: &Session, id: &str) -> bool {
+    session
+        .get::<Vec<String>>(SESSION_UNLOCKED_SHARES_KEY)
+        .ok()
+        .flatten()
+        .is_some_and(|unlocked| unlocked.iter().any(|unlocked| unlocked == id))
+}
+
+fn render_access_page(
+    hb: &Handlebars<'_>,
+    session: &Session,
+    share: &Share,
+    token: &str,
+    error: Option<&str>,
+    mut response: actix_web::HttpResponseBuilder,
+) -> HttpResponse {
+    let body = crate::handlebars_utils::render(
+        hb,
+        "share_access",
+        &json!({
+            "password_required": true,
+            "action": format!("{SHARE_PATH}/{token}"),
+            "name": share.path.file_name().map(|name| name.to_string_lossy()),
+            "error": error,
+            "csrf_token": csrf_token(session),
+        }),
+    )
+    .unwrap();
+    response
+        .insert_header(header::ContentType::html())
+        .body(body)
+}
+
+pub(super) fn render_upload_page(
+    hb: &Handlebars<'_>,
+    session: &Session,
+    share: &Share,
+    token: &str,
+) -> HttpResponse {
+    let body = crate::handlebars_utils::render(
+        hb,
+        "share_upload",
+        &json!({
+            "action": format!("{SHARE_PATH}/{token}"),
+            "name": share.path.file_name().map(|name| name.to_string_lossy()),
+            "max_uploads": share.max_uploads.map(|max| max.saturating_sub(share.uploads)),
+            "max_upload_size": share
+                .max_upload_size
+                .map(|max| max.saturating_sub(share.uploaded_bytes)),
+            "csrf_token": csrf_token(session),
+        }),
+    )
+    .unwrap();
+    HttpResponse::Ok()
+        .insert_header(header::ContentType::html())
+        .body(body)
+}
+
+pub(super) fn share_error(hb: &Handlebars<'_>, error: ShareError) -> HttpResponse {
+    let mut response = match error {
+        ShareError::InvalidLink | ShareError::Revoked => HttpResponse::NotFound(),
+        ShareError::Expired | ShareError::DownloadLimitReached | ShareError::UploadLimitReached => {
+            HttpResponse::Gone()
+        }
+        ShareError::UploadLimitExceeded => HttpResponse::PayloadTooLarge(),
+    };
+    let body =
+        crate::handlebars_utils::render(hb, "share_access", &json!({ "error": error.to_string() }))
+            .unwrap();
+    response
+        .insert_header(header::ContentType::html())
+        .body(body)
+}
+
+fn summary(req: &HttpRequest, shares: &ShareStore, share: &Share) -> ShareSummary {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    ShareSummary {
+        id: share.id.clone(),
+        kind: share.kind,
+        path: format!("/{}", share.path.to_string_lossy()),
+        is_dir: share.is_dir,
+        url: share_url(req, &shares.token(share)),
+        expires_in: (!share.is_expired(now)).then(|| describe_duration(share.expires_at - now)),
+        downloads: share.downloads,
+        max_downloads: share.max_downloads,
+        uploads: share.uploads,
+        max_uploads: share.max_uploads,
+        uploaded_bytes: share.uploaded_bytes,
+        max_upload_size: share.max_upload_size,
+        has_password: share.has_password(),
+    }
+}
+
+fn share_url(req: &HttpRequest, token: &str) -> String {
+    let connection_info = req.connection_info();
+    format!(
+        "{}://{}{SHARE_PATH}/{token}",
+        connection_info.scheme(),
+        connection_info.host()
+    )
+}
+
+fn join_url(share_root: &str, relative: &Path) -> String {
+    if relative.as_os_str().is_empty() {
+        share_root.to_owned()
+    } else {
+        format!("{share_root}/{}", relative.to_string_lossy())
+    }
+}
+
+/// Parses an optional, positive limit entered in a form.
+fn parse_limit<T: std::str::FromStr + Default + PartialOrd>(value: &str) -> Result<Option<T>, ()> {
+    match value.trim() {
+        "" => Ok(None),
+        value => match value.parse::<T>() {
+            Ok(limit) if limit > T::default() => Ok(Some(limit)),
+            _ => Err(()),
+        },
+    }
+}
+
+/// Rough, human readable length of a period, e.g. "3 days".
+pub(super) fn describe_duration(seconds: u64) -> String {
+    let (value, unit) = match seconds {
+        s if s >= 24 * 60 * 60 => (s / (24 * 60 * 60), "day"),
+        s if s >= 60 * 60 => (s / (60 * 60), "hour"),
+        s => ((s / 60).max(1), "minute"),
+    };
+    format!("{value} {unit}{}", if value == 1 { "" } else { "s" })
+}
+
+/// How long ago something happened, e.g. "5 minutes ago".
+pub(super) fn describe_age(seconds: u64) -> String {
+    if seconds < 60 {
+        "just now".to_owned()
+    } else {
+        format!("{} ago", describe_duration(seconds))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::PathBuf, time::Duration};
+
+    use actix_web::{
+        cookie::Key,
+        http::{header, StatusCode},
+        test::{self, TestRequest},
+        web, App,
+    };
+
+    use super::{unlock, ShareUnlockFailures};
+    use crate::{
+        server::{failed_attempts::MAX_FAILURES, session_middleware},
+        shares::{NewShare, ShareKind, ShareStore, SHARE_PATH},
+    };
+
+    #[actix_web::test]
+    async fn test_unlock_throttling() {
+        let dir = tempfile::tempdir().unwrap();
+        let shares = ShareStore::load(
+            &dir.path().join("shares.json"),
+            "0123456789abcdef0123456789abcdef",
+        )
+        .unwrap();
+        let (_, token) = shares
+            .create(NewShare {
+                kind: ShareKind::View,
+                owner: None,
+                owner_is_admin: false,
+                path: PathBuf::from("report.pdf"),
+                is_dir: false,
+                expires_in: Duration::from_secs(3600),
+                max_downloads: None,
+                max_uploads: None,
+                max_upload_size: None,
+                password: Some("secret"),
+            })
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .wrap(session_middleware(Key::generate(), false))
+                .app_data(web::Data::new(crate::handlebars_utils::prepare()))
+                .app_data(web::Data::new(shares))
+                .app_data(web::Data::new(ShareUnlockFailures::default()))
+                .route(&format!("{SHARE_PATH}/{{token}}"), web::post().to(unlock)),
+        )
+        .await;
+        let unlock = |client: &str, password: &str| {
+            let request = TestRequest::post()
+                .uri(&format!("{SHARE_PATH}/{token}"))
+                .peer_addr(format!("{client}:40000").parse().unwrap())
+                .set_form([("password", password)])
+                .to_request();
+            test::call_service(&app, request)
+        };
+
+        for _ in 0..MAX_FAILURES {
+            let response = unlock("203.0.113.7", "guess").await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+        // even the right password is refused until the window has passed
+        let response = unlock("203.0.113.7", "secret").await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(response.headers().contains_key(header::RETRY_AFTER));
+        let response = unlock("203.0.113.8", "secret").await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    }
+}
@@ -1,54 +1,78 @@
-use actix_web::{http::header, web, HttpResponse, Responder};
+use actix_multipart::form::{tempfile::TempFile, text::Text};
+use actix_session::Session;
+use actix_web::{http::header, web, HttpRequest, HttpResponse, Responder};
 use handlebars::Handlebars;
+
+use super::conditional;
+use crate::{
+    audit::{AuditAction, Auditor},
+    drive_access::{
+        access_control::Permission, volumes::Volumes, OnConflict, UserAccess, Visibility,
+    },
+    server::access_of,
+    shares::{ShareKind, ShareStore},
+};
 use serde_json::json;
-use tracing::trace_span;
-use std::path::PathBuf;
+use std::path::Path;
+use tracing::{info, trace_span};
 
 #[derive(Debug, actix_multipart::form::MultipartForm)]
 pub(super) struct UploadFile {
     #[multipart(rename = "file")]
-    files: Vec<actix_multipart::form::tempfile::TempFile>,
+    files: Vec<TempFile>,
+}
+
+/// Upload through an upload link, see [`crate::shares::ShareKind::Upload`].
+#[derive(Debug, actix_multipart::form::MultipartForm)]
+pub(super) struct DropUploadFile {
+    #[multipart(rename = "file")]
+    files: Vec<TempFile>,
+    /// Name of the uploader, prepended to the names of the uploaded files.
+    uploader: Text<String>,
 }
 
+/// Uploads files to the requested folder, see [`conditional::upload_conflicts`] for
+/// existing files.
+#[allow(clippy::too_many_arguments)]
 pub(super) async fn handle(
+    req: HttpRequest,
     hb: web::Data<Handlebars<'_>>,
-    base_dir: web::Data<PathBuf>,
+    volumes: web::Data<Volumes>,
     form: actix_multipart::form::MultipartForm<UploadFile>,
     path: web::ReqData<crate::server::RequestedPath>,
     accept_header: web::Header<header::Accept>,
+    access: UserAccess,
+    auditor: Auditor,
 ) -> impl Responder {
     let path = path.as_ref();
-    let dir_path = base_dir.join(path).to_path_buf();
+    let dir_path = path.to_path_buf();
 
-    // save new files
     let files = form.into_inner().files;
-    let span = trace_span!("save new files", files_count=files.len());
+    if !conditional::upload_preconditions_met(&req, &dir_path, &files, &access).await {
+        return HttpResponse::PreconditionFailed().body("The file was changed in the meantime");
+    }
 
-    let enter = span.enter();
-    let results = crate::drive_access::save_files(files, &dir_path);
-    let summary = results
-        .map(|(name, r)| match r {
-            Ok(_) => {
-                json!({"message": format!("File {} saved", name), "isError": false})
-            }
-            Err(e) => {
-                json!({"message": format!("File {} failed to save: {}", name, e), "isError": true})
-            }
-        })
-        .collect::<Vec<_>>();
-    drop(enter);
+    // save new files
+    let max_size = volumes.max_upload_size(&dir_path);
+    let on_conflict = conditional::upload_conflicts(&req);
+    let summary = save_files(files, &dir_path, None, max_size, on_conflict, &auditor);
 
     let span = trace_span!("list files");
 
     let _enter = span.enter();
-    let data = crate::drive_access::list_files(&dir_path, &base_dir).await;
+    let data = crate::drive_access::list_files(&dir_path, &access).await;
     match data {
         Ok(data) => {
-            let body = hb.render("files_listing", &data).unwrap();
-            let summary = hb.render("upload_file_summary_message", &summary).unwrap();
-            let confirmation_toast = hb
-                .render("confirmation_toast", &json!({ "message": summary }))
-                .unwrap();
+            let body = crate::handlebars_utils::render(&hb, "files_listing", &data).unwrap();
+            let summary =
+                crate::handlebars_utils::render(&hb, "upload_file_summary_message", &summary)
+                    .unwrap();
+            let confirmation_toast = crate::handlebars_utils::render(
+                &hb,
+                "confirmation_toast",
+                &json!({ "message": summary }),
+            )
+            .unwrap();
             if accept_header.iter().any(|h| h.item.subtype() == "json") {
                 HttpResponse::Ok().json(json!({"files": data, "message": summary}))
             } else {
@@ -58,3 +82,109 @@ pub(super) async fn handle(
         Err(_) => HttpResponse::InternalServerError().finish(),
     }
 }
+
+/// Saves files sent with an upload link. The response only summarizes the upload,
+/// contents of the target folder are never revealed.
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn handle_drop(
+    req: HttpRequest,
+    hb: web::Data<Handlebars<'_>>,
+    volumes: web::Data<Volumes>,
+    shares: Option<web::Data<ShareStore>>,
+    session: Session,
+    token: web::Path<String>,
+    form: actix_multipart::form::MultipartForm<DropUploadFile>,
+    accept_header: web::Header<header::Accept>,
+    auditor: Auditor,
+) -> impl Responder {
+    let Some(shares) = shares else {
+        return HttpResponse::NotFound().finish();
+    };
+    let share = match shares.resolve(&token) {
+        Ok(share) if share.kind == ShareKind::Upload => share,
+        Ok(_) => return HttpResponse::NotFound().finish(),
+        Err(e) => return super::shares::share_error(&hb, e),
+    };
+    if share.has_password() && !super::shares::is_unlocked(&session, &share.id) {
+        return HttpResponse::Unauthorized().finish();
+    }
+    let form = form.into_inner();
+    let uploader = form.uploader.trim();
+    if uploader.is_empty() || !crate::drive_access::path_resolution::is_plain_file_name(uploader) {
+        return HttpResponse::BadRequest().body("Invalid uploader name");
+    }
+    let dir_path = volumes.join(&share.path);
+    // the link stops working when its owner can no longer write to the folder
+    let access = access_of(&req, share.owner.clone(), share.owner_is_admin);
+    if !dir_path.is_dir() || Visibility::new(&volumes).is_hidden(&dir_path, true) {
+        return HttpResponse::NotFound().finish();
+    }
+    if !access.permissions(&dir_path).allows(Permission::Write) {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    let files = form
+        .files
+        .into_iter()
+        .filter(|file| file.file_name.is_some())
+        .collect::<Vec<_>>();
+    let bytes = files.iter().map(|file| file.size as u64).sum();
+    let count = u32::try_from(files.len()).unwrap_or(u32::MAX);
+    if let Err(e) = shares.record_upload(&share.id, count, bytes) {
+        return super::shares::share_error(&hb, e);
+    }
+    info!(
+        "{} uploaded {} files with link {}",
+        uploader, count, share.id
+    );
+    let auditor = auditor.acting_as(uploader, format!("upload link {}", share.id));
+    let max_size = volumes.max_upload_size(&dir_path);
+    let summary = save_files(
+        files,
+        &dir_path,
+        Some(uploader),
+        max_size,
+        OnConflict::Rename,
+        &auditor,
+    );
+    if accept_header.iter().any(|h| h.item.subtype() == "json") {
+        HttpResponse::Ok().json(json!({ "message": summary }))
+    } else {
+        let body =
+            crate::handlebars_utils::render(&hb, "upload_file_summary_message", &summary).unwrap();
+        HttpResponse::Ok()
+            .insert_header(header::ContentType::html())
+            .body(body)
+    }
+}
+
+fn save_files(
+    files: Vec<TempFile>,
+    dir_path: &Path,
+    name_prefix: Option<&str>,
+    max_size: Option<u64>,
+    on_conflict: OnConflict,
+    auditor: &Auditor,
+) -> Vec<serde_json::Value> {
+    let span = trace_span!("save new files", files_count = files.len());
+    let _enter = span.enter();
+    crate::drive_access::save_files(files, dir_path, name_prefix, max_size, on_conflict)
+        .map(|(name, r)| {
+            let bytes = r
+                .as_ref()
+                .ok()
+                .and_then(|file| file.metadata().ok())
+                .map(|m| m.len());
+            auditor.record(AuditAction::Upload, &dir_path.join(&name), bytes, &r);
+            (name, r)
+        })
+        .map(|(name, r)| match r {
+            Ok(_) => {
+                json!({"message": format!("File {} saved", name), "isError": false})
+            }
+            Err(e) => {
+                json!({"message": format!("File {} failed to save: {}", name, e), "isError": true})
+            }
+        })
+        .collect::<Vec<_>>()
+}
@@ -0,0 +1,160 @@
+use actix_web::{http::Method, web, HttpRequest, HttpResponse, Responder};
+use dav_server::{
+    actix::{DavRequest, DavResponse},
+    DavHandler,
+};
+
+use crate::{audit::Auditor, drive_access::UserAccess, server::CurrentUser, webdav::DavUser};
+
+/// Serves the drive to WebDAV clients.
+pub(super) async fn handle(
+    http_req: HttpRequest,
+    req: DavRequest,
+    dav: web::Data<DavHandler<DavUser>>,
+    user: Option<web::ReqData<CurrentUser>>,
+    access: UserAccess,
+    mut auditor: Auditor,
+) -> HttpResponse {
+    // lock owners are shown to other clients
+    let principal = user
+        .map(|user| user.name.clone())
+        .unwrap_or("anonymous".to_owned());
+    auditor.via.get_or_insert_with(|| "WebDAV".to_owned());
+    let response = DavResponse::from(
+        dav.handle_guarded(req.request, principal, DavUser { access, auditor })
+            .await,
+    )
+    .respond_to(&http_req);
+    if http_req.method() == Method::GET {
+        crate::metrics::download(response)
+    } else {
+        response
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use actix_web::{
+        http::{Method, StatusCode},
+        test::{self, TestRequest},
+        web, App,
+    };
+
+    use super::handle;
+    use crate::{
+        drive_access::{
+            path_resolution::SymlinkPolicy,
+            volumes::{Volume, Volumes},
+        },
+        webdav::DAV_PATH,
+    };
+
+    fn method(name: &str) -> Method {
+        Method::from_bytes(name.as_bytes()).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_propfind_lock_and_move() {
+        let (root, disk) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        std::fs::create_dir(root.path().join("docs")).unwrap();
+        std::fs::write(root.path().join("docs/a.txt"), "a").unwrap();
+        std::fs::write(root.path().join("docs/b.txt"), "b").unwrap();
+        std::fs::write(disk.path().join("b.txt"), "old b").unwrap();
+        let volume = Volume {
+            name: "media".to_owned(),
+            path: disk.path().to_path_buf(),
+            read_only: false,
+            max_upload_size: None,
+        };
+        let volumes = Volumes::new(root.path().to_path_buf(), vec![volume]).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(crate::webdav::handler(
+                    &volumes,
+                    SymlinkPolicy::default(),
+                )))
+                .app_data(web::Data::new(volumes))
+                .service(
+                    web::resource([DAV_PATH.to_owned(), format!("{DAV_PATH}/{{tail:.*}}")])
+                        .to(handle),
+                ),
+        )
+        .await;
+
+        let request = TestRequest::default()
+            .method(method("PROPFIND"))
+            .uri("/dav/")
+            .insert_header(("Depth", "1"))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::MULTI_STATUS);
+        let body = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+        assert!(body.contains("/dav/docs/"), "{body}");
+        assert!(body.contains("/dav/media/"), "{body}");
+
+        let request = TestRequest::default()
+            .method(method("LOCK"))
+            .uri("/dav/docs/a.txt")
+            .insert_header(("Timeout", "Second-60"))
+            .set_payload(
+                r#"<?xml version="1.0" encoding="utf-8"?>
+                <D:lockinfo xmlns:D="DAV:">
+                  <D:lockscope><D:exclusive/></D:lockscope>
+                  <D:locktype><D:write/></D:locktype>
+                </D:lockinfo>"#,
+            )
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let lock_token = response
+            .headers()
+            .get("Lock-Token")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_owned();
+        let move_request = |from: &str, to: &str| {
+            TestRequest::default()
+                .method(method("MOVE"))
+                .uri(from)
+                .insert_header(("Destination", format!("http://localhost{to}")))
+        };
+        let response = test::call_service(
+            &app,
+            move_request("/dav/docs/a.txt", "/dav/a.txt").to_request(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::LOCKED);
+        let request = move_request("/dav/docs/a.txt", "/dav/a.txt")
+            .insert_header(("If", format!("({lock_token})")))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert!(root.path().join("a.txt").exists());
+
+        // entries moved to another volume are copied and deleted
+        let request = move_request("/dav/docs/b.txt", "/dav/media/b.txt")
+            .insert_header(("Overwrite", "F"))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+        let response = test::call_service(
+            &app,
+            move_request("/dav/docs/b.txt", "/dav/media/b.txt").to_request(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            std::fs::read_to_string(disk.path().join("b.txt")).unwrap(),
+            "b"
+        );
+        let response = test::call_service(
+            &app,
+            move_request("/dav/docs", "/dav/media/docs").to_request(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert!(!root.path().join("docs").exists());
+        assert!(disk.path().join("docs").is_dir());
+    }
+}